@@ -2,7 +2,10 @@ use crate::backend_error::PointsViewerError;
 use crate::state::AppState;
 use actix_web::{dev::BodyEncoding, http::ContentEncoding, web, HttpResponse};
 use byteorder::{LittleEndian, WriteBytesExt};
-use nalgebra::Matrix4;
+use nalgebra::{Matrix4, Point3, Vector3};
+use point_viewer::attributes::AttributeData;
+use point_viewer::geometry::Ray;
+use point_viewer::iterator::PointCloud;
 use point_viewer::octree::{self, Octree};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -63,6 +66,94 @@ pub fn get_visible_nodes(
     }
 }
 
+#[derive(Deserialize)]
+pub struct PickInfo {
+    /// Comma separated coordinates of the ray origin.
+    origin: String,
+    /// Comma separated coordinates of the ray direction.
+    direction: String,
+    radius: f64,
+    max_level: Option<u8>,
+}
+
+fn parse_vector(input: &str) -> Result<Vector3<f64>, PointsViewerError> {
+    let e = input
+        .split(',')
+        .map(|s| s.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| PointsViewerError::BadRequest(format!("Parsing Error: {}", err)))?;
+    if e.len() != 3 {
+        return Err(PointsViewerError::BadRequest(
+            "Parsing Error: Expected vector with 3 elements".to_string(),
+        ));
+    }
+    Ok(Vector3::new(e[0], e[1], e[2]))
+}
+
+/// Method that returns the first point along a ray, or null if no point is hit.
+pub fn pick_point(
+    (octree_id, state, pick_query): (
+        web::Path<String>,
+        web::Data<Arc<AppState>>,
+        web::Query<PickInfo>,
+    ),
+) -> HttpResponse {
+    let octree = match get_octree_from_state(&octree_id.into_inner(), &state) {
+        Err(err) => return HttpResponse::from_error(err.into()),
+        Ok(octree) => octree,
+    };
+    let ray = match (
+        parse_vector(&pick_query.origin),
+        parse_vector(&pick_query.direction),
+    ) {
+        (Ok(origin), Ok(direction)) if direction.norm() > 0. => {
+            Ray::new(Point3::from(origin), direction)
+        }
+        (Err(err), _) | (_, Err(err)) => return HttpResponse::from_error(err.into()),
+        _ => {
+            return HttpResponse::from_error(
+                PointsViewerError::BadRequest("The ray direction must not be zero.".to_string())
+                    .into(),
+            )
+        }
+    };
+
+    if pick_query.radius.is_nan() || pick_query.radius < 0. {
+        return HttpResponse::from_error(
+            PointsViewerError::BadRequest("The radius must not be negative.".to_string()).into(),
+        );
+    }
+
+    let attributes = ["color"];
+    let picked = match pick_query.max_level {
+        Some(max_level) => {
+            octree.pick_point_up_to_level(&ray, pick_query.radius, max_level, &attributes)
+        }
+        None => octree.pick_point(&ray, pick_query.radius, &attributes),
+    };
+    let reply = match picked {
+        Err(err) => return HttpResponse::from_error(PointsViewerError::from(err).into()),
+        Ok(None) => "null".to_string(),
+        Ok(Some(picked)) => {
+            let p = picked.point.position[0];
+            let color = match picked.point.attributes.get("color") {
+                Some(AttributeData::U8Vec3(data)) => {
+                    format!("[{},{},{}]", data[0].x, data[0].y, data[0].z)
+                }
+                _ => "null".to_string(),
+            };
+            format!(
+                "{{\"position\":[{},{},{}],\"color\":{},\"distanceAlongRay\":{},\"distanceToRay\":{}}}",
+                p.x, p.y, p.z, color, picked.distance_along_ray, picked.distance_to_ray
+            )
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(reply)
+}
+
 // Javascript requires its arrays to be padded to 8 bytes.
 fn pad(input: &mut Vec<u8>) {
     let pad = input.len() % 8;
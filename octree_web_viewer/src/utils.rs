@@ -1,4 +1,4 @@
-use crate::backend::{get_nodes_data, get_visible_nodes};
+use crate::backend::{get_nodes_data, get_visible_nodes, pick_point};
 use crate::backend_error::PointsViewerError;
 use crate::state::AppState;
 use actix_web::{web, HttpResponse, HttpServer};
@@ -48,6 +48,7 @@ pub fn start_octree_server(
             .service(web::resource("/init_tree").to(get_init_tree))
             .service(web::resource("/visible_nodes/{octree_id}/").to(get_visible_nodes))
             .service(web::resource("/nodes_data/{octree_id}/").to(get_nodes_data))
+            .service(web::resource("/pick_point/{octree_id}/").to(pick_point))
     })
     .bind(&ip_port)
     .unwrap_or_else(|_| panic!("Can not bind to {}", &ip_port))
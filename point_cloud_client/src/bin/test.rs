@@ -3,15 +3,14 @@
 
 use nalgebra::Point3;
 use point_cloud_client::PointCloudClientBuilder;
-use point_viewer::errors::{ErrorKind, Result};
+use point_viewer::errors::{Error, ErrorKind, Result};
 use point_viewer::geometry::Aabb;
-use point_viewer::iterator::{PointLocation, PointQuery};
+use point_viewer::iterator::{PointLocation, PointQuery, QueryHandle};
+use point_viewer::utils::create_progress_bar;
 use point_viewer::PointsBatch;
+use std::sync::Arc;
 use structopt::StructOpt;
 
-// size for batch
-const BATCH_SIZE: usize = 1_000_000;
-
 fn point3f64_from_str(s: &str) -> std::result::Result<Point3<f64>, &'static str> {
     let coords: std::result::Result<Vec<f64>, &'static str> = s
         .split(|c| c == ' ' || c == ',' || c == ';')
@@ -74,31 +73,37 @@ fn main() {
         location: PointLocation::Aabb(Aabb::new(args.min, args.max)),
         ..Default::default()
     };
+    let handle = Arc::new(QueryHandle::new());
+    let mut progress_bar = None;
     let mut point_count: usize = 0;
-    let mut print_count: usize = 1;
     let callback_func = |points_batch: PointsBatch| -> Result<()> {
         point_count += points_batch.position.len();
-        if point_count >= print_count * BATCH_SIZE {
-            print_count += 1;
-            eprintln!("Streamed {}M points", point_count / BATCH_SIZE);
-        }
+        // The number of nodes is known once the first batch arrives.
+        progress_bar
+            .get_or_insert_with(|| create_progress_bar(handle.num_nodes(), "Reading nodes"))
+            .set(handle.num_nodes_completed() as u64);
         if point_count >= num_points {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Interrupted,
-                format!("Maximum number of {} points reached.", num_points),
-            )
-            .into());
+            handle.cancel();
         }
         Ok(())
     };
-    match point_cloud_client.for_each_point_data(&point_location, callback_func) {
+    match point_cloud_client.for_each_point_data_with_handle(
+        &point_location,
+        Arc::clone(&handle),
+        callback_func,
+    ) {
         Ok(_) => (),
-        Err(e) => match e.kind() {
-            ErrorKind::Io(ref e) if e.kind() == std::io::ErrorKind::Interrupted => (),
-            _ => {
-                eprintln!("Encountered error:\n{}", e);
-                std::process::exit(1);
-            }
-        },
+        Err(Error(ErrorKind::Cancelled, _)) => {
+            eprintln!("Maximum number of {} points reached.", num_points)
+        }
+        Err(e) => {
+            eprintln!("Encountered error:\n{}", e);
+            std::process::exit(1);
+        }
     }
+    eprintln!(
+        "Streamed {} points, read {} bytes.",
+        handle.num_points(),
+        handle.num_bytes_read()
+    );
 }
@@ -1,10 +1,13 @@
+use point_viewer::aggregation::{Aggregate, Aggregations};
+use point_viewer::attributes::LabelDictionary;
 use point_viewer::data_provider::{DataProvider, DataProviderFactory};
 use point_viewer::errors::*;
 use point_viewer::geometry::Aabb;
-use point_viewer::iterator::{ParallelIterator, PointCloud, PointQuery};
+use point_viewer::iterator::{ParallelIterator, PointCloud, PointQuery, QueryHandle};
 use point_viewer::octree::Octree;
 use point_viewer::s2_cells::S2Cells;
 use point_viewer::{PointsBatch, NUM_POINTS_PER_BATCH};
+use std::sync::Arc;
 
 enum PointClouds {
     Octrees(Vec<Octree>),
@@ -24,7 +27,25 @@ impl PointCloudClient {
         &self.aabb
     }
 
-    fn for_each<C, F>(&self, point_cloud: &[C], point_query: &PointQuery, mut func: F) -> Result<()>
+    /// Returns the label dictionary of 'attribute' of the first point cloud that has one.
+    pub fn label_dictionary(&self, attribute: &str) -> Option<&LabelDictionary> {
+        match &self.point_clouds {
+            PointClouds::Octrees(octrees) => octrees
+                .iter()
+                .find_map(|octree| octree.label_dictionary(attribute)),
+            PointClouds::S2Cells(s2_cells) => s2_cells
+                .iter()
+                .find_map(|s2_cells| s2_cells.label_dictionary(attribute)),
+        }
+    }
+
+    fn for_each<C, F>(
+        &self,
+        point_cloud: &[C],
+        point_query: &PointQuery,
+        handle: Arc<QueryHandle>,
+        mut func: F,
+    ) -> Result<()>
     where
         C: PointCloud,
         F: FnMut(PointsBatch) -> Result<()>,
@@ -35,17 +56,58 @@ impl PointCloudClient {
             self.num_points_per_batch,
             self.num_threads,
             self.buffer_size,
-        );
+        )
+        .query_handle(handle);
         parallel_iterator.try_for_each_batch(&mut func)
     }
 
     pub fn for_each_point_data<F>(&self, point_query: &PointQuery, func: F) -> Result<()>
+    where
+        F: FnMut(PointsBatch) -> Result<()>,
+    {
+        self.for_each_point_data_with_handle(point_query, Arc::new(QueryHandle::new()), func)
+    }
+
+    /// Like `for_each_point_data`, reporting the progress to 'handle', which can also cancel the
+    /// query.
+    pub fn for_each_point_data_with_handle<F>(
+        &self,
+        point_query: &PointQuery,
+        handle: Arc<QueryHandle>,
+        func: F,
+    ) -> Result<()>
     where
         F: FnMut(PointsBatch) -> Result<()>,
     {
         match &self.point_clouds {
-            PointClouds::Octrees(octrees) => self.for_each(octrees, point_query, func),
-            PointClouds::S2Cells(s2_cells) => self.for_each(s2_cells, point_query, func),
+            PointClouds::Octrees(octrees) => self.for_each(octrees, point_query, handle, func),
+            PointClouds::S2Cells(s2_cells) => self.for_each(s2_cells, point_query, handle, func),
+        }
+    }
+
+    /// Aggregates the points matching the query in parallel, without collecting them.
+    pub fn aggregate(
+        &self,
+        point_query: &PointQuery,
+        aggregations: &Aggregations,
+    ) -> Result<Aggregate> {
+        match &self.point_clouds {
+            PointClouds::Octrees(octrees) => ParallelIterator::new(
+                octrees,
+                point_query,
+                self.num_points_per_batch,
+                self.num_threads,
+                self.buffer_size,
+            )
+            .aggregate(aggregations),
+            PointClouds::S2Cells(s2_cells) => ParallelIterator::new(
+                s2_cells,
+                point_query,
+                self.num_points_per_batch,
+                self.num_threads,
+                self.buffer_size,
+            )
+            .aggregate(aggregations),
         }
     }
 }
@@ -2,13 +2,12 @@ use point_cloud_client::{PointCloudClient, PointCloudClientBuilder};
 /// This module has functions to generate synthetic point clouds in a temp dir
 /// and provides queries on these synthetic point clouds.
 use point_viewer::data_provider::OnDiskDataProvider;
-use point_viewer::octree::{build_octree, Octree};
-use point_viewer::read_write::{Encoding, NodeWriter, OpenMode, RawNodeWriter, S2Splitter};
+use point_viewer::octree::{build_octree, BuildOptions, Octree};
+use point_viewer::read_write::{
+    publish_staged_build, start_staged_build, write_meta, Encoding, NodeWriter, OpenMode,
+    RawNodeWriter, S2Splitter,
+};
 use point_viewer::s2_cells::S2Cells;
-use point_viewer::META_FILENAME;
-use protobuf::Message;
-use std::fs::File;
-use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 use std::sync::Once;
 use tempdir::TempDir;
@@ -57,21 +56,34 @@ pub fn make_octree(args: &Arguments, dir: &Path) {
     let bbox = points_oct.bbox();
     let batches_oct = Batched::new(points_oct, args.batch_size);
 
-    build_octree(dir, args.resolution, bbox, batches_oct, &["color"]);
+    build_octree(
+        dir,
+        args.resolution,
+        bbox,
+        batches_oct,
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .expect("Could not build the octree.");
 }
 
 pub fn make_s2_cells(args: &Arguments, dir: &Path) {
     let points_s2 = SyntheticData::new(args.width, args.height, args.num_points, args.seed);
-    let mut s2_writer: S2Splitter<RawNodeWriter> =
-        S2Splitter::with_split_level(S2_LEVEL, dir, Encoding::Plain, OpenMode::Truncate);
+    let build_directory = start_staged_build(dir).unwrap();
+    let mut s2_writer: S2Splitter<RawNodeWriter> = S2Splitter::with_split_level(
+        S2_LEVEL,
+        &build_directory,
+        Encoding::Plain,
+        OpenMode::Truncate,
+    );
     Batched::new(points_s2, args.batch_size)
         .try_for_each(|batch| s2_writer.write(&batch))
         .expect("Writing failed");
     // An S2 writer that has not written any points cannot produce a meta proto,
     // but in this case we know it did write points.
     let meta = s2_writer.get_meta().unwrap().to_proto();
-    let mut meta_writer = BufWriter::new(File::create(dir.join(META_FILENAME)).unwrap());
-    meta.write_to_writer(&mut meta_writer).unwrap();
+    write_meta(&build_directory, &meta).unwrap();
+    publish_staged_build(dir).unwrap();
 }
 
 static INIT: Once = Once::new();
@@ -83,17 +95,17 @@ pub fn get_s2_and_octree_path(args: &Arguments) -> (PathBuf, PathBuf, SyntheticD
     let (s2_path_buf, octree_path_buf) = unsafe {
         INIT.call_once(|| {
             let octree_dir = TempDir::new("octree").unwrap();
-            make_octree(&args, octree_dir.path());
+            make_octree(&args, &octree_dir.path().join("octree"));
             OCTREE_DIR = Some(octree_dir);
             let s2_dir = TempDir::new("s2").unwrap();
-            make_s2_cells(&args, s2_dir.path());
+            make_s2_cells(&args, &s2_dir.path().join("s2"));
             S2_DIR = Some(s2_dir);
             ARGUMENTS = Some(args.clone());
         });
         // If somebody called this twice with different arguments, caching doesn't make sense
         assert_eq!(ARGUMENTS, Some(args.clone()));
-        let s2_path_buf = S2_DIR.as_ref().unwrap().path().to_owned();
-        let octree_path_buf = OCTREE_DIR.as_ref().unwrap().path().to_owned();
+        let s2_path_buf = S2_DIR.as_ref().unwrap().path().join("s2");
+        let octree_path_buf = OCTREE_DIR.as_ref().unwrap().path().join("octree");
         (s2_path_buf, octree_path_buf)
     };
     let data = SyntheticData::new(args.width, args.height, args.num_points, args.seed);
@@ -102,13 +114,9 @@ pub fn get_s2_and_octree_path(args: &Arguments) -> (PathBuf, PathBuf, SyntheticD
 
 pub fn setup_pointcloud(args: &Arguments) -> (S2Cells, Octree, SyntheticData) {
     let (s2_path_buf, oct_path_buf, data) = get_s2_and_octree_path(args);
-    let s2_data_provider = OnDiskDataProvider {
-        directory: s2_path_buf,
-    };
+    let s2_data_provider = OnDiskDataProvider::new(s2_path_buf);
     let s2 = S2Cells::from_data_provider(Box::new(s2_data_provider)).unwrap();
-    let oct_data_provider = OnDiskDataProvider {
-        directory: oct_path_buf,
-    };
+    let oct_data_provider = OnDiskDataProvider::new(oct_path_buf);
     let oct = Octree::from_data_provider(Box::new(oct_data_provider)).unwrap();
     (s2, oct, data)
 }
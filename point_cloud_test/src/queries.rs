@@ -1,11 +1,14 @@
 // Some synthetic queries for synthetic data. These are just examples, more can be added.
 use crate::synthetic_data::SyntheticData;
 use crate::S2_LEVEL;
-use nalgebra::{Perspective3, Point3, Vector2, Vector3};
+use nalgebra::{Perspective3, Point2, Point3, Vector2, Vector3};
 use nav_types::{ECEF, WGS84};
-use point_viewer::geometry::{Aabb, CellUnion, Frustum, Obb, WebMercatorRect};
+use point_viewer::geometry::{
+    Aabb, CellUnion, Corridor, Difference, Frustum, Intersection, Obb, Prism, Sphere, Union,
+    WebMercatorRect,
+};
 use point_viewer::iterator::PointLocation;
-use point_viewer::math::{FromPoint3, WebMercatorCoord};
+use point_viewer::math::{ClosedInterval, FromPoint3, WebMercatorCoord};
 use s2::cellid::CellID;
 
 pub fn get_aabb(data: SyntheticData) -> Aabb<f64> {
@@ -35,6 +38,70 @@ pub fn get_obb_query(data: SyntheticData) -> PointLocation {
     PointLocation::Obb(get_obb(data))
 }
 
+// A sphere around the center of the point cloud, with half the width of the data as radius.
+pub fn get_sphere(data: SyntheticData) -> Sphere<f64> {
+    let center = data.ecef_from_local().translation.vector;
+    Sphere::new(Point3::from(center), 0.5 * data.half_width)
+}
+
+pub fn get_sphere_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Sphere(get_sphere(data))
+}
+
+// An L-shaped prism in the center of the point cloud, aligned with gravity.
+pub fn get_prism(data: SyntheticData) -> Prism<f64> {
+    let w = 0.5 * data.half_width;
+    Prism::new(
+        *data.ecef_from_local(),
+        vec![
+            Point2::new(-w, -w),
+            Point2::new(w, -w),
+            Point2::new(w, 0.0),
+            Point2::new(0.0, 0.0),
+            Point2::new(0.0, w),
+            Point2::new(-w, w),
+        ],
+        ClosedInterval::new(-0.5 * data.half_height, 0.5 * data.half_height),
+    )
+}
+
+pub fn get_prism_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Prism(get_prism(data))
+}
+
+// A zigzag line through the center of the point cloud.
+fn corridor_polyline(data: &SyntheticData) -> Vec<Point3<f64>> {
+    let w = 0.5 * data.half_width;
+    vec![
+        Point3::new(-w, -w, 0.0),
+        Point3::new(0.0, w, 0.0),
+        Point3::new(w, -w, 0.0),
+        Point3::new(w, w, 0.0),
+    ]
+}
+
+// A corridor along a zigzag line through the center of the point cloud.
+pub fn get_corridor(data: SyntheticData) -> Corridor<f64> {
+    Corridor::new(corridor_polyline(&data), 0.2 * data.half_width)
+        .transformed(data.ecef_from_local())
+}
+
+pub fn get_corridor_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Corridor(get_corridor(data))
+}
+
+// A corridor along the same line that is thin to the sides, but spans the whole height.
+pub fn get_tall_corridor_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Corridor(
+        Corridor::with_vertical_extent(
+            corridor_polyline(&data),
+            0.1 * data.half_width,
+            data.half_height,
+        )
+        .transformed(data.ecef_from_local()),
+    )
+}
+
 pub fn get_frustum(data: SyntheticData) -> Frustum<f64> {
     let ecef_from_local = *data.ecef_from_local();
     let perspective = Perspective3::new(
@@ -70,3 +137,25 @@ pub fn get_web_mercator_rect(data: SyntheticData) -> WebMercatorRect {
 pub fn get_web_mercator_rect_query(data: SyntheticData) -> PointLocation {
     PointLocation::WebMercatorRect(get_web_mercator_rect(data))
 }
+
+pub fn get_union_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Union(Union::new(vec![
+        get_aabb_query(data.clone()),
+        get_sphere_query(data),
+    ]))
+}
+
+pub fn get_intersection_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Intersection(Intersection::new(vec![
+        get_obb_query(data.clone()),
+        get_corridor_query(data),
+    ]))
+}
+
+// The OBB with a spherical hole in its center.
+pub fn get_difference_query(data: SyntheticData) -> PointLocation {
+    PointLocation::Difference(Difference::new(
+        get_obb_query(data.clone()),
+        get_sphere_query(data),
+    ))
+}
@@ -2,6 +2,7 @@ use nalgebra::{Point3, Vector3};
 use num_integer::div_ceil;
 use point_cloud_test_lib::queries::*;
 use point_cloud_test_lib::{setup_pointcloud, Arguments, SyntheticData};
+use point_viewer::geometry::Ray;
 use point_viewer::iterator::PointCloud;
 use point_viewer::iterator::{PointLocation, PointQuery};
 use point_viewer::math::{sat, ConvexPolyhedron, PointCulling};
@@ -52,6 +53,41 @@ fn check_obb_query_equality() {
     check_equality(get_obb_query);
 }
 
+#[test]
+fn check_sphere_query_equality() {
+    check_equality(get_sphere_query);
+}
+
+#[test]
+fn check_prism_query_equality() {
+    check_equality(get_prism_query);
+}
+
+#[test]
+fn check_corridor_query_equality() {
+    check_equality(get_corridor_query);
+}
+
+#[test]
+fn check_tall_corridor_query_equality() {
+    check_equality(get_tall_corridor_query);
+}
+
+#[test]
+fn check_union_query_equality() {
+    check_equality(get_union_query);
+}
+
+#[test]
+fn check_intersection_query_equality() {
+    check_equality(get_intersection_query);
+}
+
+#[test]
+fn check_difference_query_equality() {
+    check_equality(get_difference_query);
+}
+
 #[test]
 fn check_cell_union_query_equality() {
     check_equality(get_cell_union_query)
@@ -82,6 +118,45 @@ fn check_web_mercator_rect_point_culling_equality() {
     check_point_culling_equality(get_web_mercator_rect);
 }
 
+#[test]
+fn check_nearest_neighbors() {
+    let args = Arguments::default();
+    let (s2, oct, data) = setup_pointcloud(&args);
+    // The center of the point cloud, far above it and off to its side, where the query point is
+    // outside of all S2 cells.
+    let query_points: Vec<Point3<f64>> = [
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(10.0, -20.0, 500.0),
+        Point3::new(3.0 * data.half_width, 0.0, -data.half_height),
+    ]
+    .iter()
+    .map(|p| data.ecef_from_local() * p)
+    .collect();
+    check_nearest_neighbors_against_all_points(&s2, &query_points, args.batch_size);
+    check_nearest_neighbors_against_all_points(&oct, &query_points, args.batch_size);
+}
+
+#[test]
+fn check_pick_point() {
+    let args = Arguments::default();
+    let (s2, oct, data) = setup_pointcloud(&args);
+    // Looking down at the point cloud at an angle, and along it from its side.
+    let ecef_from_local = data.ecef_from_local();
+    let rays = [
+        Ray::new(
+            ecef_from_local * Point3::new(-5.0, 3.0, 2.0 * data.half_height),
+            ecef_from_local * Vector3::new(0.5, 0.2, -1.0),
+        ),
+        Ray::new(
+            ecef_from_local * Point3::new(-2.0 * data.half_width, 1.0, 0.0),
+            ecef_from_local * Vector3::new(1.0, 0.0, 0.0),
+        ),
+    ];
+    let radius = 0.5;
+    check_pick_point_against_all_points(&s2, &rays, radius, args.batch_size);
+    check_pick_point_against_all_points(&oct, &rays, radius, args.batch_size);
+}
+
 fn check_equality<F>(gen_location: F)
 where
     F: FnOnce(SyntheticData) -> PointLocation,
@@ -131,9 +206,9 @@ where
     C: PointCloud,
 {
     let mut points = Vec::new();
-    for node_id in point_cloud.nodes_in_location(&query.location).into_iter() {
+    for (node_id, relation) in point_cloud.nodes_in_location(&query.location).into_iter() {
         point_cloud
-            .stream_points_for_query_in_node(query, node_id, batch_size, |batch| {
+            .stream_points_for_query_in_node(query, node_id, relation, batch_size, |batch| {
                 let color: &Vec<Vector3<u8>> = batch.get_attribute_vec("color")?;
                 let indexed_point_iter = color.iter().zip(batch.position.iter()).map(|(c, p)| {
                     // Decode the index we encoded in the color
@@ -153,6 +228,64 @@ where
     points
 }
 
+// Compares the nearest neighbors with the distances to all points, which finds nodes that are
+// skipped because their distance to the query point was overestimated.
+fn check_nearest_neighbors_against_all_points<C>(
+    point_cloud: &C,
+    query_points: &[Point3<f64>],
+    batch_size: usize,
+) where
+    C: PointCloud,
+{
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+    let all_points = query_and_sort(point_cloud, &query, batch_size);
+    let k = 20;
+    let neighbors = point_cloud
+        .nearest_neighbors_batch(query_points, k, std::f64::INFINITY, &["color"])
+        .unwrap();
+    for (query_point, neighbors) in query_points.iter().zip(neighbors) {
+        let mut distances: Vec<f64> = all_points
+            .iter()
+            .map(|p| nalgebra::distance(&p.pos, query_point))
+            .collect();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(neighbors.distances, distances[..k].to_vec());
+    }
+}
+
+// Compares the picked point with the first of all points within 'radius' of the ray.
+fn check_pick_point_against_all_points<C>(
+    point_cloud: &C,
+    rays: &[Ray<f64>],
+    radius: f64,
+    batch_size: usize,
+) where
+    C: PointCloud,
+{
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+    let all_points = query_and_sort(point_cloud, &query, batch_size);
+    for ray in rays {
+        let closest_along_ray = all_points
+            .iter()
+            .filter(|p| ray.distance_to_line(&p.pos) <= radius)
+            .map(|p| ray.distance_along(&p.pos))
+            .filter(|along| *along >= 0.0)
+            .fold(std::f64::INFINITY, f64::min);
+        assert!(closest_along_ray.is_finite());
+        let picked = point_cloud
+            .pick_point(ray, radius, &["color"])
+            .unwrap()
+            .expect("The ray should hit a point.");
+        assert_eq!(picked.distance_along_ray, closest_along_ray);
+    }
+}
+
 struct IndexedPoint {
     idx: usize,
     pos: Point3<f64>,
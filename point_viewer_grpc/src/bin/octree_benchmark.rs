@@ -88,15 +88,14 @@ fn server_benchmark(
     num_threads: usize,
     buffer_size: usize,
 ) {
-    let octree = Octree::from_data_provider(Box::new(OnDiskDataProvider {
-        directory: octree_directory.into(),
-    }))
-    .unwrap_or_else(|_| {
-        panic!(
-            "Could not create octree from '{}'",
-            octree_directory.display()
-        )
-    });
+    let octree =
+        Octree::from_data_provider(Box::new(OnDiskDataProvider::new(octree_directory.into())))
+            .unwrap_or_else(|_| {
+                panic!(
+                    "Could not create octree from '{}'",
+                    octree_directory.display()
+                )
+            });
     let mut counter: usize = 0;
     let mut points_streamed_m = 0;
     let all_points = PointQuery {
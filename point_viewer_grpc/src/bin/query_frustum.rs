@@ -20,7 +20,7 @@ use num_integer::div_ceil;
 use point_viewer::attributes::AttributeData;
 use point_viewer::color::Color;
 use point_viewer::geometry::Aabb;
-use point_viewer::octree::build_octree;
+use point_viewer::octree::{build_octree, BuildOptions};
 use point_viewer::{NumberOfPoints, Point, PointsBatch, NUM_POINTS_PER_BATCH};
 pub use point_viewer_grpc_proto_rust::proto::GetPointsInFrustumRequest;
 pub use point_viewer_grpc_proto_rust::proto_grpc;
@@ -155,5 +155,7 @@ fn main() {
         bounding_box,
         Points::new(points),
         &["color"],
-    );
+        &BuildOptions::default(),
+    )
+    .expect("Could not build the octree.");
 }
@@ -14,12 +14,12 @@
 
 use crate::proto_grpc::OctreeClient;
 use futures::{Future, Stream};
-use grpcio::{ChannelBuilder, EnvBuilder};
+use grpcio::{ChannelBuilder, ClientSStreamReceiver, EnvBuilder};
 use nalgebra::Point3;
 use point_viewer::color::Color;
 use point_viewer::data_provider::{DataProvider, DataProviderFactoryResult};
 use point_viewer::errors::*;
-use point_viewer::geometry::Aabb;
+use point_viewer::geometry::{Aabb, Ray, Sphere};
 use point_viewer::proto::Meta;
 use point_viewer::Point;
 pub use point_viewer_grpc_proto_rust::proto;
@@ -52,7 +52,7 @@ impl GrpcOctreeDataProvider {
     pub fn get_points_in_box(
         &self,
         bounding_box: &Aabb<f64>,
-        mut func: impl FnMut(&[Point]) -> bool,
+        func: impl FnMut(&[Point]) -> bool,
     ) -> Result<()> {
         let mut req = proto::GetPointsInBoxRequest::new();
         req.set_octree_id(self.octree_id.clone());
@@ -66,7 +66,74 @@ impl GrpcOctreeDataProvider {
             .client
             .get_points_in_box(&req)
             .map_err(|_| point_viewer::errors::ErrorKind::Grpc)?;
+        Self::stream_points(replies, func)
+    }
 
+    pub fn get_points_in_sphere(
+        &self,
+        sphere: &Sphere<f64>,
+        func: impl FnMut(&[Point]) -> bool,
+    ) -> Result<()> {
+        let mut req = proto::GetPointsInSphereRequest::new();
+        req.set_octree_id(self.octree_id.clone());
+        req.mut_center().set_x(sphere.center().x);
+        req.mut_center().set_y(sphere.center().y);
+        req.mut_center().set_z(sphere.center().z);
+        req.set_radius(sphere.radius());
+        let replies = self
+            .client
+            .get_points_in_sphere(&req)
+            .map_err(|_| point_viewer::errors::ErrorKind::Grpc)?;
+        Self::stream_points(replies, func)
+    }
+
+    /// Returns the first point along 'ray' within 'radius' of it, optionally only considering
+    /// nodes up to 'max_level'.
+    pub fn pick_point(
+        &self,
+        ray: &Ray<f64>,
+        radius: f64,
+        max_level: Option<u8>,
+    ) -> Result<Option<Point>> {
+        let mut req = proto::PickPointRequest::new();
+        req.set_octree_id(self.octree_id.clone());
+        req.mut_origin().set_x(ray.origin().x);
+        req.mut_origin().set_y(ray.origin().y);
+        req.mut_origin().set_z(ray.origin().z);
+        req.mut_direction().set_x(ray.direction().x);
+        req.mut_direction().set_y(ray.direction().y);
+        req.mut_direction().set_z(ray.direction().z);
+        req.set_radius(radius);
+        if let Some(max_level) = max_level {
+            req.set_limit_level(true);
+            req.set_max_level(u32::from(max_level));
+        }
+        let reply = self
+            .client
+            .pick_point(&req)
+            .map_err(|_| point_viewer::errors::ErrorKind::Grpc)?;
+        if !reply.hit {
+            return Ok(None);
+        }
+        let color = reply.get_color();
+        Ok(Some(Point {
+            position: Point3::from(reply.get_position()),
+            color: Color {
+                red: color.red,
+                green: color.green,
+                blue: color.blue,
+                alpha: color.alpha,
+            }
+            .to_u8(),
+            intensity: None,
+        }))
+    }
+
+    /// Calls 'func' with the points of each reply until it returns false.
+    fn stream_points(
+        replies: ClientSStreamReceiver<proto::PointsReply>,
+        mut func: impl FnMut(&[Point]) -> bool,
+    ) -> Result<()> {
         let mut points = Vec::new();
         let mut interrupted = false;
         let result = replies
@@ -15,8 +15,8 @@
 use crate::proto;
 use crate::proto_grpc;
 use crate::Color;
-use futures::sync::mpsc;
-use futures::{Future, Sink, Stream};
+use futures::future::{self, Either};
+use futures::{Async, Future, Poll, Sink, Stream};
 use grpcio::{
     Environment, RpcContext, RpcStatus, RpcStatusCode, Server, ServerBuilder, ServerStreamingSink,
     UnarySink, WriteFlags,
@@ -25,8 +25,10 @@ use nalgebra::{Isometry3, Perspective3, Point3, Quaternion, UnitQuaternion, Vect
 use point_viewer::attributes::AttributeData;
 use point_viewer::data_provider::DataProviderFactory;
 use point_viewer::errors::*;
-use point_viewer::geometry::{Aabb, Frustum};
-use point_viewer::iterator::{ParallelIterator, PointLocation, PointQuery};
+use point_viewer::geometry::{Aabb, Frustum, Ray, Sphere};
+use point_viewer::iterator::{
+    PointCloud, PointLocation, PointQuery, PointsIterator, PointsStream, QueryHandle,
+};
 use point_viewer::octree::{NodeId, Octree};
 use point_viewer::PointsBatch;
 use protobuf::Message;
@@ -36,7 +38,8 @@ use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 
 struct OctreeServiceData {
-    octree: Octree,
+    // Holds the single octree, shared with the threads that stream its points.
+    octrees: Arc<[Octree]>,
     meta: point_viewer::proto::Meta,
 }
 
@@ -47,16 +50,21 @@ struct OctreeService {
     factory: DataProviderFactory,
 }
 
-fn send_fail_stream<T>(ctx: &RpcContext, sink: ServerStreamingSink<T>, err_str: String) {
+fn send_fail_stream<T>(
+    ctx: &RpcContext,
+    sink: ServerStreamingSink<T>,
+    status_code: RpcStatusCode,
+    err_str: String,
+) {
     let f = sink
-        .fail(RpcStatus::new(RpcStatusCode::Internal, Some(err_str)))
+        .fail(RpcStatus::new(status_code, Some(err_str)))
         .map_err(move |err| eprintln!("Failed to reply: {:?}", err));
     ctx.spawn(f);
 }
 
-fn send_fail<T>(ctx: &RpcContext, sink: UnarySink<T>, err_str: String) {
+fn send_fail<T>(ctx: &RpcContext, sink: UnarySink<T>, status_code: RpcStatusCode, err_str: String) {
     let f = sink
-        .fail(RpcStatus::new(RpcStatusCode::Internal, Some(err_str)))
+        .fail(RpcStatus::new(status_code, Some(err_str)))
         .map_err(move |err| eprintln!("Failed to reply: {:?}", err));
     ctx.spawn(f);
 }
@@ -71,7 +79,7 @@ impl proto_grpc::Octree for OctreeService {
         let mut resp = proto::GetMetaReply::new();
         let service_data = match self.get_service_data(&req.octree_id) {
             Ok(service_data) => service_data,
-            Err(e) => return send_fail(&ctx, sink, e.to_string()),
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
         };
         resp.set_meta(service_data.meta.clone());
         let f = sink
@@ -88,15 +96,15 @@ impl proto_grpc::Octree for OctreeService {
     ) {
         let service_data = match self.get_service_data(&req.octree_id) {
             Ok(service_data) => service_data,
-            Err(e) => return send_fail(&ctx, sink, e.to_string()),
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
         };
         let node_id = match NodeId::from_str(&req.id) {
             Ok(node_id) => node_id,
-            Err(e) => return send_fail(&ctx, sink, e.to_string()),
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
         };
-        let node_data = match service_data.octree.get_node_data(&node_id) {
+        let node_data = match service_data.octrees[0].get_node_data(&node_id) {
             Ok(data) => data,
-            Err(e) => return send_fail(&ctx, sink, e.to_string()),
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
         };
         let mut resp = proto::GetNodeDataReply::new();
         resp.mut_node()
@@ -152,6 +160,91 @@ impl proto_grpc::Octree for OctreeService {
         self.stream_points_back_to_sink(location, &req.octree_id, &ctx, resp)
     }
 
+    fn get_points_in_sphere(
+        &mut self,
+        ctx: RpcContext,
+        req: proto::GetPointsInSphereRequest,
+        resp: ServerStreamingSink<proto::PointsReply>,
+    ) {
+        let center = match req.center.as_ref() {
+            Some(c) => Point3::new(c.x, c.y, c.z),
+            None => {
+                let message = "The center of the sphere is missing.".to_string();
+                return send_fail_stream(&ctx, resp, RpcStatusCode::InvalidArgument, message);
+            }
+        };
+        if req.radius.is_nan() || req.radius < 0. {
+            let message = format!("The radius must not be negative, got {}.", req.radius);
+            return send_fail_stream(&ctx, resp, RpcStatusCode::InvalidArgument, message);
+        }
+        let location = PointLocation::Sphere(Sphere::new(center, req.radius));
+        self.stream_points_back_to_sink(location, &req.octree_id, &ctx, resp)
+    }
+
+    fn pick_point(
+        &mut self,
+        ctx: RpcContext,
+        req: proto::PickPointRequest,
+        sink: UnarySink<proto::PickPointReply>,
+    ) {
+        let service_data = match self.get_service_data(&req.octree_id) {
+            Ok(service_data) => service_data,
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
+        };
+        let ray = {
+            let o = req.get_origin();
+            let d = req.get_direction();
+            let direction = Vector3::new(d.x, d.y, d.z);
+            if direction.norm().is_nan() || direction.norm() == 0. {
+                let message = "The ray direction must not be zero.".to_string();
+                return send_fail(&ctx, sink, RpcStatusCode::InvalidArgument, message);
+            }
+            Ray::new(Point3::new(o.x, o.y, o.z), direction)
+        };
+        if req.radius.is_nan() || req.radius < 0. {
+            let message = format!("The radius must not be negative, got {}.", req.radius);
+            return send_fail(&ctx, sink, RpcStatusCode::InvalidArgument, message);
+        }
+        let attributes = ["color"];
+        let picked = if req.limit_level {
+            let max_level = std::cmp::min(req.max_level, u32::from(std::u8::MAX)) as u8;
+            service_data.octrees[0].pick_point_up_to_level(&ray, req.radius, max_level, &attributes)
+        } else {
+            service_data.octrees[0].pick_point(&ray, req.radius, &attributes)
+        };
+        let mut resp = proto::PickPointReply::new();
+        match picked {
+            Ok(Some(picked)) => {
+                resp.set_hit(true);
+                let p = picked.point.position[0];
+                resp.mut_position().set_x(p.x);
+                resp.mut_position().set_y(p.y);
+                resp.mut_position().set_z(p.z);
+                if let Some(AttributeData::U8Vec3(data)) = picked.point.attributes.get("color") {
+                    let rgb32: Color<f32> = crate::Color {
+                        red: data[0].x,
+                        green: data[0].y,
+                        blue: data[0].z,
+                        alpha: 255,
+                    }
+                    .to_f32();
+                    resp.mut_color().set_red(rgb32.red);
+                    resp.mut_color().set_green(rgb32.green);
+                    resp.mut_color().set_blue(rgb32.blue);
+                    resp.mut_color().set_alpha(rgb32.alpha);
+                }
+                resp.set_distance_along_ray(picked.distance_along_ray);
+                resp.set_distance_to_ray(picked.distance_to_ray);
+            }
+            Ok(None) => resp.set_hit(false),
+            Err(e) => return send_fail(&ctx, sink, RpcStatusCode::Internal, e.to_string()),
+        }
+        let f = sink
+            .success(resp)
+            .map_err(move |e| eprintln!("failed to reply {:?}: {:?}", req, e));
+        ctx.spawn(f)
+    }
+
     fn get_all_points(
         &mut self,
         ctx: RpcContext,
@@ -163,6 +256,131 @@ impl proto_grpc::Octree for OctreeService {
     }
 }
 
+/// The number of points that fit into one reply, which must be below the 4 MB message limit.
+fn num_points_per_reply() -> usize {
+    let mut reply = proto::PointsReply::new();
+    let initial_proto_size = reply.compute_size();
+    let mut v = point_viewer::proto::Vector3d::new();
+    v.set_x(1.);
+    v.set_y(1.);
+    v.set_z(1.);
+    reply.mut_positions().push(v);
+
+    let mut v = point_viewer::proto::Color::new();
+    v.set_red(1.);
+    v.set_green(1.);
+    v.set_blue(1.);
+    v.set_alpha(1.);
+    reply.mut_colors().push(v);
+
+    reply.mut_intensities().push(1.);
+
+    let bytes_per_point = reply.compute_size() - initial_proto_size;
+    let max_message_size = 4 * 1024 * 1024;
+    max_message_size / bytes_per_point as usize
+}
+
+// This function is currently not efficiently implemented.
+fn to_points_reply(p_data: PointsBatch) -> Result<proto::PointsReply> {
+    let mut reply = proto::PointsReply::new();
+    reply.positions = p_data
+        .position
+        .iter()
+        .map(|p| {
+            let mut v = point_viewer::proto::Vector3d::new();
+            v.set_x(p.x);
+            v.set_y(p.y);
+            v.set_z(p.z);
+            v
+        })
+        .collect();
+
+    reply.colors = match p_data.attributes.get(&"color".to_string()) {
+        Some(AttributeData::U8Vec3(data)) => data
+            .iter()
+            .map(|p| {
+                let rgb8: Color<u8> = crate::Color {
+                    red: p.x,
+                    green: p.y,
+                    blue: p.z,
+                    alpha: 255,
+                };
+                let rgb32: Color<f32> = crate::Color::to_f32(rgb8);
+                let mut v = point_viewer::proto::Color::new();
+                v.set_red(rgb32.red);
+                v.set_green(rgb32.green);
+                v.set_blue(rgb32.blue);
+                v.set_alpha(rgb32.alpha);
+                v
+            })
+            .collect(),
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Color format is not u8",
+            )
+            .into());
+        }
+    };
+
+    reply.intensities = match p_data.attributes.get(&"intensity".to_string()) {
+        Some(AttributeData::F32(data)) => data.clone(),
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Intensity format is not f32",
+            )
+            .into());
+        }
+    };
+    Ok(reply)
+}
+
+/// The replies to a points request: One per batch of points, followed by a last one that lists
+/// the nodes which were skipped because they could not be read. The stream ends after the first
+/// error, and dropping it cancels the query.
+struct PointsReplies {
+    points: PointsStream<Octree>,
+    done: bool,
+}
+
+impl Stream for PointsReplies {
+    type Item = proto::PointsReply;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<proto::PointsReply>, Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        let result = match self.points.poll() {
+            Ok(Async::Ready(Some(batch))) => to_points_reply(batch),
+            Ok(Async::Ready(None)) => {
+                let skipped_nodes = self
+                    .points
+                    .skipped_nodes()
+                    .into_iter()
+                    .map(|skipped| {
+                        let mut skipped_node = proto::SkippedNode::new();
+                        skipped_node.set_id(skipped.node_id);
+                        skipped_node.set_error(skipped.error);
+                        skipped_node
+                    })
+                    .collect::<Vec<_>>();
+                let mut reply = proto::PointsReply::new();
+                reply.set_skipped_nodes(skipped_nodes.into());
+                self.done = true;
+                Ok(reply)
+            }
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.done = true;
+        }
+        result.map(|reply| Async::Ready(Some(reply)))
+    }
+}
+
 impl OctreeService {
     fn stream_points_back_to_sink(
         &self,
@@ -171,141 +389,60 @@ impl OctreeService {
         ctx: &RpcContext,
         resp: ServerStreamingSink<proto::PointsReply>,
     ) {
-        use std::thread;
-
         let service_data = match self.get_service_data(octree_id) {
             Ok(service_data) => service_data,
-            Err(e) => return send_fail_stream(&ctx, resp, e.to_string()),
+            Err(e) => return send_fail_stream(&ctx, resp, RpcStatusCode::Internal, e.to_string()),
         };
 
-        // This creates a async-aware (tx, rx) pair that can wake up the event loop when new data
-        // is piped through it.
-
-        // We create a channel with a small buffer, which yields better performance than
-        // fully blocking without requiring a ton of memory. This has not been carefully benchmarked
-        // for best performance though.
+        let point_query = PointQuery {
+            attributes: vec!["color", "intensity"],
+            location,
+            ..Default::default()
+        };
+        // We use a small buffer, which yields better performance than fully blocking without
+        // requiring a ton of memory. This has not been carefully benchmarked for best
+        // performance though.
         let buffer_size = 4;
-        let (tx, rx) = mpsc::channel(buffer_size);
-        thread::spawn(move || {
-            // This is the secret sauce connecting an OS thread to a event-based receiver. Calling
-            // wait() on this turns the event aware, i.e. async 'tx' into a blocking 'tx' that will
-            // make this thread block when the event loop is not quick enough with piping out data.
-            let mut tx = tx.wait();
-
-            let mut reply = proto::PointsReply::new();
-            let bytes_per_point = {
-                let initial_proto_size = reply.compute_size();
-                let mut v = point_viewer::proto::Vector3d::new();
-                v.set_x(1.);
-                v.set_y(1.);
-                v.set_z(1.);
-                reply.mut_positions().push(v);
-
-                let mut v = point_viewer::proto::Color::new();
-                v.set_red(1.);
-                v.set_green(1.);
-                v.set_blue(1.);
-                v.set_alpha(1.);
-                reply.mut_colors().push(v);
-
-                reply.mut_intensities().push(1.);
-
-                let final_proto_size = reply.compute_size();
-                reply.mut_positions().clear();
-                reply.mut_colors().clear();
-                reply.mut_intensities().clear();
-                final_proto_size - initial_proto_size
-            };
-
-            // Proto message must be below 4 MB.
-            let max_message_size = 4 * 1024 * 1024;
-            let num_points_per_batch: usize = max_message_size / bytes_per_point as usize;
-
-            {
-                // Extra scope to make sure that 'func' does not outlive 'reply'.
-                // this function is currently not efficiently implemented
-                let func = |p_data: PointsBatch| {
-                    reply.positions = p_data
-                        .position
-                        .iter()
-                        .map(|p| {
-                            let mut v = point_viewer::proto::Vector3d::new();
-                            v.set_x(p.x);
-                            v.set_y(p.y);
-                            v.set_z(p.z);
-                            v
-                        })
-                        .collect();
-
-                    reply.colors = match p_data.attributes.get(&"color".to_string()) {
-                        Some(AttributeData::U8Vec3(data)) => data
-                            .iter()
-                            .map(|p| {
-                                let rgb8: Color<u8> = crate::Color {
-                                    red: p.x,
-                                    green: p.y,
-                                    blue: p.z,
-                                    alpha: 255,
-                                };
-                                let rgb32: Color<f32> = crate::Color::to_f32(rgb8);
-                                let mut v = point_viewer::proto::Color::new();
-                                v.set_red(rgb32.red);
-                                v.set_green(rgb32.green);
-                                v.set_blue(rgb32.blue);
-                                v.set_alpha(rgb32.alpha);
-                                v
-                            })
-                            .collect(),
-                        _ => {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "Color format is not u8",
-                            )
-                            .into());
-                        }
-                    };
-
-                    reply.intensities = match p_data.attributes.get(&"intensity".to_string()) {
-                        Some(AttributeData::F32(data)) => data.clone(),
-                        _ => {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::InvalidData,
-                                "Intensity format is not f32",
-                            )
-                            .into());
-                        }
-                    };
-
-                    tx.send((reply.clone(), WriteFlags::default())).unwrap();
-                    reply.mut_positions().clear();
-                    reply.mut_colors().clear();
-                    reply.mut_intensities().clear();
-                    Ok(())
-                };
-
-                let octree_slice: &[Octree] = std::slice::from_ref(&service_data.octree);
-                let point_query = PointQuery {
-                    attributes: vec!["color", "intensity"],
-                    location,
-                    ..Default::default()
-                };
-                let mut parallel_iterator = ParallelIterator::new(
-                    octree_slice,
-                    &point_query,
-                    num_points_per_batch,
-                    std::cmp::max(1, num_cpus::get() - 1),
-                    buffer_size,
-                );
-                // TODO(catevita): missing error handling for the thread
-                let _result = parallel_iterator.try_for_each_batch(func);
-            }
-            tx.send((reply, WriteFlags::default())).unwrap();
-        });
+        // A corrupt node should not fail the whole request.
+        let points = match PointsIterator::new(
+            Arc::clone(&service_data.octrees),
+            &point_query,
+            num_points_per_reply(),
+            std::cmp::max(1, num_cpus::get() - 1),
+            buffer_size,
+            true,
+            Arc::new(QueryHandle::new()),
+        ) {
+            Ok(points) => points,
+            Err(e) => return send_fail_stream(&ctx, resp, RpcStatusCode::Internal, e.to_string()),
+        };
+        let replies = PointsReplies {
+            points: points.into_stream(),
+            done: false,
+        };
 
-        let rx = rx.map_err(|_| grpcio::Error::RemoteStopped);
-        let f = resp
-            .send_all(rx)
-            .map(|_| {})
+        // Replies are only polled when the sink can take them, so a slow client slows down the
+        // query, and a client going away drops the stream and with it the query.
+        let f = replies
+            .then(|result| {
+                Ok::<_, grpcio::Error>(
+                    result.map_err(|e| format!("Failed to stream points: {}", e)),
+                )
+            })
+            .fold((resp, None), |(sink, error), item| match item {
+                Ok(reply) => Either::A(
+                    sink.send((reply, WriteFlags::default()))
+                        .map(|sink| (sink, error)),
+                ),
+                // An error is sent instead of the last reply, and fails the whole request.
+                Err(message) => Either::B(future::ok((sink, Some(message)))),
+            })
+            .and_then(|(mut sink, error)| match error {
+                None => Either::A(future::poll_fn(move || sink.close())),
+                Some(message) => {
+                    Either::B(sink.fail(RpcStatus::new(RpcStatusCode::Internal, Some(message))))
+                }
+            })
             .map_err(|e| eprintln!("failed to reply: {:?}", e));
         ctx.spawn(f)
     }
@@ -319,7 +456,10 @@ impl OctreeService {
                 .generate_data_provider(self.location.join(&octree_id).to_string_lossy())?,
         )?;
         let meta = octree.to_meta_proto();
-        let service_data = Arc::new(OctreeServiceData { octree, meta });
+        let service_data = Arc::new(OctreeServiceData {
+            octrees: vec![octree].into(),
+            meta,
+        });
         self.data_cache
             .write()
             .unwrap()
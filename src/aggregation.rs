@@ -0,0 +1,277 @@
+//! Aggregates of the points matching a query, e.g. counts or histograms in a region, computed
+//! without transferring the points.
+
+use crate::errors::*;
+use crate::geometry::Aabb;
+use crate::iterator::{PointCloud, PointQuery};
+use crate::math::Relation;
+use crate::statistics::AttributeAccumulator;
+pub use crate::statistics::HistogramBins;
+use crate::PointsBatch;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// What to compute besides the number of points, which is always computed. Attributes can be
+/// named "position" to aggregate the coordinates of the points.
+#[derive(Clone, Debug, Default)]
+pub struct Aggregations<'a> {
+    /// Compute the tight bounding box of the points.
+    pub bounding_box: bool,
+    /// Compute min, max and mean of each component of these attributes.
+    pub statistics: Vec<&'a str>,
+    /// Compute a histogram of each component of these attributes.
+    pub histograms: HashMap<&'a str, HistogramBins>,
+}
+
+impl<'a> Aggregations<'a> {
+    /// Whether the points of a node need to be read, or whether its number of points suffices.
+    fn needs_points(&self) -> bool {
+        self.bounding_box || !self.statistics.is_empty() || !self.histograms.is_empty()
+    }
+
+    /// The attributes that need to be read to compute the aggregations.
+    pub fn attributes(&self) -> Vec<&'a str> {
+        let mut attributes: Vec<&'a str> = self
+            .statistics
+            .iter()
+            .chain(self.histograms.keys())
+            .filter(|name| **name != "position")
+            .cloned()
+            .collect();
+        attributes.sort();
+        attributes.dedup();
+        attributes
+    }
+
+    /// Returns 'query' with the attributes needed to compute the aggregations added.
+    pub(crate) fn query(&self, query: &PointQuery<'a>) -> PointQuery<'a> {
+        let mut attributes = query.attributes.clone();
+        attributes.extend(self.attributes());
+        attributes.sort();
+        attributes.dedup();
+        PointQuery {
+            attributes,
+            ..query.clone()
+        }
+    }
+}
+
+/// Min, max and mean of one component of an attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ComponentSummary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// The aggregates of the points matching a query.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Aggregate {
+    pub num_points: u64,
+    /// Only computed if requested and if there are any points.
+    pub bounding_box: Option<Aabb<f64>>,
+    /// The summary of each component of each requested attribute.
+    pub statistics: BTreeMap<String, Vec<ComponentSummary>>,
+    /// The histogram of each component of each requested attribute.
+    pub histograms: BTreeMap<String, Vec<Vec<u64>>>,
+}
+
+/// The aggregates of some of the points matching a query, which can be merged.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PartialAggregate {
+    num_points: u64,
+    bounding_box: Option<Aabb<f64>>,
+    attributes: AttributeAccumulator,
+}
+
+impl PartialAggregate {
+    fn grow_bounding_box(&mut self, bounding_box: &Aabb<f64>) {
+        match &mut self.bounding_box {
+            Some(aabb) => {
+                aabb.grow(*bounding_box.min());
+                aabb.grow(*bounding_box.max());
+            }
+            None => self.bounding_box = Some(bounding_box.clone()),
+        }
+    }
+
+    fn add_batch(&mut self, batch: &PointsBatch, aggregations: &Aggregations) {
+        self.num_points += batch.position.len() as u64;
+        if aggregations.bounding_box {
+            for p in &batch.position {
+                self.grow_bounding_box(&Aabb::new(*p, *p));
+            }
+        }
+        for name in &aggregations.statistics {
+            self.attributes.add_statistics(batch, name);
+        }
+        for (name, bins) in &aggregations.histograms {
+            self.attributes.add_histograms(batch, name, |_| *bins);
+        }
+    }
+
+    pub fn merge(&mut self, other: PartialAggregate) {
+        self.num_points += other.num_points;
+        if let Some(bounding_box) = &other.bounding_box {
+            self.grow_bounding_box(bounding_box);
+        }
+        self.attributes.merge(other.attributes);
+    }
+
+    pub fn finish(self) -> Aggregate {
+        let statistics = self
+            .attributes
+            .statistics
+            .into_iter()
+            .map(|(name, components)| {
+                let summaries = components
+                    .iter()
+                    .map(|stats| ComponentSummary {
+                        min: stats.min,
+                        max: stats.max,
+                        mean: stats.mean(),
+                    })
+                    .collect();
+                (name, summaries)
+            })
+            .collect();
+        Aggregate {
+            num_points: self.num_points,
+            bounding_box: self.bounding_box,
+            statistics,
+            histograms: self.attributes.histograms,
+        }
+    }
+}
+
+/// Aggregates the points matching 'query' in a single node, and returns the number of bytes read
+/// from it. Nodes that are completely inside the query are answered from the meta alone if only
+/// the number of points is needed. 'query' must be from `Aggregations::query`, with its filter
+/// resolved by `resolve_filter`.
+pub(crate) fn aggregate_node<C: PointCloud + ?Sized>(
+    point_cloud: &C,
+    query: &PointQuery,
+    aggregations: &Aggregations,
+    node_id: C::Id,
+    relation: Relation,
+    batch_size: usize,
+) -> Result<(PartialAggregate, usize)> {
+    let mut aggregate = PartialAggregate::default();
+    if !aggregations.needs_points()
+        && relation == Relation::In
+        && point_cloud.filter_relation(query.filter.as_ref(), node_id) == Relation::In
+    {
+        aggregate.num_points = point_cloud.num_points_in_node(node_id) as u64;
+        return Ok((aggregate, 0));
+    }
+    let num_bytes = point_cloud.stream_points_for_query_in_node(
+        query,
+        node_id,
+        relation,
+        batch_size,
+        |batch| {
+            aggregate.add_batch(&batch, aggregations);
+            Ok(())
+        },
+    )?;
+    Ok((aggregate, num_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterator::{ParallelIterator, PointLocation, QueryHandle};
+    use crate::math::ClosedInterval;
+    use crate::octree::tests::{build_and_open_octree, spread_out_points, NUM_POINTS};
+    use crate::octree::BuildOptions;
+    use nalgebra::Point3;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_aggregate() {
+        let (batch, bounding_box) = spread_out_points();
+        let (_tmp_dir, octree) = build_and_open_octree(
+            0.001,
+            bounding_box,
+            vec![batch],
+            &["color"],
+            &BuildOptions::default(),
+        );
+
+        // All nodes are inside, so the count is answered from the meta without reading points.
+        let everything = PointQuery {
+            location: PointLocation::Aabb(Aabb::new(
+                Point3::new(-1., -1., -1.),
+                Point3::new(1001., 1001., 1001.),
+            )),
+            ..Default::default()
+        };
+        let handle = Arc::new(QueryHandle::new());
+        let aggregate =
+            ParallelIterator::new(std::slice::from_ref(&octree), &everything, 4096, 2, 2)
+                .query_handle(Arc::clone(&handle))
+                .aggregate(&Aggregations::default())
+                .unwrap();
+        assert_eq!(aggregate.num_points, NUM_POINTS as u64);
+        assert!(aggregate.bounding_box.is_none());
+        assert_eq!(handle.num_bytes_read(), 0);
+
+        // Compare against the points, as they were decoded from the octree.
+        let query = PointQuery {
+            attributes: vec!["color"],
+            location: PointLocation::Aabb(Aabb::new(
+                Point3::new(100., 10., 0.),
+                Point3::new(200., 20., 3.),
+            )),
+            ..Default::default()
+        };
+        let mut positions = Vec::new();
+        ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+            .try_for_each_batch(|points_batch| {
+                positions.extend(points_batch.position);
+                Ok(())
+            })
+            .unwrap();
+        assert!(!positions.is_empty());
+        let mut expected_bounding_box = Aabb::new(positions[0], positions[0]);
+        positions
+            .iter()
+            .for_each(|p| expected_bounding_box.grow(*p));
+        let expected_mean_x = positions.iter().map(|p| p.x).sum::<f64>() / positions.len() as f64;
+
+        let aggregations = Aggregations {
+            bounding_box: true,
+            statistics: vec!["position"],
+            histograms: vec![(
+                "color",
+                HistogramBins {
+                    range: ClosedInterval::new(0., 256.),
+                    num_bins: 2,
+                },
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let handle = Arc::new(QueryHandle::new());
+        let aggregate = ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+            .query_handle(Arc::clone(&handle))
+            .aggregate(&aggregations)
+            .unwrap();
+        assert_eq!(aggregate.num_points, positions.len() as u64);
+        assert!(handle.num_bytes_read() > 0);
+        assert_eq!(aggregate.bounding_box, Some(expected_bounding_box.clone()));
+        let x = aggregate.statistics["position"][0];
+        assert_eq!(x.min, expected_bounding_box.min().x);
+        assert_eq!(x.max, expected_bounding_box.max().x);
+        assert!((x.mean - expected_mean_x).abs() < 1e-6);
+        // All points are green.
+        assert_eq!(
+            aggregate.histograms["color"],
+            vec![
+                vec![positions.len() as u64, 0],
+                vec![0, positions.len() as u64],
+                vec![positions.len() as u64, 0]
+            ]
+        );
+    }
+}
@@ -1,6 +1,15 @@
+use crate::color::Color;
 use crate::errors::{ErrorKind, Result};
-use nalgebra::Vector3;
+use crate::math::ClosedInterval;
+use crate::PointsBatch;
+use nalgebra::{Vector3, Vector4};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
 pub use point_viewer_proto_rust::proto;
 
@@ -17,7 +26,11 @@ pub enum AttributeDataType {
     F32,
     F64,
     U8Vec3,
+    U16Vec3,
+    I32Vec3,
+    F32Vec3,
     F64Vec3,
+    U8Vec4,
 }
 
 impl AttributeDataType {
@@ -34,7 +47,11 @@ impl AttributeDataType {
             AttributeDataType::F32 => proto::AttributeDataType::F32,
             AttributeDataType::F64 => proto::AttributeDataType::F64,
             AttributeDataType::U8Vec3 => proto::AttributeDataType::U8Vec3,
+            AttributeDataType::U16Vec3 => proto::AttributeDataType::U16Vec3,
+            AttributeDataType::I32Vec3 => proto::AttributeDataType::I32Vec3,
+            AttributeDataType::F32Vec3 => proto::AttributeDataType::F32Vec3,
             AttributeDataType::F64Vec3 => proto::AttributeDataType::F64Vec3,
+            AttributeDataType::U8Vec4 => proto::AttributeDataType::U8Vec4,
         }
     }
 
@@ -51,7 +68,11 @@ impl AttributeDataType {
             proto::AttributeDataType::F32 => AttributeDataType::F32,
             proto::AttributeDataType::F64 => AttributeDataType::F64,
             proto::AttributeDataType::U8Vec3 => AttributeDataType::U8Vec3,
+            proto::AttributeDataType::U16Vec3 => AttributeDataType::U16Vec3,
+            proto::AttributeDataType::I32Vec3 => AttributeDataType::I32Vec3,
+            proto::AttributeDataType::F32Vec3 => AttributeDataType::F32Vec3,
             proto::AttributeDataType::F64Vec3 => AttributeDataType::F64Vec3,
+            proto::AttributeDataType::U8Vec4 => AttributeDataType::U8Vec4,
             proto::AttributeDataType::INVALID_DATA_TYPE => {
                 return Err(
                     ErrorKind::InvalidInput("Attribute data type invalid".to_string()).into(),
@@ -68,7 +89,10 @@ impl AttributeDataType {
             AttributeDataType::U32 | AttributeDataType::I32 | AttributeDataType::F32 => 4,
             AttributeDataType::U64 | AttributeDataType::I64 | AttributeDataType::F64 => 8,
             AttributeDataType::U8Vec3 => 3,
+            AttributeDataType::U16Vec3 => 3 * 2,
+            AttributeDataType::I32Vec3 | AttributeDataType::F32Vec3 => 3 * 4,
             AttributeDataType::F64Vec3 => 3 * 8,
+            AttributeDataType::U8Vec4 => 4,
         }
     }
 }
@@ -87,7 +111,11 @@ pub enum AttributeData {
     F32(Vec<f32>),
     F64(Vec<f64>),
     U8Vec3(Vec<Vector3<u8>>),
+    U16Vec3(Vec<Vector3<u16>>),
+    I32Vec3(Vec<Vector3<i32>>),
+    F32Vec3(Vec<Vector3<f32>>),
     F64Vec3(Vec<Vector3<f64>>),
+    U8Vec4(Vec<Vector4<u8>>),
 }
 
 // Convenience macro if you want to operate on the Vec inside an AttributeData
@@ -106,27 +134,40 @@ macro_rules! match_attr_data {
             AttributeData::F32(_d) => $match_rhs!(F32, _d $(, $arg )* ),
             AttributeData::F64(_d) => $match_rhs!(F64, _d $(, $arg )* ),
             AttributeData::U8Vec3(_d) => $match_rhs!(U8Vec3, _d $(, $arg )* ),
+            AttributeData::U16Vec3(_d) => $match_rhs!(U16Vec3, _d $(, $arg )* ),
+            AttributeData::I32Vec3(_d) => $match_rhs!(I32Vec3, _d $(, $arg )* ),
+            AttributeData::F32Vec3(_d) => $match_rhs!(F32Vec3, _d $(, $arg )* ),
             AttributeData::F64Vec3(_d) => $match_rhs!(F64Vec3, _d $(, $arg )* ),
+            AttributeData::U8Vec4(_d) => $match_rhs!(U8Vec4, _d $(, $arg )* ),
         }
     };
 }
 
+/// Like 'match_attr_data', but only for scalar attributes. Returns the result of the match arm,
+/// or an error message for vector attributes.
 #[macro_export]
 macro_rules! match_1d_attr_data {
     ($x:expr, $match_rhs:tt $(, $arg:tt )* ) => {
         match $x {
-            AttributeData::U8(_d) => $match_rhs!(U8, _d $(, $arg )* ),
-            AttributeData::U16(_d) => $match_rhs!(U16, _d $(, $arg )* ),
-            AttributeData::U32(_d) => $match_rhs!(U32, _d $(, $arg )* ),
-            AttributeData::U64(_d) => $match_rhs!(U64, _d $(, $arg )* ),
-            AttributeData::I8(_d) => $match_rhs!(I8, _d $(, $arg )* ),
-            AttributeData::I16(_d) => $match_rhs!(I16, _d $(, $arg )* ),
-            AttributeData::I32(_d) => $match_rhs!(I32, _d $(, $arg )* ),
-            AttributeData::I64(_d) => $match_rhs!(I64, _d $(, $arg )* ),
-            AttributeData::F32(_d) => $match_rhs!(F32, _d $(, $arg )* ),
-            AttributeData::F64(_d) => $match_rhs!(F64, _d $(, $arg )* ),
-            AttributeData::U8Vec3(_d) => unimplemented!(),
-            AttributeData::F64Vec3(_d) => unimplemented!(),
+            AttributeData::U8(_d) => Ok($match_rhs!(U8, _d $(, $arg )* )),
+            AttributeData::U16(_d) => Ok($match_rhs!(U16, _d $(, $arg )* )),
+            AttributeData::U32(_d) => Ok($match_rhs!(U32, _d $(, $arg )* )),
+            AttributeData::U64(_d) => Ok($match_rhs!(U64, _d $(, $arg )* )),
+            AttributeData::I8(_d) => Ok($match_rhs!(I8, _d $(, $arg )* )),
+            AttributeData::I16(_d) => Ok($match_rhs!(I16, _d $(, $arg )* )),
+            AttributeData::I32(_d) => Ok($match_rhs!(I32, _d $(, $arg )* )),
+            AttributeData::I64(_d) => Ok($match_rhs!(I64, _d $(, $arg )* )),
+            AttributeData::F32(_d) => Ok($match_rhs!(F32, _d $(, $arg )* )),
+            AttributeData::F64(_d) => Ok($match_rhs!(F64, _d $(, $arg )* )),
+            AttributeData::U8Vec3(_)
+            | AttributeData::U16Vec3(_)
+            | AttributeData::I32Vec3(_)
+            | AttributeData::F32Vec3(_)
+            | AttributeData::F64Vec3(_)
+            | AttributeData::U8Vec4(_) => Err(format!(
+                "Expected a scalar attribute, but it has {} components.",
+                $x.dim()
+            )),
         }
     };
 }
@@ -157,7 +198,12 @@ impl AttributeData {
             | AttributeData::I64(_)
             | AttributeData::F32(_)
             | AttributeData::F64(_) => 1,
-            AttributeData::U8Vec3(_) | AttributeData::F64Vec3(_) => 3,
+            AttributeData::U8Vec3(_)
+            | AttributeData::U16Vec3(_)
+            | AttributeData::I32Vec3(_)
+            | AttributeData::F32Vec3(_)
+            | AttributeData::F64Vec3(_) => 3,
+            AttributeData::U8Vec4(_) => 4,
         }
     }
 
@@ -183,7 +229,11 @@ impl AttributeData {
             (AttributeData::F32(s), AttributeData::F32(o)) => s.append(o),
             (AttributeData::F64(s), AttributeData::F64(o)) => s.append(o),
             (AttributeData::U8Vec3(s), AttributeData::U8Vec3(o)) => s.append(o),
+            (AttributeData::U16Vec3(s), AttributeData::U16Vec3(o)) => s.append(o),
+            (AttributeData::I32Vec3(s), AttributeData::I32Vec3(o)) => s.append(o),
+            (AttributeData::F32Vec3(s), AttributeData::F32Vec3(o)) => s.append(o),
             (AttributeData::F64Vec3(s), AttributeData::F64Vec3(o)) => s.append(o),
+            (AttributeData::U8Vec4(s), AttributeData::U8Vec4(o)) => s.append(o),
             (s, o) => {
                 return Err(format!(
                     "Own data type '{:?}' is incompatible with other type '{:?}'.",
@@ -214,6 +264,276 @@ impl AttributeData {
     }
 }
 
+/// Gives access to the components of a single attribute value as f64.
+pub(crate) trait Components {
+    fn for_each_component(&self, f: &mut impl FnMut(usize, f64));
+
+    /// Returns the component with 'index', or NaN if there is none.
+    fn component(&self, index: usize) -> f64 {
+        let mut component = std::f64::NAN;
+        self.for_each_component(&mut |i, value| {
+            if i == index {
+                component = value;
+            }
+        });
+        component
+    }
+}
+
+macro_rules! scalar_components {
+    ($($scalar:ty),*) => {
+        $(
+            impl Components for $scalar {
+                fn for_each_component(&self, f: &mut impl FnMut(usize, f64)) {
+                    f(0, *self as f64)
+                }
+            }
+        )*
+    };
+}
+
+scalar_components!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+macro_rules! vector_components {
+    ($($vector:ident),*) => {
+        $(
+            impl<T: Components + nalgebra::Scalar> Components for $vector<T> {
+                fn for_each_component(&self, f: &mut impl FnMut(usize, f64)) {
+                    for (i, value) in self.iter().enumerate() {
+                        value.for_each_component(&mut |_, v| f(i, v));
+                    }
+                }
+            }
+        )*
+    };
+}
+
+vector_components!(Vector3, Vector4);
+
+/// The smallest and largest value of every scalar attribute of the points in a node. Nodes whose
+/// ranges do not overlap the filter intervals of a query do not need to be read.
+/// NaN values are not part of the ranges, so it is recorded separately which attributes have them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AttributeRanges {
+    ranges: BTreeMap<String, ClosedInterval<f64>>,
+    with_nan: BTreeSet<String>,
+}
+
+/// Returns whether 'data' contains NaN values.
+fn extend_range<T: ToPrimitive>(range: &mut Option<ClosedInterval<f64>>, data: &[T]) -> bool {
+    let mut contains_nan = false;
+    for value in data.iter().filter_map(T::to_f64) {
+        if value.is_nan() {
+            contains_nan = true;
+            continue;
+        }
+        match range {
+            Some(range) => range.extend(value),
+            None => *range = Some(ClosedInterval::new(value, value)),
+        }
+    }
+    contains_nan
+}
+
+impl AttributeRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grows the ranges so that they include the scalar attributes of 'batch'.
+    pub fn update(&mut self, batch: &PointsBatch) {
+        macro_rules! rhs {
+            ($dtype:ident, $data:ident, $range:expr) => {
+                extend_range($range, $data)
+            };
+        }
+        for (name, data) in batch.attributes.iter().filter(|(_, data)| data.dim() == 1) {
+            let mut range = self.ranges.get(name).cloned();
+            let range_ref = &mut range;
+            if match_1d_attr_data!(data, rhs, range_ref) == Ok(true) {
+                self.with_nan.insert(name.clone());
+            }
+            if let Some(range) = range {
+                self.ranges.insert(name.clone(), range);
+            }
+        }
+    }
+
+    /// Returns the range of the values of 'attribute' that are not NaN.
+    pub fn get(&self, attribute: &str) -> Option<ClosedInterval<f64>> {
+        self.ranges.get(attribute).cloned()
+    }
+
+    /// Returns whether some values of 'attribute' are NaN.
+    pub fn contains_nan(&self, attribute: &str) -> bool {
+        self.with_nan.contains(attribute)
+    }
+
+    pub fn from_proto(protos: &[proto::AttributeRange]) -> Self {
+        let mut ranges = Self::new();
+        for range in protos {
+            if range.min <= range.max {
+                ranges.ranges.insert(
+                    range.name.clone(),
+                    ClosedInterval::new(range.min, range.max),
+                );
+            }
+            if range.contains_nan {
+                ranges.with_nan.insert(range.name.clone());
+            }
+        }
+        ranges
+    }
+
+    pub fn to_proto(&self) -> Vec<proto::AttributeRange> {
+        self.ranges
+            .keys()
+            .chain(self.with_nan.iter())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| {
+                let mut proto = proto::AttributeRange::new();
+                proto.set_name(name.clone());
+                // Attributes with only NaN values have an empty range.
+                let (min, max) = self
+                    .get(name)
+                    .map_or((std::f64::NAN, std::f64::NAN), |range| {
+                        (range.lower_bound(), range.upper_bound())
+                    });
+                proto.set_min(min);
+                proto.set_max(max);
+                proto.set_contains_nan(self.contains_nan(name));
+                proto
+            })
+            .collect()
+    }
+}
+
+/// The label and optional display color of a value of a categorical attribute.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AttributeLabel {
+    pub name: String,
+    pub color: Option<Color<f32>>,
+}
+
+/// Maps the values of a categorical attribute to labels, e.g. the classification code 2 to
+/// "ground".
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LabelDictionary(BTreeMap<i64, AttributeLabel>);
+
+impl LabelDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: i64, name: impl Into<String>, color: Option<Color<f32>>) {
+        let name = name.into();
+        self.0.insert(value, AttributeLabel { name, color });
+    }
+
+    pub fn label(&self, value: i64) -> Option<&AttributeLabel> {
+        self.0.get(&value)
+    }
+
+    /// Returns the value with the label 'name'.
+    pub fn value(&self, name: &str) -> Option<i64> {
+        self.0
+            .iter()
+            .find(|(_, label)| label.name == name)
+            .map(|(value, _)| *value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i64, &AttributeLabel)> {
+        self.0.iter().map(|(value, label)| (*value, label))
+    }
+
+    pub fn from_proto(proto: &proto::LabelDictionary) -> Self {
+        let mut dictionary = Self::new();
+        for label in proto.get_labels() {
+            let color = label.color.as_ref().map(|color| Color {
+                red: color.red,
+                green: color.green,
+                blue: color.blue,
+                alpha: color.alpha,
+            });
+            dictionary.insert(label.value, label.name.clone(), color);
+        }
+        dictionary
+    }
+
+    pub fn to_proto(&self, attribute: &str) -> proto::LabelDictionary {
+        let mut proto = proto::LabelDictionary::new();
+        proto.set_attribute(attribute.to_string());
+        for (value, label) in self.iter() {
+            let mut label_proto = proto::AttributeLabel::new();
+            label_proto.set_value(value);
+            label_proto.set_name(label.name.clone());
+            if let Some(color) = label.color {
+                let mut color_proto = proto::Color::new();
+                color_proto.set_red(color.red);
+                color_proto.set_green(color.green);
+                color_proto.set_blue(color.blue);
+                color_proto.set_alpha(color.alpha);
+                label_proto.set_color(color_proto);
+            }
+            proto.mut_labels().push(label_proto);
+        }
+        proto
+    }
+}
+
+/// The label dictionaries of the categorical attributes of a point cloud.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LabelDictionaries(BTreeMap<String, LabelDictionary>);
+
+impl LabelDictionaries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, attribute: impl Into<String>, dictionary: LabelDictionary) {
+        self.0.insert(attribute.into(), dictionary);
+    }
+
+    pub fn get(&self, attribute: &str) -> Option<&LabelDictionary> {
+        self.0.get(attribute)
+    }
+
+    /// Reads the label dictionaries from a JSON file that maps attributes to their dictionaries,
+    /// e.g. `{"classification": {"2": {"name": "ground"}, "6": {"name": "building"}}}`. Colors
+    /// are given like `"color": {"red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 1.0}`.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(|e| {
+            ErrorKind::InvalidInput(format!(
+                "Invalid label dictionaries in {}: {}",
+                path.display(),
+                e
+            ))
+            .into()
+        })
+    }
+
+    pub fn from_proto(protos: &[proto::LabelDictionary]) -> Self {
+        LabelDictionaries(
+            protos
+                .iter()
+                .map(|proto| (proto.attribute.clone(), LabelDictionary::from_proto(proto)))
+                .collect(),
+        )
+    }
+
+    pub fn to_proto(&self) -> Vec<proto::LabelDictionary> {
+        self.0
+            .iter()
+            .map(|(attribute, dictionary)| dictionary.to_proto(attribute))
+            .collect()
+    }
+}
+
 macro_rules! try_from_impl {
     ($data:ident, $attribute_data_type:ident, $vec_data_type:ty) => {
         match $data {
@@ -269,4 +589,8 @@ try_from_attribute_data!(I64, i64);
 try_from_attribute_data!(F32, f32);
 try_from_attribute_data!(F64, f64);
 try_from_attribute_data!(U8Vec3, Vector3<u8>);
+try_from_attribute_data!(U16Vec3, Vector3<u16>);
+try_from_attribute_data!(I32Vec3, Vector3<i32>);
+try_from_attribute_data!(F32Vec3, Vector3<f32>);
 try_from_attribute_data!(F64Vec3, Vector3<f64>);
+try_from_attribute_data!(U8Vec4, Vector4<u8>);
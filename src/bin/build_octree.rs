@@ -12,21 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use point_viewer::octree::build_octree_from_file;
+use point_viewer::attributes::LabelDictionaries;
+use point_viewer::math::Crs;
+use point_viewer::octree::{
+    build_octree_from_file, build_subtree_from_partition, merge_subtrees,
+    partition_octree_from_file, BuildOptions, DeduplicationPolicy, NodeId,
+};
 use rayon::ThreadPoolBuilder;
 use std::path::PathBuf;
-use structopt::StructOpt;
+use structopt::{clap, StructOpt};
 
+const ATTRIBUTES: &[&str] = &["color", "intensity"];
+
+/// Without a subcommand, builds a complete octree on this machine.
 #[derive(StructOpt, Debug)]
 #[structopt(name = "build_octree")]
 struct CommandlineArguments {
-    /// PLY/PTS file to parse for the points.
+    /// PLY/PTS file to parse for the points. Required without a subcommand.
     #[structopt(parse(from_os_str))]
-    input: PathBuf,
+    input: Option<PathBuf>,
 
-    /// Output directory to write the octree into.
+    /// Output directory to write the octree into. Required without a subcommand.
     #[structopt(long, parse(from_os_str))]
-    output_directory: PathBuf,
+    output_directory: Option<PathBuf>,
 
     /// Minimal precision that this point cloud should have.
     /// This decides on the number of bits used to encode each node.
@@ -36,6 +44,111 @@ struct CommandlineArguments {
     /// The number of threads used to shard octree building. Set this as high as possible for SSDs.
     #[structopt(long, default_value = "10")]
     num_threads: usize,
+
+    /// Continue an interrupted build in the output directory instead of starting from scratch.
+    #[structopt(long)]
+    resume: bool,
+
+    /// Merge points in the same leaf that are closer than the resolution. One of keep_first,
+    /// average or max_intensity, which decides which attributes the merged point gets.
+    #[structopt(long)]
+    deduplicate: Option<DeduplicationPolicy>,
+
+    /// The frame to store the positions in, e.g. "ecef", "utm:10N" or "enu:37.4,-122.1". It
+    /// is recorded in the meta.
+    #[structopt(long)]
+    crs: Option<Crs>,
+
+    /// The frame of the input positions, if they need to be converted into 'crs'.
+    #[structopt(long)]
+    input_crs: Option<Crs>,
+
+    /// JSON file with the label dictionaries of categorical attributes to record in the meta,
+    /// e.g. {"classification": {"2": {"name": "ground"}}}.
+    #[structopt(long, parse(from_os_str))]
+    label_dictionaries: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Distributes the input into subtrees that can be built independently. Prints the ids of
+    /// all subtrees.
+    Partition {
+        /// PLY/PTS file to parse for the points.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Output directory to write the partition into.
+        #[structopt(long, parse(from_os_str))]
+        output_directory: PathBuf,
+
+        /// Minimal precision that this point cloud should have.
+        /// This decides on the number of bits used to encode each node.
+        #[structopt(long, default_value = "0.001")]
+        resolution: f64,
+
+        /// Octree level of the subtree roots. There are up to 8^level subtrees.
+        #[structopt(long, default_value = "2")]
+        partition_level: u8,
+
+        /// The frame to store the positions in, e.g. "ecef", "utm:10N" or "enu:37.4,-122.1". It
+        /// is recorded in the meta of the partition, the subtrees and the merged octree.
+        #[structopt(long)]
+        crs: Option<Crs>,
+
+        /// The frame of the input positions, if they need to be converted into 'crs'.
+        #[structopt(long)]
+        input_crs: Option<Crs>,
+
+        /// JSON file with the label dictionaries of categorical attributes to record in the meta,
+        /// e.g. {"classification": {"2": {"name": "ground"}}}.
+        #[structopt(long, parse(from_os_str))]
+        label_dictionaries: Option<PathBuf>,
+    },
+
+    /// Builds one subtree of a partition.
+    Subtree {
+        /// Directory of the partition.
+        #[structopt(long, parse(from_os_str))]
+        partition_directory: PathBuf,
+
+        /// Id of the subtree's root, as printed by 'partition'.
+        #[structopt(long)]
+        subtree: NodeId,
+
+        /// Output directory to write the subtree into.
+        #[structopt(long, parse(from_os_str))]
+        output_directory: PathBuf,
+
+        /// Continue an interrupted build in the output directory instead of starting from scratch.
+        #[structopt(long)]
+        resume: bool,
+
+        /// Merge points in the same leaf that are closer than the resolution. One of keep_first,
+        /// average or max_intensity, which decides which attributes the merged point gets.
+        #[structopt(long)]
+        deduplicate: Option<DeduplicationPolicy>,
+    },
+
+    /// Merges the subtrees of a partition into a complete octree.
+    Merge {
+        /// Directories of all subtrees.
+        #[structopt(parse(from_os_str), required = true)]
+        subtree_directories: Vec<PathBuf>,
+
+        /// Output directory to write the octree into.
+        #[structopt(long, parse(from_os_str))]
+        output_directory: PathBuf,
+    },
+}
+
+fn read_label_dictionaries(path: Option<PathBuf>) -> LabelDictionaries {
+    path.map_or_else(LabelDictionaries::new, |path| {
+        LabelDictionaries::from_json_file(path).expect("Could not read the label dictionaries.")
+    })
 }
 
 fn main() {
@@ -44,10 +157,85 @@ fn main() {
         .num_threads(args.num_threads)
         .build_global()
         .expect("Could not create thread pool.");
-    build_octree_from_file(
-        args.output_directory,
-        args.resolution,
-        args.input,
-        &["color", "intensity"],
-    );
+    let command = match args.command {
+        Some(command) => command,
+        None => {
+            let (input, output_directory) = match (args.input, args.output_directory) {
+                (Some(input), Some(output_directory)) => (input, output_directory),
+                _ => clap::Error::with_description(
+                    "An input and --output-directory are required without a subcommand.",
+                    clap::ErrorKind::MissingRequiredArgument,
+                )
+                .exit(),
+            };
+            build_octree_from_file(
+                output_directory,
+                args.resolution,
+                input,
+                ATTRIBUTES,
+                &BuildOptions {
+                    resume: args.resume,
+                    deduplication: args.deduplicate,
+                    // Without a target frame, the input is stored in its own frame.
+                    crs: args.crs.or(args.input_crs),
+                    input_crs: args.input_crs,
+                    label_dictionaries: read_label_dictionaries(args.label_dictionaries),
+                },
+            )
+            .expect("Could not build the octree.");
+            return;
+        }
+    };
+    match command {
+        Command::Partition {
+            input,
+            output_directory,
+            resolution,
+            partition_level,
+            crs,
+            input_crs,
+            label_dictionaries,
+        } => {
+            let subtrees = partition_octree_from_file(
+                output_directory,
+                resolution,
+                input,
+                ATTRIBUTES,
+                partition_level,
+                &BuildOptions {
+                    crs: crs.or(input_crs),
+                    input_crs,
+                    label_dictionaries: read_label_dictionaries(label_dictionaries),
+                    ..Default::default()
+                },
+            )
+            .expect("Could not partition the input.");
+            for id in subtrees {
+                println!("{}", id);
+            }
+        }
+        Command::Subtree {
+            partition_directory,
+            subtree,
+            output_directory,
+            resume,
+            deduplicate,
+        } => build_subtree_from_partition(
+            output_directory,
+            partition_directory,
+            subtree,
+            ATTRIBUTES,
+            &BuildOptions {
+                resume,
+                deduplication: deduplicate,
+                ..Default::default()
+            },
+        )
+        .expect("Could not build the subtree."),
+        Command::Merge {
+            subtree_directories,
+            output_directory,
+        } => merge_subtrees(output_directory, &subtree_directories, ATTRIBUTES)
+            .expect("Could not merge the subtrees."),
+    }
 }
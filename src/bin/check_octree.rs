@@ -0,0 +1,78 @@
+use point_viewer::octree::{check_octree, repair_octree, CheckReport, Problem};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "check_octree")]
+/// Verifies that the node files of an octree match its meta.
+struct CommandlineArguments {
+    /// Directory of the octree to check.
+    #[structopt(parse(from_os_str))]
+    directory: PathBuf,
+
+    /// Print the report as JSON instead of text.
+    #[structopt(long)]
+    json: bool,
+
+    /// Rewrite the meta so that it only contains intact nodes. Node files are not deleted.
+    #[structopt(long)]
+    repair: bool,
+}
+
+fn describe(problem: &Problem) -> String {
+    match problem {
+        Problem::MissingFile { node, attribute } => {
+            format!("{}: missing file for '{}'", node, attribute)
+        }
+        Problem::FileSizeMismatch {
+            node,
+            attribute,
+            expected_bytes,
+            actual_bytes,
+        } => format!(
+            "{}: file for '{}' has {} bytes, expected {}",
+            node, attribute, actual_bytes, expected_bytes
+        ),
+        Problem::PositionsOutsideNode { node, num_points } => format!(
+            "{}: {} points are outside of the node's bounding cube",
+            node, num_points
+        ),
+        Problem::MissingParent { node, parent } => {
+            format!("{}: parent {} does not exist", node, parent)
+        }
+        Problem::OrphanFile { file } => format!("{}: not part of any node", file),
+    }
+}
+
+fn print_report(report: &CheckReport) {
+    for problem in &report.problems {
+        println!("{}", describe(problem));
+    }
+    println!(
+        "Checked {} nodes, found {} problems.",
+        report.num_nodes,
+        report.problems.len()
+    );
+}
+
+fn main() {
+    let args = CommandlineArguments::from_args();
+    let report = if args.repair {
+        repair_octree(&args.directory)
+    } else {
+        check_octree(&args.directory)
+    }
+    .expect("Could not check octree.");
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print_report(&report);
+        if args.repair && !report.is_ok() {
+            println!("Rewrote the meta to match the data.");
+        }
+    }
+    if !report.is_ok() && !args.repair {
+        std::process::exit(1);
+    }
+}
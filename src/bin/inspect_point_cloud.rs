@@ -0,0 +1,157 @@
+use point_viewer::data_provider::DataProviderFactory;
+use point_viewer::errors::*;
+use point_viewer::iterator::{PointCloud, PointLocation};
+use point_viewer::octree::Octree;
+use point_viewer::s2_cells::S2Cells;
+use point_viewer::statistics::{
+    compute_attribute_statistics, summarize_meta, ComponentStatistics, MetaSummary,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "inspect_point_cloud")]
+/// Prints what an octree or S2 point cloud contains.
+struct CommandlineArguments {
+    /// Location of the point cloud, e.g. a directory.
+    location: String,
+
+    /// Only print what is in the meta, without reading any points.
+    #[structopt(long)]
+    meta_only: bool,
+
+    /// The number of bins of the attribute histograms. 0 skips the histograms, which saves a
+    /// second pass over all points.
+    #[structopt(long, default_value = "10")]
+    num_histogram_bins: usize,
+
+    /// The number of threads used to read points.
+    #[structopt(long, default_value = "4")]
+    num_threads: usize,
+
+    /// Print JSON instead of text.
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Report {
+    #[serde(flatten)]
+    meta: MetaSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribute_statistics: Option<BTreeMap<String, Vec<ComponentStatistics>>>,
+}
+
+/// Returns the attributes of the meta that were written. Octrees imply their attributes, so not
+/// all of them have to exist. Only the first node is probed; if another node lacks one of its
+/// attributes, computing the statistics fails.
+fn expected_attributes<C: PointCloud>(point_cloud: &C) -> Vec<String> {
+    let first_node = point_cloud
+        .nodes_in_location(&PointLocation::AllPoints)
+        .into_iter()
+        .map(|(node_id, _)| node_id)
+        .next();
+    let mut attributes: Vec<String> = point_cloud
+        .attribute_data_types()
+        .keys()
+        .filter(|attribute| match first_node {
+            Some(node_id) => point_cloud
+                .points_in_node(&[attribute.as_str()], node_id, 1)
+                .is_ok(),
+            None => true,
+        })
+        .cloned()
+        .collect();
+    attributes.sort();
+    attributes
+}
+
+fn attribute_statistics<C: PointCloud>(
+    point_cloud: C,
+    args: &CommandlineArguments,
+) -> Result<BTreeMap<String, Vec<ComponentStatistics>>> {
+    let attributes = expected_attributes(&point_cloud);
+    let attributes: Vec<&str> = attributes.iter().map(String::as_str).collect();
+    compute_attribute_statistics(
+        std::slice::from_ref(&point_cloud),
+        &attributes,
+        args.num_histogram_bins,
+        args.num_threads,
+    )
+}
+
+fn print_report(report: &Report) {
+    let meta = &report.meta;
+    println!("Format: {} (version {})", meta.format, meta.version);
+    println!(
+        "Bounding box: min {:?}, max {:?}",
+        meta.bounding_box.min().coords.as_slice(),
+        meta.bounding_box.max().coords.as_slice()
+    );
+    if let Some(resolution) = meta.resolution {
+        println!("Resolution: {}", resolution);
+    }
+    println!("Attributes:");
+    for (name, data_type) in &meta.attributes {
+        println!("  {}: {}", name, data_type);
+    }
+    println!("Nodes: {}", meta.num_nodes);
+    println!("Points: {}", meta.num_points);
+    println!("Points per level:");
+    for (level, num_points) in &meta.num_points_per_level {
+        println!("  {:>2}: {}", level, num_points);
+    }
+    println!("Nodes per position encoding:");
+    for (encoding, num_nodes) in &meta.num_nodes_per_encoding {
+        println!("  {}: {}", encoding, num_nodes);
+    }
+    if let Some(statistics) = &report.attribute_statistics {
+        println!("Attribute statistics:");
+        for (name, components) in statistics {
+            for (i, stats) in components.iter().enumerate() {
+                println!(
+                    "  {}[{}]: min {}, max {}, mean {:.3}",
+                    name, i, stats.min, stats.max, stats.mean
+                );
+                if !stats.histogram.is_empty() {
+                    println!("    histogram: {:?}", stats.histogram);
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = CommandlineArguments::from_args();
+    let data_provider = DataProviderFactory::new()
+        .generate_data_provider(&args.location)
+        .expect("Could not open point cloud.");
+    let meta_proto = data_provider
+        .meta_proto()
+        .expect("Could not read the meta of the point cloud.");
+    let meta = summarize_meta(&meta_proto).expect("Could not understand the meta.");
+
+    let attribute_statistics = if args.meta_only {
+        None
+    } else {
+        let statistics = if meta.format == "s2" {
+            S2Cells::from_data_provider(data_provider)
+                .and_then(|s2_cells| attribute_statistics(s2_cells, &args))
+        } else {
+            Octree::from_data_provider(data_provider)
+                .and_then(|octree| attribute_statistics(octree, &args))
+        };
+        Some(statistics.expect("Could not compute attribute statistics."))
+    };
+    let report = Report {
+        meta,
+        attribute_statistics,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print_report(&report);
+    }
+}
@@ -5,6 +5,10 @@ use std::io::Read;
 
 pub trait DataProvider: Send + Sync {
     fn meta_proto(&self) -> Result<proto::Meta>;
+    /// Fails with 'BuildInProgress' if the point cloud is not completely written yet.
+    fn check_complete(&self) -> Result<()> {
+        Ok(())
+    }
     fn data(
         &self,
         node_id: &str,
@@ -41,9 +41,9 @@ impl DataProviderFactory {
 
         // If no data provider was generated, create it from disk
         if Path::new(data_provider_argument).exists() {
-            Ok(Box::new(OnDiskDataProvider {
-                directory: data_provider_argument.into(),
-            }))
+            Ok(Box::new(OnDiskDataProvider::new(
+                data_provider_argument.into(),
+            )))
         } else {
             Err(format!(
                 "Directory '{}' for creating an OnDiskDataProvider doesn't exist.",
@@ -2,6 +2,7 @@ use crate::attribute_extension;
 use crate::data_provider::DataProvider;
 use crate::errors::*;
 use crate::proto;
+use crate::read_write::is_build_in_progress;
 use crate::META_FILENAME;
 use std::collections::HashMap;
 use std::fs::{self, File};
@@ -13,6 +14,13 @@ pub struct OnDiskDataProvider {
 }
 
 impl OnDiskDataProvider {
+    /// Reads the point cloud in 'directory'. If it is a symlink to a published version, that
+    /// version keeps being read even if a newer one is published meanwhile.
+    pub fn new(directory: PathBuf) -> Self {
+        let directory = fs::canonicalize(&directory).unwrap_or(directory);
+        OnDiskDataProvider { directory }
+    }
+
     /// Returns the path on disk where the data for this node is saved.
     pub fn stem(&self, node_id: &str) -> PathBuf {
         self.directory.join(node_id)
@@ -34,6 +42,13 @@ impl OnDiskDataProvider {
 }
 
 impl DataProvider for OnDiskDataProvider {
+    fn check_complete(&self) -> Result<()> {
+        if is_build_in_progress(&self.directory) {
+            return Err(ErrorKind::BuildInProgress(self.directory.display().to_string()).into());
+        }
+        Ok(())
+    }
+
     fn meta_proto(&self) -> Result<proto::Meta> {
         // We used to use JSON earlier.
         if self.directory.join("meta.json").exists() {
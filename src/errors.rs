@@ -35,6 +35,12 @@ error_chain! {
             the currently created version is supported.", version, crate::CURRENT_VERSION)
         }
 
+        BuildInProgress(directory: String) {
+            description("The point cloud is still being built")
+            display("The point cloud in {} is incomplete, its build is in progress or was \
+            interrupted.", directory)
+        }
+
         NodeNotFound {
             description("The node does not exist.")
         }
@@ -48,5 +54,9 @@ error_chain! {
             display("{}", msg)
         }
 
+        Cancelled {
+            description("The query was cancelled.")
+        }
+
     }
 }
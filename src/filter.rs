@@ -0,0 +1,749 @@
+//! Expressions that select points by their attribute values, e.g.
+//! "intensity > 40 && return_number == 1", "classification in {2, 6, 9}" or "color[2] >= 128".
+//! Comparisons can be combined with "&&", "||", "!" and parentheses, and closed intervals are
+//! written as "intensity in [2, 51]".
+
+use crate::attributes::{AttributeRanges, Components};
+use crate::errors::*;
+use crate::math::{ClosedInterval, Relation};
+use crate::{AttributeData, PointsBatch};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How the value of an attribute is compared to a constant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    #[allow(clippy::float_cmp)]
+    fn holds(self, value: f64, constant: f64) -> bool {
+        match self {
+            Comparison::Less => value < constant,
+            Comparison::LessOrEqual => value <= constant,
+            Comparison::Greater => value > constant,
+            Comparison::GreaterOrEqual => value >= constant,
+            Comparison::Equal => value == constant,
+            Comparison::NotEqual => value != constant,
+        }
+    }
+
+    /// Returns whether all, some or none of the values in 'range' compare true to 'constant'.
+    #[allow(clippy::float_cmp)]
+    fn relation(self, range: ClosedInterval<f64>, constant: f64) -> Relation {
+        let (lower, upper) = (range.lower_bound(), range.upper_bound());
+        let only_constant = lower == constant && upper == constant;
+        let (all, none) = match self {
+            Comparison::Less => (upper < constant, lower >= constant),
+            Comparison::LessOrEqual => (upper <= constant, lower > constant),
+            Comparison::Greater => (lower > constant, upper <= constant),
+            Comparison::GreaterOrEqual => (lower >= constant, upper < constant),
+            Comparison::Equal => (only_constant, !range.contains(constant)),
+            Comparison::NotEqual => (!range.contains(constant), only_constant),
+        };
+        if all {
+            Relation::In
+        } else if none {
+            Relation::Out
+        } else {
+            Relation::Cross
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+        }
+    }
+}
+
+/// A scalar attribute, or one component of a vector attribute such as "color[2]".
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Operand {
+    pub attribute: String,
+    pub component: Option<usize>,
+}
+
+impl Operand {
+    pub fn new(attribute: impl Into<String>) -> Self {
+        Operand {
+            attribute: attribute.into(),
+            component: None,
+        }
+    }
+
+    pub fn with_component(attribute: impl Into<String>, component: usize) -> Self {
+        Operand {
+            attribute: attribute.into(),
+            component: Some(component),
+        }
+    }
+
+    /// Returns the values of the operand for all points of 'batch'.
+    fn values(&self, batch: &PointsBatch) -> Result<Vec<f64>> {
+        let data = batch.attributes.get(&self.attribute).ok_or_else(|| {
+            ErrorKind::InvalidInput(format!("Filter attribute '{}' not found.", self.attribute))
+        })?;
+        let index = match self.component {
+            Some(index) if index < data.dim() => index,
+            None if data.dim() == 1 => 0,
+            _ => {
+                return Err(ErrorKind::InvalidInput(format!(
+                    "Cannot filter by '{}', the attribute has {} components.",
+                    self,
+                    data.dim()
+                ))
+                .into())
+            }
+        };
+        macro_rules! rhs {
+            ($dtype:ident, $data:ident, $index:expr) => {
+                $data
+                    .iter()
+                    .map(|value| Components::component(value, $index))
+                    .collect::<Vec<f64>>()
+            };
+        }
+        Ok(match_attr_data!(data, rhs, index))
+    }
+
+    /// Returns the range of the values, which is only known for scalar attributes.
+    fn range(&self, ranges: &AttributeRanges) -> Option<ClosedInterval<f64>> {
+        match self.component {
+            None | Some(0) => ranges.get(&self.attribute),
+            Some(_) => None,
+        }
+    }
+
+    /// Returns whether some values can be NaN, which are not part of the range.
+    fn contains_nan(&self, ranges: &AttributeRanges) -> bool {
+        ranges.contains_nan(&self.attribute)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.component {
+            Some(component) => write!(f, "{}[{}]", self.attribute, component),
+            None => write!(f, "{}", self.attribute),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FilterExpression {
+    /// E.g. "intensity > 40".
+    Compare(Operand, Comparison, f64),
+    /// E.g. "classification in {2, 6, 9}".
+    In(Operand, Vec<f64>),
+    /// E.g. "intensity in [2, 51]", both bounds are included.
+    Interval(Operand, ClosedInterval<f64>),
+    And(Vec<FilterExpression>),
+    Or(Vec<FilterExpression>),
+    Not(Box<FilterExpression>),
+}
+
+impl FilterExpression {
+    /// Returns the attributes that need to be read to evaluate the expression.
+    pub fn attributes(&self) -> Vec<&str> {
+        let mut attributes = Vec::new();
+        self.collect_attributes(&mut attributes);
+        attributes.sort();
+        attributes.dedup();
+        attributes
+    }
+
+    fn collect_attributes<'a>(&'a self, attributes: &mut Vec<&'a str>) {
+        match self {
+            FilterExpression::Compare(operand, _, _)
+            | FilterExpression::In(operand, _)
+            | FilterExpression::Interval(operand, _) => attributes.push(&operand.attribute),
+            FilterExpression::And(terms) | FilterExpression::Or(terms) => {
+                for term in terms {
+                    term.collect_attributes(attributes);
+                }
+            }
+            FilterExpression::Not(term) => term.collect_attributes(attributes),
+        }
+    }
+
+    /// Returns for every point of 'batch' whether it matches the expression.
+    pub fn evaluate(&self, batch: &PointsBatch) -> Result<Vec<bool>> {
+        let matches = |operand: &Operand, f: &dyn Fn(f64) -> bool| -> Result<Vec<bool>> {
+            Ok(operand.values(batch)?.into_iter().map(f).collect())
+        };
+        match self {
+            FilterExpression::Compare(operand, comparison, constant) => {
+                matches(operand, &|v| comparison.holds(v, *constant))
+            }
+            FilterExpression::In(operand, values) => matches(operand, &|v| values.contains(&v)),
+            FilterExpression::Interval(operand, interval) => {
+                matches(operand, &|v| interval.contains(v))
+            }
+            FilterExpression::And(terms) => {
+                let mut result = vec![true; batch.position.len()];
+                for term in terms {
+                    for (r, m) in result.iter_mut().zip(term.evaluate(batch)?) {
+                        *r &= m;
+                    }
+                }
+                Ok(result)
+            }
+            FilterExpression::Or(terms) => {
+                let mut result = vec![false; batch.position.len()];
+                for term in terms {
+                    for (r, m) in result.iter_mut().zip(term.evaluate(batch)?) {
+                        *r |= m;
+                    }
+                }
+                Ok(result)
+            }
+            FilterExpression::Not(term) => {
+                Ok(term.evaluate(batch)?.into_iter().map(|m| !m).collect())
+            }
+        }
+    }
+
+    /// Returns whether all, some or none of the points whose attributes are within 'ranges' can
+    /// match the expression. Attributes without a known range could have any value.
+    #[allow(clippy::float_cmp)]
+    pub fn relation_to(&self, ranges: &AttributeRanges) -> Relation {
+        // NaN values are not part of the ranges. They only match "!=", so a node with NaN values
+        // is only In or Out if its other values agree.
+        let relation =
+            |operand: &Operand, matches_nan: bool, f: &dyn Fn(ClosedInterval<f64>) -> Relation| {
+                let relation = operand.range(ranges).map_or(Relation::Cross, f);
+                let nan_relation = if matches_nan {
+                    Relation::In
+                } else {
+                    Relation::Out
+                };
+                if operand.contains_nan(ranges) && relation != nan_relation {
+                    Relation::Cross
+                } else {
+                    relation
+                }
+            };
+        match self {
+            FilterExpression::Compare(operand, comparison, constant) => {
+                let matches_nan = *comparison == Comparison::NotEqual;
+                relation(operand, matches_nan, &|range| {
+                    comparison.relation(range, *constant)
+                })
+            }
+            FilterExpression::In(operand, values) => relation(operand, false, &|range| {
+                if !values.iter().any(|v| range.contains(*v)) {
+                    Relation::Out
+                } else if range.lower_bound() == range.upper_bound() {
+                    Relation::In
+                } else {
+                    Relation::Cross
+                }
+            }),
+            FilterExpression::Interval(operand, interval) => {
+                relation(operand, false, &|range| range.relation_to(*interval))
+            }
+            FilterExpression::And(terms) => terms
+                .iter()
+                .map(|term| term.relation_to(ranges))
+                .max()
+                .unwrap_or(Relation::In),
+            FilterExpression::Or(terms) => terms
+                .iter()
+                .map(|term| term.relation_to(ranges))
+                .min()
+                .unwrap_or(Relation::Out),
+            FilterExpression::Not(term) => match term.relation_to(ranges) {
+                Relation::In => Relation::Out,
+                Relation::Cross => Relation::Cross,
+                Relation::Out => Relation::In,
+            },
+        }
+    }
+}
+
+fn write_terms(f: &mut fmt::Formatter, terms: &[FilterExpression], separator: &str) -> fmt::Result {
+    for (i, term) in terms.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        match term {
+            FilterExpression::And(_) | FilterExpression::Or(_) => write!(f, "({})", term)?,
+            _ => write!(f, "{}", term)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for FilterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterExpression::Compare(operand, comparison, constant) => {
+                write!(f, "{} {} {}", operand, comparison.symbol(), constant)
+            }
+            FilterExpression::In(operand, values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "{} in {{{}}}", operand, values.join(", "))
+            }
+            FilterExpression::Interval(operand, interval) => write!(
+                f,
+                "{} in [{}, {}]",
+                operand,
+                interval.lower_bound(),
+                interval.upper_bound()
+            ),
+            FilterExpression::And(terms) => write_terms(f, terms, " && "),
+            FilterExpression::Or(terms) => write_terms(f, terms, " || "),
+            FilterExpression::Not(term) => write!(f, "!({})", term),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseFilterError(String);
+
+impl std::error::Error for ParseFilterError {}
+
+impl fmt::Display for ParseFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(f64),
+    Comparison(Comparison),
+    And,
+    Or,
+    Not,
+    Comma,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+}
+
+fn tokenize(s: &str) -> std::result::Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (token, len) = match (chars[i], chars.get(i + 1)) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('<', Some('=')) => (Token::Comparison(Comparison::LessOrEqual), 2),
+            ('>', Some('=')) => (Token::Comparison(Comparison::GreaterOrEqual), 2),
+            ('=', Some('=')) => (Token::Comparison(Comparison::Equal), 2),
+            ('!', Some('=')) => (Token::Comparison(Comparison::NotEqual), 2),
+            ('<', _) => (Token::Comparison(Comparison::Less), 1),
+            ('>', _) => (Token::Comparison(Comparison::Greater), 1),
+            ('!', _) => (Token::Not, 1),
+            (',', _) => (Token::Comma, 1),
+            ('(', _) => (Token::OpenParen, 1),
+            (')', _) => (Token::CloseParen, 1),
+            ('{', _) => (Token::OpenBrace, 1),
+            ('}', _) => (Token::CloseBrace, 1),
+            ('[', _) => (Token::OpenBracket, 1),
+            (']', _) => (Token::CloseBracket, 1),
+            (c, _) if c.is_ascii_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                    .count();
+                let identifier = chars[i..i + len].iter().collect();
+                (Token::Identifier(identifier), len)
+            }
+            (c, _) if c.is_ascii_digit() || c == '.' || c == '-' || c == '+' => {
+                let mut len = 1;
+                while let Some(c) = chars.get(i + len) {
+                    let after_exponent = chars[i + len - 1] == 'e' || chars[i + len - 1] == 'E';
+                    if c.is_ascii_digit()
+                        || *c == '.'
+                        || *c == 'e'
+                        || *c == 'E'
+                        || ((*c == '-' || *c == '+') && after_exponent)
+                    {
+                        len += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let number: String = chars[i..i + len].iter().collect();
+                let value = number
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", number))?;
+                (Token::Number(value), len)
+            }
+            (c, _) => return Err(format!("unexpected character '{}'", c)),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, "&&" binds stronger than "||".
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> std::result::Result<(), String> {
+        match self.next_token() {
+            Some(ref token) if *token == expected => Ok(()),
+            token => Err(format!("expected {:?}, found {:?}", expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> std::result::Result<FilterExpression, String> {
+        let mut terms = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            FilterExpression::Or(terms)
+        })
+    }
+
+    fn and(&mut self) -> std::result::Result<FilterExpression, String> {
+        let mut terms = vec![self.not()?];
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            terms.push(self.not()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            FilterExpression::And(terms)
+        })
+    }
+
+    fn not(&mut self) -> std::result::Result<FilterExpression, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(FilterExpression::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> std::result::Result<FilterExpression, String> {
+        match self.next_token() {
+            Some(Token::OpenParen) => {
+                let expression = self.or()?;
+                self.expect(Token::CloseParen)?;
+                Ok(expression)
+            }
+            Some(Token::Identifier(attribute)) => {
+                let mut operand = Operand::new(attribute);
+                if self.peek() == Some(&Token::OpenBracket) {
+                    self.position += 1;
+                    operand.component = Some(self.index()?);
+                    self.expect(Token::CloseBracket)?;
+                }
+                match self.next_token() {
+                    Some(Token::Comparison(comparison)) => Ok(FilterExpression::Compare(
+                        operand,
+                        comparison,
+                        self.number()?,
+                    )),
+                    Some(Token::Identifier(ref keyword)) if keyword == "in" => {
+                        self.membership(operand)
+                    }
+                    token => Err(format!(
+                        "expected a comparison or 'in' after '{}', found {:?}",
+                        operand, token
+                    )),
+                }
+            }
+            token => Err(format!("expected an attribute or '(', found {:?}", token)),
+        }
+    }
+
+    fn membership(&mut self, operand: Operand) -> std::result::Result<FilterExpression, String> {
+        match self.next_token() {
+            Some(Token::OpenBrace) => {
+                let mut values = Vec::new();
+                if self.peek() != Some(&Token::CloseBrace) {
+                    values.push(self.number()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.position += 1;
+                        values.push(self.number()?);
+                    }
+                }
+                self.expect(Token::CloseBrace)?;
+                Ok(FilterExpression::In(operand, values))
+            }
+            Some(Token::OpenBracket) => {
+                let lower_bound = self.number()?;
+                self.expect(Token::Comma)?;
+                let upper_bound = self.number()?;
+                self.expect(Token::CloseBracket)?;
+                if lower_bound > upper_bound {
+                    return Err(format!(
+                        "invalid interval [{}, {}]",
+                        lower_bound, upper_bound
+                    ));
+                }
+                Ok(FilterExpression::Interval(
+                    operand,
+                    ClosedInterval::new(lower_bound, upper_bound),
+                ))
+            }
+            token => Err(format!("expected '{{' or '[', found {:?}", token)),
+        }
+    }
+
+    fn number(&mut self) -> std::result::Result<f64, String> {
+        match self.next_token() {
+            Some(Token::Number(value)) => Ok(value),
+            token => Err(format!("expected a number, found {:?}", token)),
+        }
+    }
+
+    fn index(&mut self) -> std::result::Result<usize, String> {
+        match self.next_token() {
+            Some(Token::Number(value)) if value >= 0. && value.fract() == 0. => Ok(value as usize),
+            token => Err(format!("expected a component index, found {:?}", token)),
+        }
+    }
+}
+
+impl FromStr for FilterExpression {
+    type Err = ParseFilterError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = |reason| ParseFilterError(format!("Invalid filter '{}': {}.", s, reason));
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(invalid)?,
+            position: 0,
+        };
+        let expression = parser.or().map_err(invalid)?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("unexpected {:?}", token)));
+        }
+        Ok(expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Point3, Vector3};
+
+    fn test_batch() -> PointsBatch {
+        let num_points = 6;
+        let mut batch = PointsBatch {
+            position: vec![Point3::origin(); num_points],
+            attributes: Default::default(),
+        };
+        batch.attributes.insert(
+            "classification".to_string(),
+            AttributeData::U8(vec![1, 2, 6, 9, 2, 3]),
+        );
+        batch.attributes.insert(
+            "intensity".to_string(),
+            AttributeData::F32(vec![10., 50., 45., 30., 41., 100.]),
+        );
+        batch.attributes.insert(
+            "return_number".to_string(),
+            AttributeData::U8(vec![1, 1, 2, 1, 1, 2]),
+        );
+        batch.attributes.insert(
+            "color".to_string(),
+            AttributeData::U8Vec3(
+                (0..num_points as u8)
+                    .map(|i| Vector3::new(0, 0, i * 50))
+                    .collect(),
+            ),
+        );
+        batch
+    }
+
+    fn evaluate(expression: &str) -> Vec<bool> {
+        expression
+            .parse::<FilterExpression>()
+            .unwrap()
+            .evaluate(&test_batch())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let expression: FilterExpression =
+            "intensity > 40 && (classification in {2, 6} || !(color[2] <= 1e2))"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            expression,
+            FilterExpression::And(vec![
+                FilterExpression::Compare(Operand::new("intensity"), Comparison::Greater, 40.),
+                FilterExpression::Or(vec![
+                    FilterExpression::In(Operand::new("classification"), vec![2., 6.]),
+                    FilterExpression::Not(Box::new(FilterExpression::Compare(
+                        Operand::with_component("color", 2),
+                        Comparison::LessOrEqual,
+                        100.
+                    ))),
+                ]),
+            ])
+        );
+        assert_eq!(
+            "intensity in [-2.5, 51]"
+                .parse::<FilterExpression>()
+                .unwrap(),
+            FilterExpression::Interval(Operand::new("intensity"), ClosedInterval::new(-2.5, 51.))
+        );
+        for invalid in &[
+            "",
+            "intensity",
+            "intensity >",
+            "intensity > 40 &&",
+            "(intensity > 40",
+            "intensity in {2, }",
+            "intensity in [5, 1]",
+            "color[1.5] > 0",
+            "intensity > 40 classification",
+            "intensity = 40",
+        ] {
+            assert!(invalid.parse::<FilterExpression>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for text in &[
+            "intensity > 40 && return_number == 1",
+            "classification in {2, 6, 9} || color[2] != 0",
+            "!(intensity in [2, 51]) && (classification < 3 || classification >= 9)",
+        ] {
+            let expression: FilterExpression = text.parse().unwrap();
+            assert_eq!(&expression.to_string(), text);
+            assert_eq!(
+                expression.to_string().parse::<FilterExpression>().unwrap(),
+                expression
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(
+            evaluate("classification in {2, 6, 9}"),
+            vec![false, true, true, true, true, false]
+        );
+        assert_eq!(
+            evaluate("intensity > 40 && return_number == 1"),
+            vec![false, true, false, false, true, false]
+        );
+        assert_eq!(
+            evaluate("color[2] >= 100 || intensity in [10, 10]"),
+            vec![true, false, true, true, true, true]
+        );
+        assert_eq!(
+            evaluate("!(classification != 2)"),
+            vec![false, true, false, false, true, false]
+        );
+        let batch = test_batch();
+        for invalid in &["color > 0", "color[3] > 0", "unknown > 0"] {
+            let expression: FilterExpression = invalid.parse().unwrap();
+            match expression.evaluate(&batch) {
+                Err(Error(ErrorKind::InvalidInput(_), _)) => (),
+                _ => panic!("Expected an error for '{}'.", invalid),
+            }
+        }
+    }
+
+    #[test]
+    fn test_relation_to() {
+        let mut ranges = AttributeRanges::new();
+        ranges.update(&test_batch());
+        let relation = |expression: &str| {
+            expression
+                .parse::<FilterExpression>()
+                .unwrap()
+                .relation_to(&ranges)
+        };
+        assert_eq!(relation("intensity >= 10"), Relation::In);
+        assert_eq!(relation("intensity > 100"), Relation::Out);
+        assert_eq!(relation("intensity > 40"), Relation::Cross);
+        assert_eq!(relation("classification in {0, 10}"), Relation::Out);
+        assert_eq!(relation("classification in {2}"), Relation::Cross);
+        assert_eq!(relation("!(intensity < 10)"), Relation::In);
+        assert_eq!(
+            relation("intensity > 100 || return_number <= 2"),
+            Relation::In
+        );
+        assert_eq!(
+            relation("intensity > 100 && return_number <= 2"),
+            Relation::Out
+        );
+        // Ranges are only known for scalar attributes.
+        assert_eq!(relation("color[2] > 1000"), Relation::Cross);
+    }
+
+    #[test]
+    fn test_relation_to_with_nan() {
+        let mut batch = test_batch();
+        batch.attributes.insert(
+            "intensity".to_string(),
+            AttributeData::F32(vec![5., std::f32::NAN, 5., 5., 5., 5.]),
+        );
+        let mut ranges = AttributeRanges::new();
+        ranges.update(&batch);
+        assert_eq!(ranges.get("intensity"), Some(ClosedInterval::new(5., 5.)));
+        assert!(ranges.contains_nan("intensity"));
+        assert!(!ranges.contains_nan("classification"));
+        assert_eq!(AttributeRanges::from_proto(&ranges.to_proto()), ranges);
+
+        let relation = |expression: &str| {
+            expression
+                .parse::<FilterExpression>()
+                .unwrap()
+                .relation_to(&ranges)
+        };
+        assert_eq!(relation("intensity in [0, 10]"), Relation::Cross);
+        assert_eq!(relation("intensity in [6, 10]"), Relation::Out);
+        assert_eq!(relation("intensity == 5"), Relation::Cross);
+        assert_eq!(relation("intensity > 6"), Relation::Out);
+        // NaN values are the only ones that match.
+        assert_eq!(relation("intensity != 5"), Relation::Cross);
+        assert_eq!(relation("!(intensity < 10)"), Relation::Cross);
+        assert_eq!(relation("!(intensity in {5})"), Relation::Cross);
+        assert_eq!(relation("intensity != 6"), Relation::In);
+        assert_eq!(relation("!(intensity > 6)"), Relation::In);
+    }
+}
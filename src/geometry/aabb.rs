@@ -1,7 +1,7 @@
 //! Axis-aligned box and cube.
 
-use crate::math::base::{HasAabbIntersector, PointCulling};
-use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Intersector};
+use crate::math::base::{HasAabbIntersector, IntersectAabb, PointCulling};
+use crate::math::sat::{ConvexPolyhedron, Intersector, Relation};
 use crate::proto;
 use arrayvec::ArrayVec;
 use nalgebra::{Isometry3, Point3, RealField, Vector3};
@@ -51,6 +51,12 @@ impl<S: RealField> Aabb<S> {
         nalgebra::center(&self.mins, &self.maxs)
     }
 
+    /// The distance from 'p' to the closest point in the box, which is zero for points inside.
+    pub fn distance(&self, p: &Point3<S>) -> S {
+        let closest = nalgebra::sup(&self.mins, &nalgebra::inf(&self.maxs, p));
+        nalgebra::distance(p, &closest)
+    }
+
     pub fn diag(&self) -> Vector3<S> {
         self.maxs - self.mins
     }
@@ -98,16 +104,30 @@ impl<S: RealField> PointCulling<S> for Aabb<S> {
     }
 }
 
-impl<'a, S: RealField> HasAabbIntersector<'a, S> for Aabb<S> {
-    type Intersector = CachedAxesIntersector<S>;
-    fn aabb_intersector(&'a self) -> CachedAxesIntersector<S> {
-        CachedAxesIntersector {
-            axes: vec![Vector3::x_axis(), Vector3::y_axis(), Vector3::z_axis()],
-            corners: self.compute_corners(),
+/// Consistent with `contains`, AABBs touching the max faces are not reported to be inside.
+impl<S: RealField> IntersectAabb<S> for Aabb<S> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        if !(nalgebra::partial_le(aabb.min(), &self.maxs)
+            && nalgebra::partial_le(&self.mins, aabb.max()))
+        {
+            Relation::Out
+        } else if nalgebra::partial_le(&self.mins, aabb.min())
+            && nalgebra::partial_lt(aabb.max(), &self.maxs)
+        {
+            Relation::In
+        } else {
+            Relation::Cross
         }
     }
 }
 
+impl<'a, S: RealField> HasAabbIntersector<'a, S> for Aabb<S> {
+    type Intersector = Aabb<S>;
+    fn aabb_intersector(&'a self) -> Aabb<S> {
+        self.clone()
+    }
+}
+
 impl<S: RealField> ConvexPolyhedron<S> for Aabb<S> {
     fn compute_corners(&self) -> [Point3<S>; 8] {
         [
@@ -189,3 +209,24 @@ impl Cube {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_intersects_aabb() {
+        let query = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0));
+        let intersector = query.aabb_intersector();
+        let inside = Aabb::new(Point3::new(0.0, 0.5, 0.5), Point3::new(1.0, 1.5, 1.5));
+        assert_eq!(intersector.intersect_aabb(&inside), Relation::In);
+        // Points on the max faces are not contained, so a box touching them is not inside.
+        let flush_with_max = Aabb::new(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 1.5, 1.5));
+        assert!(!query.contains(&Point3::new(2.0, 1.2, 1.2)));
+        assert_eq!(intersector.intersect_aabb(&flush_with_max), Relation::Cross);
+        let crossing = Aabb::new(Point3::new(1.0, 1.0, 1.0), Point3::new(3.0, 1.5, 1.5));
+        assert_eq!(intersector.intersect_aabb(&crossing), Relation::Cross);
+        let outside = Aabb::new(Point3::new(2.5, 0.0, 0.0), Point3::new(3.0, 1.0, 1.0));
+        assert_eq!(intersector.intersect_aabb(&outside), Relation::Out);
+    }
+}
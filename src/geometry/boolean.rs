@@ -0,0 +1,228 @@
+//! Boolean combinations of other geometries, e.g. "inside this frustum, but not inside that box".
+
+use super::aabb::Aabb;
+use crate::math::sat::Relation;
+use crate::math::{HasAabbIntersector, IntersectAabb, PointCulling};
+use nalgebra::{Point3, RealField};
+use serde::{Deserialize, Serialize};
+
+/// Contains the points contained in any of its parts. Without parts, it contains nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Union<T> {
+    parts: Vec<T>,
+}
+
+/// Contains the points contained in all of its parts. Without parts, it contains everything.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Intersection<T> {
+    parts: Vec<T>,
+}
+
+/// Contains the points contained in 'minuend', but not in 'subtrahend'.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Difference<T> {
+    minuend: Box<T>,
+    subtrahend: Box<T>,
+}
+
+impl<T> Union<T> {
+    pub fn new(parts: Vec<T>) -> Self {
+        Union { parts }
+    }
+
+    pub fn parts(&self) -> &[T] {
+        &self.parts
+    }
+}
+
+impl<T> Intersection<T> {
+    pub fn new(parts: Vec<T>) -> Self {
+        Intersection { parts }
+    }
+
+    pub fn parts(&self) -> &[T] {
+        &self.parts
+    }
+}
+
+impl<T> Difference<T> {
+    pub fn new(minuend: T, subtrahend: T) -> Self {
+        Difference {
+            minuend: Box::new(minuend),
+            subtrahend: Box::new(subtrahend),
+        }
+    }
+
+    pub fn minuend(&self) -> &T {
+        &self.minuend
+    }
+
+    pub fn subtrahend(&self) -> &T {
+        &self.subtrahend
+    }
+}
+
+/// The relation of an object to a union, given its relations to the parts of the union.
+pub fn union_relation(relations: impl IntoIterator<Item = Relation>) -> Relation {
+    relations.into_iter().min().unwrap_or(Relation::Out)
+}
+
+/// The relation of an object to an intersection, given its relations to the parts of the
+/// intersection.
+pub fn intersection_relation(relations: impl IntoIterator<Item = Relation>) -> Relation {
+    relations.into_iter().max().unwrap_or(Relation::In)
+}
+
+/// The relation of an object to a difference, given its relations to the minuend and subtrahend.
+pub fn difference_relation(minuend: Relation, subtrahend: Relation) -> Relation {
+    match (minuend, subtrahend) {
+        (Relation::Out, _) | (_, Relation::In) => Relation::Out,
+        (Relation::In, Relation::Out) => Relation::In,
+        _ => Relation::Cross,
+    }
+}
+
+impl<S: RealField, T: PointCulling<S>> PointCulling<S> for Union<T> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        self.parts.iter().any(|part| part.contains(p))
+    }
+}
+
+impl<S: RealField, T: PointCulling<S>> PointCulling<S> for Intersection<T> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        self.parts.iter().all(|part| part.contains(p))
+    }
+}
+
+impl<S: RealField, T: PointCulling<S>> PointCulling<S> for Difference<T> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        self.minuend.contains(p) && !self.subtrahend.contains(p)
+    }
+}
+
+pub struct UnionIntersector<I> {
+    parts: Vec<I>,
+}
+
+pub struct IntersectionIntersector<I> {
+    parts: Vec<I>,
+}
+
+pub struct DifferenceIntersector<I> {
+    minuend: I,
+    subtrahend: I,
+}
+
+impl<S: RealField, I: IntersectAabb<S>> IntersectAabb<S> for UnionIntersector<I> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        union_relation(self.parts.iter().map(|part| part.intersect_aabb(aabb)))
+    }
+}
+
+impl<S: RealField, I: IntersectAabb<S>> IntersectAabb<S> for IntersectionIntersector<I> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        intersection_relation(self.parts.iter().map(|part| part.intersect_aabb(aabb)))
+    }
+}
+
+impl<S: RealField, I: IntersectAabb<S>> IntersectAabb<S> for DifferenceIntersector<I> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        let minuend = self.minuend.intersect_aabb(aabb);
+        if minuend == Relation::Out {
+            return Relation::Out;
+        }
+        difference_relation(minuend, self.subtrahend.intersect_aabb(aabb))
+    }
+}
+
+impl<'a, S: RealField, T: HasAabbIntersector<'a, S>> HasAabbIntersector<'a, S> for Union<T> {
+    type Intersector = UnionIntersector<T::Intersector>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        UnionIntersector {
+            parts: self
+                .parts
+                .iter()
+                .map(|part| part.aabb_intersector())
+                .collect(),
+        }
+    }
+}
+
+impl<'a, S: RealField, T: HasAabbIntersector<'a, S>> HasAabbIntersector<'a, S> for Intersection<T> {
+    type Intersector = IntersectionIntersector<T::Intersector>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        IntersectionIntersector {
+            parts: self
+                .parts
+                .iter()
+                .map(|part| part.aabb_intersector())
+                .collect(),
+        }
+    }
+}
+
+impl<'a, S: RealField, T: HasAabbIntersector<'a, S>> HasAabbIntersector<'a, S> for Difference<T> {
+    type Intersector = DifferenceIntersector<T::Intersector>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        DifferenceIntersector {
+            minuend: self.minuend.aabb_intersector(),
+            subtrahend: self.subtrahend.aabb_intersector(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sphere;
+
+    fn unit_cube_at(x: f64) -> Aabb<f64> {
+        Aabb::new(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_boolean_contains() {
+        let union = Union::new(vec![unit_cube_at(0.0), unit_cube_at(2.0)]);
+        assert!(union.contains(&Point3::new(0.5, 0.5, 0.5)));
+        assert!(union.contains(&Point3::new(2.5, 0.5, 0.5)));
+        assert!(!union.contains(&Point3::new(1.5, 0.5, 0.5)));
+
+        let intersection = Intersection::new(vec![
+            Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0)),
+            unit_cube_at(1.0),
+        ]);
+        assert!(intersection.contains(&Point3::new(1.5, 0.5, 0.5)));
+        assert!(!intersection.contains(&Point3::new(0.5, 0.5, 0.5)));
+
+        let difference = Difference::new(
+            Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0),
+            Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0),
+        );
+        assert!(difference.contains(&Point3::new(1.5, 0.0, 0.0)));
+        assert!(!difference.contains(&Point3::new(0.5, 0.0, 0.0)));
+        assert!(!difference.contains(&Point3::new(2.5, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_boolean_intersects_aabb() {
+        let union = Union::new(vec![unit_cube_at(0.0), unit_cube_at(2.0)]);
+        let intersector = union.aabb_intersector();
+        let inside = Aabb::new(Point3::new(2.2, 0.2, 0.2), Point3::new(2.8, 0.8, 0.8));
+        assert_eq!(intersector.intersect_aabb(&inside), Relation::In);
+        let between = Aabb::new(Point3::new(1.2, 0.2, 0.2), Point3::new(1.8, 0.8, 0.8));
+        assert_eq!(intersector.intersect_aabb(&between), Relation::Out);
+
+        let difference = Difference::new(
+            Sphere::new(Point3::new(0.0, 0.0, 0.0), 10.0),
+            Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0),
+        );
+        let intersector = difference.aabb_intersector();
+        assert_eq!(intersector.intersect_aabb(&unit_cube_at(5.0)), Relation::In);
+        assert_eq!(
+            intersector.intersect_aabb(&unit_cube_at(0.0)),
+            Relation::Cross
+        );
+        let hole = Aabb::new(Point3::new(-0.1, -0.1, -0.1), Point3::new(0.1, 0.1, 0.1));
+        assert_eq!(intersector.intersect_aabb(&hole), Relation::Out);
+    }
+}
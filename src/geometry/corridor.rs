@@ -0,0 +1,489 @@
+//! The volume swept by a sphere along a polyline, e.g. along a vehicle trajectory.
+
+use super::aabb::Aabb;
+use super::is_non_negative;
+use super::obb::Obb;
+use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Relation};
+use crate::math::{HasAabbIntersector, IntersectAabb, PointCulling};
+use nalgebra::{Isometry3, Point3, RealField, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// All points within 'radius' of a polyline, i.e. the union of one capsule per segment. Unlike
+/// a number of overlapping queries along the polyline, each point is contained only once.
+///
+/// A trajectory usually needs a different extent vertically than to the sides, e.g. to include
+/// tall structures along a road, so a corridor can instead have a box-like cross-section: points
+/// within 'half_width' of the polyline horizontally and within 'half_height' above or below it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "CorridorData<S>", into = "CorridorData<S>")]
+pub struct Corridor<S: RealField> {
+    polyline: Vec<Point3<S>>,
+    radius: S,
+    vertical_extent: Option<VerticalExtent<S>>,
+}
+
+/// The serialized form of a corridor, which is checked like in 'Corridor::new' when
+/// deserializing. The vertical direction is normalized.
+#[derive(Serialize, Deserialize)]
+struct CorridorData<S: RealField> {
+    polyline: Vec<Point3<S>>,
+    radius: S,
+    vertical_extent: Option<VerticalExtent<S>>,
+}
+
+impl<S: RealField> TryFrom<CorridorData<S>> for Corridor<S> {
+    type Error = String;
+
+    fn try_from(data: CorridorData<S>) -> Result<Self, String> {
+        if data.polyline.len() < 2 {
+            return Err("The polyline of a corridor needs at least two points.".to_string());
+        }
+        if !is_non_negative(data.radius) {
+            return Err("The radius of a corridor must not be negative.".to_string());
+        }
+        let vertical_extent = match data.vertical_extent {
+            Some(VerticalExtent { up, half_height }) => {
+                if !is_non_negative(half_height) {
+                    return Err("The half height of a corridor must not be negative.".to_string());
+                }
+                let up = up
+                    .try_normalize(S::default_epsilon())
+                    .ok_or_else(|| "The vertical of a corridor must not be zero.".to_string())?;
+                Some(VerticalExtent { up, half_height })
+            }
+            None => None,
+        };
+        Ok(Corridor {
+            polyline: data.polyline,
+            radius: data.radius,
+            vertical_extent,
+        })
+    }
+}
+
+impl<S: RealField> From<Corridor<S>> for CorridorData<S> {
+    fn from(corridor: Corridor<S>) -> Self {
+        CorridorData {
+            polyline: corridor.polyline,
+            radius: corridor.radius,
+            vertical_extent: corridor.vertical_extent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VerticalExtent<S: RealField> {
+    /// The unit vertical direction.
+    up: Vector3<S>,
+    half_height: S,
+}
+
+/// The points within a segment's neighborhood: either within 'radius' of it, or, with a
+/// vertical extent, within 'radius' horizontally and 'half_height' vertically of a point on it.
+#[derive(Debug, Clone)]
+struct SegmentVolume<S: RealField> {
+    start: Point3<S>,
+    end: Point3<S>,
+    radius: S,
+    vertical_extent: Option<VerticalExtent<S>>,
+}
+
+impl<S: RealField> SegmentVolume<S> {
+    fn capsule_contains(&self, p: &Point3<S>) -> bool {
+        let segment = self.end - self.start;
+        let to_p = p - self.start;
+        let length_squared = segment.norm_squared();
+        let t = if length_squared > S::zero() {
+            let t = to_p.dot(&segment) / length_squared;
+            if t < S::zero() {
+                S::zero()
+            } else if t > S::one() {
+                S::one()
+            } else {
+                t
+            }
+        } else {
+            S::zero()
+        };
+        (to_p - segment * t).norm_squared() <= self.radius * self.radius
+    }
+
+    /// The volume is the Minkowski sum of the segment and a vertical cylinder, so 'p' is inside
+    /// if the horizontal and the vertical constraint hold for the same point of the segment.
+    fn box_contains(&self, p: &Point3<S>, vertical_extent: &VerticalExtent<S>) -> bool {
+        let up = &vertical_extent.up;
+        let segment = self.end - self.start;
+        let to_p = p - self.start;
+        let (segment_up, to_p_up) = (segment.dot(up), to_p.dot(up));
+        let segment_horizontal = segment - up * segment_up;
+        let to_p_horizontal = to_p - up * to_p_up;
+
+        // The range of the segment parameter 't' satisfying both constraints.
+        let (mut min_t, mut max_t) = (S::zero(), S::one());
+        // |to_p_horizontal - t * segment_horizontal|² <= radius²
+        let a = segment_horizontal.norm_squared();
+        let b = to_p_horizontal.dot(&segment_horizontal);
+        let c = to_p_horizontal.norm_squared() - self.radius * self.radius;
+        if a > S::zero() {
+            let discriminant = b * b - a * c;
+            if discriminant < S::zero() {
+                return false;
+            }
+            let root = discriminant.sqrt();
+            min_t = min_t.max((b - root) / a);
+            max_t = max_t.min((b + root) / a);
+        } else if c > S::zero() {
+            return false;
+        }
+        // |to_p_up - t * segment_up| <= half_height
+        let half_height = vertical_extent.half_height;
+        if segment_up != S::zero() {
+            let t0 = (to_p_up - half_height) / segment_up;
+            let t1 = (to_p_up + half_height) / segment_up;
+            min_t = min_t.max(t0.min(t1));
+            max_t = max_t.min(t0.max(t1));
+        } else if to_p_up.abs() > half_height {
+            return false;
+        }
+        min_t <= max_t
+    }
+
+    fn contains(&self, p: &Point3<S>) -> bool {
+        match &self.vertical_extent {
+            None => self.capsule_contains(p),
+            Some(vertical_extent) => self.box_contains(p, vertical_extent),
+        }
+    }
+
+    /// The smallest OBB containing the volume, aligned with the segment, or with its horizontal
+    /// direction and the vertical with a vertical extent.
+    fn bounding_obb(&self) -> Obb<S> {
+        let segment = self.end - self.start;
+        let half = nalgebra::convert::<f64, S>(0.5);
+        let center = self.start + segment * half;
+        let (rotation, half_extent) = match &self.vertical_extent {
+            None => {
+                let rotation = UnitQuaternion::rotation_between(&Vector3::x(), &segment)
+                    // The segment is degenerate or points in the negative x direction.
+                    .unwrap_or_else(|| {
+                        if segment.x < S::zero() {
+                            UnitQuaternion::from_axis_angle(&Vector3::z_axis(), S::pi())
+                        } else {
+                            UnitQuaternion::identity()
+                        }
+                    });
+                let half_extent = Vector3::new(
+                    segment.norm() * half + self.radius,
+                    self.radius,
+                    self.radius,
+                );
+                (rotation, half_extent)
+            }
+            Some(VerticalExtent { up, half_height }) => {
+                let segment_up = segment.dot(up);
+                let segment_horizontal = segment - up * segment_up;
+                // Any horizontal direction will do for vertical segments.
+                let forward = segment_horizontal
+                    .try_normalize(S::default_epsilon())
+                    .unwrap_or_else(|| {
+                        let other = if up.x.abs() < nalgebra::convert::<f64, S>(0.9) {
+                            Vector3::x()
+                        } else {
+                            Vector3::y()
+                        };
+                        up.cross(&other).normalize()
+                    });
+                // Maps z to 'forward' and y to 'up'.
+                let rotation = UnitQuaternion::face_towards(&forward, up);
+                let half_extent = Vector3::new(
+                    self.radius,
+                    segment_up.abs() * half + *half_height,
+                    segment_horizontal.norm() * half + self.radius,
+                );
+                (rotation, half_extent)
+            }
+        };
+        Obb::new(
+            Isometry3::from_parts(center.coords.into(), rotation),
+            half_extent,
+        )
+    }
+}
+
+impl<S: RealField> Corridor<S> {
+    /// The polyline needs at least two points, and the radius must not be negative.
+    pub fn new(polyline: Vec<Point3<S>>, radius: S) -> Self {
+        assert!(
+            polyline.len() >= 2,
+            "The polyline of a corridor needs at least two points."
+        );
+        assert!(
+            radius >= S::zero(),
+            "The radius of a corridor must not be negative."
+        );
+        Corridor {
+            polyline,
+            radius,
+            vertical_extent: None,
+        }
+    }
+
+    /// A corridor with a box-like cross-section, where the z axis is the vertical. The half height
+    /// must not be negative.
+    pub fn with_vertical_extent(polyline: Vec<Point3<S>>, half_width: S, half_height: S) -> Self {
+        assert!(
+            half_height >= S::zero(),
+            "The half height of a corridor must not be negative."
+        );
+        Corridor {
+            vertical_extent: Some(VerticalExtent {
+                up: Vector3::z(),
+                half_height,
+            }),
+            ..Self::new(polyline, half_width)
+        }
+    }
+
+    pub fn transformed(&self, global_from_query: &Isometry3<S>) -> Self {
+        Corridor {
+            polyline: self
+                .polyline
+                .iter()
+                .map(|p| global_from_query * p)
+                .collect(),
+            radius: self.radius,
+            vertical_extent: self
+                .vertical_extent
+                .as_ref()
+                .map(|vertical_extent| VerticalExtent {
+                    up: global_from_query * vertical_extent.up,
+                    half_height: vertical_extent.half_height,
+                }),
+        }
+    }
+
+    pub fn polyline(&self) -> &[Point3<S>] {
+        &self.polyline
+    }
+
+    /// The distance to the polyline, horizontally if the corridor has a vertical extent.
+    pub fn radius(&self) -> S {
+        self.radius
+    }
+
+    pub fn half_height(&self) -> Option<S> {
+        self.vertical_extent
+            .as_ref()
+            .map(|vertical_extent| vertical_extent.half_height)
+    }
+
+    fn segment_volumes(&self) -> impl Iterator<Item = SegmentVolume<S>> + '_ {
+        self.polyline.windows(2).map(move |segment| SegmentVolume {
+            start: segment[0],
+            end: segment[1],
+            radius: self.radius,
+            vertical_extent: self.vertical_extent.clone(),
+        })
+    }
+
+    /// One OBB per segment, which together contain the corridor.
+    pub fn bounding_obbs(&self) -> Vec<Obb<S>> {
+        self.segment_volumes()
+            .map(|volume| volume.bounding_obb())
+            .collect()
+    }
+}
+
+impl<S: RealField> PointCulling<S> for Corridor<S> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        self.segment_volumes().any(|volume| volume.contains(p))
+    }
+}
+
+/// Intersects AABBs with the bounding OBBs of the segments of a corridor. An AABB is only known to
+/// be inside if it is completely inside the volume of a single segment.
+pub struct CorridorIntersector<S: RealField> {
+    segment_volumes: Vec<(SegmentVolume<S>, CachedAxesIntersector<S>)>,
+}
+
+impl<S: RealField> IntersectAabb<S> for CorridorIntersector<S> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        let corners = aabb.compute_corners();
+        let mut relation = Relation::Out;
+        for (volume, bounding_obb) in &self.segment_volumes {
+            if bounding_obb.intersect(&corners) == Relation::Out {
+                continue;
+            }
+            // The volumes are convex, so containing all corners means containing the AABB.
+            if corners.iter().all(|corner| volume.contains(corner)) {
+                return Relation::In;
+            }
+            relation = Relation::Cross;
+        }
+        relation
+    }
+}
+
+impl<'a, S: RealField> HasAabbIntersector<'a, S> for Corridor<S> {
+    type Intersector = CorridorIntersector<S>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        CorridorIntersector {
+            segment_volumes: self
+                .segment_volumes()
+                .map(|volume| {
+                    let intersector = volume
+                        .bounding_obb()
+                        .intersector()
+                        .cache_separating_axes_for_aabb();
+                    (volume, intersector)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An L-shaped polyline in the xy plane.
+    fn l_shaped_corridor() -> Corridor<f64> {
+        Corridor::new(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(10.0, 0.0, 0.0),
+                Point3::new(10.0, 10.0, 0.0),
+            ],
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_corridor_contains() {
+        let corridor = l_shaped_corridor();
+        assert!(corridor.contains(&Point3::new(5.0, 0.5, 0.5)));
+        assert!(corridor.contains(&Point3::new(10.5, 5.0, -0.5)));
+        assert!(corridor.contains(&Point3::new(-0.5, 0.0, 0.5)));
+        assert!(!corridor.contains(&Point3::new(5.0, 5.0, 0.0)));
+        assert!(!corridor.contains(&Point3::new(-0.9, 0.9, 0.0)));
+        assert!(!corridor.contains(&Point3::new(5.0, 0.0, 1.1)));
+    }
+
+    #[test]
+    fn test_corridor_intersects_aabb() {
+        let intersector = l_shaped_corridor().aabb_intersector();
+        let inside = Aabb::new(Point3::new(4.0, -0.5, -0.5), Point3::new(6.0, 0.5, 0.5));
+        assert_eq!(intersector.intersect_aabb(&inside), Relation::In);
+        let crossing = Aabb::new(Point3::new(4.0, 0.5, -0.5), Point3::new(6.0, 1.5, 0.5));
+        assert_eq!(intersector.intersect_aabb(&crossing), Relation::Cross);
+        // Spans both segments, but is not inside either capsule alone.
+        let corner = Aabb::new(Point3::new(9.5, -0.9, -0.1), Point3::new(10.5, 5.0, 0.1));
+        assert_eq!(intersector.intersect_aabb(&corner), Relation::Cross);
+        let outside = Aabb::new(Point3::new(2.0, 2.0, -0.5), Point3::new(8.0, 8.0, 0.5));
+        assert_eq!(intersector.intersect_aabb(&outside), Relation::Out);
+    }
+
+    // A sloped segment followed by a level one, 1 wide to the sides and 3 high.
+    fn tall_corridor() -> Corridor<f64> {
+        Corridor::with_vertical_extent(
+            vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(10.0, 0.0, 5.0),
+                Point3::new(10.0, 10.0, 5.0),
+            ],
+            1.0,
+            3.0,
+        )
+    }
+
+    #[test]
+    fn test_corridor_with_vertical_extent_contains() {
+        let corridor = tall_corridor();
+        assert_eq!(corridor.half_height(), Some(3.0));
+        assert!(corridor.contains(&Point3::new(5.0, 0.9, 2.5 + 2.9)));
+        assert!(corridor.contains(&Point3::new(5.0, -0.9, 2.5 - 2.9)));
+        assert!(!corridor.contains(&Point3::new(5.0, 1.1, 2.5)));
+        assert!(!corridor.contains(&Point3::new(5.0, 0.0, 6.1)));
+        // Beyond the start horizontally, but within the cylinder around it.
+        assert!(corridor.contains(&Point3::new(-0.5, 0.5, 2.9)));
+        assert!(!corridor.contains(&Point3::new(-0.8, 0.8, 0.0)));
+        // Too high above the closest point of the slope, but within the vertical extent of a
+        // point farther ahead.
+        assert!(corridor.contains(&Point3::new(1.5, 0.0, 3.9)));
+        assert!(corridor.contains(&Point3::new(10.5, 5.0, 7.5)));
+        assert!(!corridor.contains(&Point3::new(10.5, 5.0, 8.5)));
+    }
+
+    #[test]
+    fn test_corridor_with_vertical_extent_intersects_aabb() {
+        let corridor = tall_corridor();
+        let intersector = corridor.aabb_intersector();
+        let inside = Aabb::new(Point3::new(9.5, 4.0, 3.0), Point3::new(10.5, 6.0, 7.0));
+        assert_eq!(intersector.intersect_aabb(&inside), Relation::In);
+        // Not inside a capsule with the same radius.
+        let capsule = Corridor::new(corridor.polyline().to_vec(), 1.0);
+        assert_eq!(
+            capsule.aabb_intersector().intersect_aabb(&inside),
+            Relation::Cross
+        );
+        let crossing = Aabb::new(Point3::new(9.5, 4.0, 7.0), Point3::new(10.5, 6.0, 9.0));
+        assert_eq!(intersector.intersect_aabb(&crossing), Relation::Cross);
+        let above = Aabb::new(Point3::new(9.5, 4.0, 8.5), Point3::new(10.5, 6.0, 9.0));
+        assert_eq!(intersector.intersect_aabb(&above), Relation::Out);
+
+        // The bounding OBBs contain the corridor.
+        let global_from_query =
+            Isometry3::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.3, -0.2, 1.0));
+        let transformed = corridor.transformed(&global_from_query);
+        let obbs = transformed.bounding_obbs();
+        for p in corridor.polyline() {
+            for offset in &[
+                Vector3::new(0.0, 0.0, 2.9),
+                Vector3::new(0.0, 0.0, -2.9),
+                Vector3::new(0.0, 0.9, 0.0),
+                Vector3::new(-0.9, 0.0, 0.0),
+            ] {
+                let p = global_from_query * (p + offset);
+                assert!(transformed.contains(&p));
+                assert!(obbs.iter().any(|obb| obb.contains(&p)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_corridor_serialization() {
+        let corridor = tall_corridor();
+        let serialized = serde_json::to_value(&corridor).unwrap();
+        let deserialized: Corridor<f64> = serde_json::from_value(serialized.clone()).unwrap();
+        assert_eq!(deserialized.polyline(), corridor.polyline());
+        assert_eq!(deserialized.half_height(), Some(3.0));
+
+        // The vertical is normalized.
+        let mut scaled_up = serialized.clone();
+        scaled_up["vertical_extent"]["up"] = serde_json::json!([0.0, 0.0, 2.0]);
+        let deserialized: Corridor<f64> = serde_json::from_value(scaled_up).unwrap();
+        assert!(deserialized.contains(&Point3::new(5.0, 0.0, 2.5 + 2.9)));
+        assert!(!deserialized.contains(&Point3::new(5.0, 0.0, 6.1)));
+
+        let mut too_few_points = serialized.clone();
+        too_few_points["polyline"]
+            .as_array_mut()
+            .unwrap()
+            .truncate(1);
+        let mut negative_radius = serialized.clone();
+        negative_radius["radius"] = serde_json::json!(-1.0);
+        let mut negative_half_height = serialized.clone();
+        negative_half_height["vertical_extent"]["half_height"] = serde_json::json!(-1.0);
+        let mut zero_up = serialized;
+        zero_up["vertical_extent"]["up"] = serde_json::json!([0.0, 0.0, 0.0]);
+        for invalid in &[
+            too_few_points,
+            negative_radius,
+            negative_half_height,
+            zero_up,
+        ] {
+            assert!(serde_json::from_value::<Corridor<f64>>(invalid.clone()).is_err());
+        }
+    }
+}
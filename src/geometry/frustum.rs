@@ -1,9 +1,11 @@
 //! An asymmetric frustum with an arbitrary 3D pose.
 
-use crate::math::base::{HasAabbIntersector, PointCulling};
-use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Intersector};
+use super::aabb::Aabb;
+use crate::math::base::{HasAabbIntersector, IntersectAabb, PointCulling};
+use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Intersector, Relation};
 use arrayvec::ArrayVec;
 use nalgebra::{Isometry3, Matrix4, Perspective3, Point3, RealField, Unit, Vector3};
+use num_traits::Bounded;
 use serde::{Deserialize, Serialize};
 
 /// A perspective projection matrix analogous to cgmath::Perspective.
@@ -127,7 +129,26 @@ impl<S: RealField> PointCulling<S> for Frustum<S> {
     }
 }
 
-has_aabb_intersector_for_convex_polyhedron!(Frustum<S>);
+/// Intersects AABBs with a frustum. Consistent with `contains`, which excludes the boundary, AABBs
+/// touching the boundary are not reported to be inside.
+pub struct FrustumIntersector<S: RealField> {
+    intersector: CachedAxesIntersector<S>,
+}
+
+impl<S: RealField + Bounded> IntersectAabb<S> for FrustumIntersector<S> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        self.intersector.intersect_interior(&aabb.compute_corners())
+    }
+}
+
+impl<'a, S: RealField> HasAabbIntersector<'a, S> for Frustum<S> {
+    type Intersector = FrustumIntersector<S>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        FrustumIntersector {
+            intersector: self.intersector().cache_separating_axes_for_aabb(),
+        }
+    }
+}
 
 impl<S: RealField> ConvexPolyhedron<S> for Frustum<S> {
     #[rustfmt::skip]
@@ -1,12 +1,33 @@
 //! Contains geometric primitives, e.g. for defining queries against the point cloud.
 mod aabb;
+mod boolean;
+mod corridor;
 mod frustum;
 mod obb;
+mod prism;
+mod ray;
 mod s2_cell_union;
+mod sphere;
 mod web_mercator_rect;
 
 pub use aabb::*;
+pub use boolean::*;
+pub use corridor::*;
 pub use frustum::*;
 pub use obb::*;
+pub use prism::*;
+pub use ray::*;
 pub use s2_cell_union::*;
+pub use sphere::*;
 pub use web_mercator_rect::*;
+
+use nalgebra::RealField;
+use std::cmp::Ordering;
+
+/// Whether 'value' is neither negative nor NaN, for checking the sizes of deserialized geometry.
+fn is_non_negative<S: RealField>(value: S) -> bool {
+    match value.partial_cmp(&S::zero()) {
+        Some(Ordering::Less) | None => false,
+        Some(_) => true,
+    }
+}
@@ -0,0 +1,240 @@
+//! A 2D polygon extruded along the z axis of its own frame.
+
+use super::aabb::Aabb;
+use super::obb::Obb;
+use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Relation};
+use crate::math::{ClosedInterval, HasAabbIntersector, IntersectAabb, PointCulling};
+use nalgebra::{Isometry3, Point2, Point3, RealField, Translation3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// A prism whose base is a simple, possibly non-convex polygon in the xy plane of the prism frame,
+/// extruded over 'z_range'. Useful for querying map features like lanes or parcels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "PrismData<S>", into = "PrismData<S>")]
+pub struct Prism<S: RealField> {
+    query_from_prism: Isometry3<S>,
+    prism_from_query: Isometry3<S>,
+    polygon: Vec<Point2<S>>,
+    z_range: ClosedInterval<S>,
+}
+
+/// The serialized form of a prism, which is checked like in 'Prism::new' when deserializing.
+#[derive(Serialize, Deserialize)]
+struct PrismData<S: RealField> {
+    query_from_prism: Isometry3<S>,
+    polygon: Vec<Point2<S>>,
+    z_range: ClosedInterval<S>,
+}
+
+impl<S: RealField> TryFrom<PrismData<S>> for Prism<S> {
+    type Error = String;
+
+    fn try_from(data: PrismData<S>) -> Result<Self, String> {
+        if data.polygon.len() < 3 {
+            return Err("The polygon of a prism needs at least three vertices.".to_string());
+        }
+        Ok(Prism::new(
+            data.query_from_prism,
+            data.polygon,
+            data.z_range,
+        ))
+    }
+}
+
+impl<S: RealField> From<Prism<S>> for PrismData<S> {
+    fn from(prism: Prism<S>) -> Self {
+        PrismData {
+            query_from_prism: prism.query_from_prism,
+            polygon: prism.polygon,
+            z_range: prism.z_range,
+        }
+    }
+}
+
+impl<S: RealField> Prism<S> {
+    /// The polygon needs at least three vertices. Its last vertex is implicitly connected to the
+    /// first one.
+    pub fn new(
+        query_from_prism: Isometry3<S>,
+        polygon: Vec<Point2<S>>,
+        z_range: ClosedInterval<S>,
+    ) -> Self {
+        assert!(
+            polygon.len() >= 3,
+            "The polygon of a prism needs at least three vertices."
+        );
+        Prism {
+            prism_from_query: query_from_prism.inverse(),
+            query_from_prism,
+            polygon,
+            z_range,
+        }
+    }
+
+    pub fn transformed(&self, global_from_query: &Isometry3<S>) -> Self {
+        Self::new(
+            global_from_query * self.query_from_prism,
+            self.polygon.clone(),
+            self.z_range,
+        )
+    }
+
+    pub fn polygon(&self) -> &[Point2<S>] {
+        &self.polygon
+    }
+
+    pub fn z_range(&self) -> ClosedInterval<S> {
+        self.z_range
+    }
+
+    /// The smallest OBB in the prism frame that contains the prism.
+    pub fn bounding_obb(&self) -> Obb<S> {
+        let mut min = self.polygon[0];
+        let mut max = self.polygon[0];
+        for p in &self.polygon[1..] {
+            min = nalgebra::inf(&min, p);
+            max = nalgebra::sup(&max, p);
+        }
+        let (z_min, z_max) = (self.z_range.lower_bound(), self.z_range.upper_bound());
+        let half = nalgebra::convert::<f64, S>(0.5);
+        let center = Vector3::new(
+            (min.x + max.x) * half,
+            (min.y + max.y) * half,
+            (z_min + z_max) * half,
+        );
+        let half_extent = Vector3::new(
+            (max.x - min.x) * half,
+            (max.y - min.y) * half,
+            (z_max - z_min) * half,
+        );
+        Obb::new(
+            self.query_from_prism * Translation3::from(center),
+            half_extent,
+        )
+    }
+
+    /// Even-odd rule, which is correct for simple polygons of any winding order.
+    fn polygon_contains(&self, p: &Point2<S>) -> bool {
+        let mut inside = false;
+        let mut previous = self.polygon[self.polygon.len() - 1];
+        for &current in &self.polygon {
+            if (current.y > p.y) != (previous.y > p.y)
+                && p.x
+                    < (previous.x - current.x) * (p.y - current.y) / (previous.y - current.y)
+                        + current.x
+            {
+                inside = !inside;
+            }
+            previous = current;
+        }
+        inside
+    }
+}
+
+impl<S: RealField> PointCulling<S> for Prism<S> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        let p = self.prism_from_query * p;
+        self.z_range.contains(p.z) && self.polygon_contains(&Point2::new(p.x, p.y))
+    }
+}
+
+/// Intersects AABBs with the bounding OBB of a prism. Since the polygon may be non-convex, AABBs
+/// are never reported to be completely inside.
+pub struct PrismIntersector<S: RealField> {
+    bounding_obb: CachedAxesIntersector<S>,
+}
+
+impl<S: RealField> IntersectAabb<S> for PrismIntersector<S>
+where
+    CachedAxesIntersector<S>: IntersectAabb<S>,
+{
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        match self.bounding_obb.intersect_aabb(aabb) {
+            Relation::Out => Relation::Out,
+            _ => Relation::Cross,
+        }
+    }
+}
+
+impl<'a, S: RealField> HasAabbIntersector<'a, S> for Prism<S>
+where
+    CachedAxesIntersector<S>: IntersectAabb<S>,
+{
+    type Intersector = PrismIntersector<S>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        PrismIntersector {
+            bounding_obb: self
+                .bounding_obb()
+                .intersector()
+                .cache_separating_axes_for_aabb(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::UnitQuaternion;
+
+    // An L-shaped polygon, missing the upper right quadrant of the square from (0, 0) to (2, 2).
+    fn l_shaped_prism() -> Prism<f64> {
+        Prism::new(
+            Isometry3::from_parts(
+                Translation3::new(10.0, 0.0, 0.0),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), std::f64::consts::FRAC_PI_2),
+            ),
+            vec![
+                Point2::new(0.0, 0.0),
+                Point2::new(2.0, 0.0),
+                Point2::new(2.0, 1.0),
+                Point2::new(1.0, 1.0),
+                Point2::new(1.0, 2.0),
+                Point2::new(0.0, 2.0),
+            ],
+            ClosedInterval::new(-1.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn test_prism_contains() {
+        let prism = l_shaped_prism();
+        // The prism frame is rotated by 90 degrees, so (x, y) in the prism frame is at
+        // (10 - y, x) in the query frame.
+        assert!(prism.contains(&Point3::new(9.5, 0.5, 0.0)));
+        assert!(prism.contains(&Point3::new(9.5, 1.5, 0.5)));
+        assert!(prism.contains(&Point3::new(8.5, 0.5, -0.5)));
+        assert!(!prism.contains(&Point3::new(8.5, 1.5, 0.0)));
+        assert!(!prism.contains(&Point3::new(9.5, 0.5, 1.5)));
+        assert!(!prism.contains(&Point3::new(10.5, 0.5, 0.0)));
+    }
+
+    #[test]
+    fn test_prism_intersects_aabb() {
+        let prism = l_shaped_prism();
+        let intersector = prism.aabb_intersector();
+        let inside = Aabb::new(Point3::new(9.2, 0.2, -0.5), Point3::new(9.8, 0.8, 0.5));
+        assert_eq!(intersector.intersect_aabb(&inside), Relation::Cross);
+        let outside = Aabb::new(Point3::new(11.0, 0.0, -0.5), Point3::new(12.0, 1.0, 0.5));
+        assert_eq!(intersector.intersect_aabb(&outside), Relation::Out);
+        let above = Aabb::new(Point3::new(9.2, 0.2, 1.5), Point3::new(9.8, 0.8, 2.0));
+        assert_eq!(intersector.intersect_aabb(&above), Relation::Out);
+    }
+
+    #[test]
+    fn test_prism_serialization() {
+        let prism = l_shaped_prism();
+        let serialized = serde_json::to_value(&prism).unwrap();
+        assert!(serialized.get("prism_from_query").is_none());
+        let deserialized: Prism<f64> = serde_json::from_value(serialized.clone()).unwrap();
+        assert_eq!(deserialized.polygon(), prism.polygon());
+        assert!(deserialized.contains(&Point3::new(9.5, 0.5, 0.0)));
+
+        let mut too_few_vertices = serialized;
+        too_few_vertices["polygon"]
+            .as_array_mut()
+            .unwrap()
+            .truncate(2);
+        assert!(serde_json::from_value::<Prism<f64>>(too_few_vertices).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! A half-line, e.g. from the camera through the pixel under the mouse cursor.
+
+use nalgebra::{Point3, RealField, Unit, Vector3};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ray<S: RealField> {
+    origin: Point3<S>,
+    direction: Unit<Vector3<S>>,
+}
+
+impl<S: RealField> Ray<S> {
+    /// 'direction' does not need to be normalized, but must not be zero.
+    pub fn new(origin: Point3<S>, direction: Vector3<S>) -> Self {
+        Ray {
+            origin,
+            direction: Unit::new_normalize(direction),
+        }
+    }
+
+    pub fn origin(&self) -> &Point3<S> {
+        &self.origin
+    }
+
+    pub fn direction(&self) -> &Unit<Vector3<S>> {
+        &self.direction
+    }
+
+    pub fn point_at(&self, distance: S) -> Point3<S> {
+        self.origin + self.direction.into_inner() * distance
+    }
+
+    /// The distance along the ray to the projection of 'p' onto the ray's line, which is negative
+    /// for points behind the origin.
+    pub fn distance_along(&self, p: &Point3<S>) -> S {
+        (p - self.origin).dot(&self.direction)
+    }
+
+    /// The distance of 'p' to the ray's line.
+    pub fn distance_to_line(&self, p: &Point3<S>) -> S {
+        let along = self.distance_along(p);
+        let distance_squared = (p - self.origin).norm_squared() - along * along;
+        // Guard against rounding errors for points on the line.
+        if distance_squared > S::zero() {
+            distance_squared.sqrt()
+        } else {
+            S::zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ray_distances() {
+        let ray = Ray::new(Point3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0));
+        let p = Point3::new(4.0, 5.0, 4.0);
+        assert_eq!(ray.distance_along(&p), 5.0);
+        assert_eq!(ray.distance_to_line(&p), 5.0);
+        assert_eq!(ray.point_at(2.0), Point3::new(1.0, 2.0, 0.0));
+        assert!(ray.distance_along(&Point3::new(0.0, -1.0, 0.0)) < 0.0);
+    }
+}
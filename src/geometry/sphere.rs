@@ -0,0 +1,159 @@
+//! A ball around a center point, for radius queries.
+
+use super::aabb::Aabb;
+use super::is_non_negative;
+use crate::math::sat::Relation;
+use crate::math::{HasAabbIntersector, IntersectAabb, PointCulling};
+use nalgebra::{Isometry3, Point3, RealField, Vector3};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// All points within 'radius' of 'center', including the boundary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SphereData<S>", into = "SphereData<S>")]
+pub struct Sphere<S: RealField> {
+    center: Point3<S>,
+    radius: S,
+}
+
+/// The serialized form of a sphere, which is checked like in 'Sphere::new' when deserializing.
+#[derive(Serialize, Deserialize)]
+struct SphereData<S: RealField> {
+    center: Point3<S>,
+    radius: S,
+}
+
+impl<S: RealField> TryFrom<SphereData<S>> for Sphere<S> {
+    type Error = String;
+
+    fn try_from(data: SphereData<S>) -> Result<Self, String> {
+        if !is_non_negative(data.radius) {
+            return Err("The radius of a sphere must not be negative or NaN.".to_string());
+        }
+        Ok(Sphere::new(data.center, data.radius))
+    }
+}
+
+impl<S: RealField> From<Sphere<S>> for SphereData<S> {
+    fn from(sphere: Sphere<S>) -> Self {
+        SphereData {
+            center: sphere.center,
+            radius: sphere.radius,
+        }
+    }
+}
+
+impl<S: RealField> Sphere<S> {
+    /// The radius must not be negative or NaN.
+    pub fn new(center: Point3<S>, radius: S) -> Self {
+        assert!(
+            radius >= S::zero(),
+            "The radius of a sphere must not be negative or NaN."
+        );
+        Sphere { center, radius }
+    }
+
+    pub fn center(&self) -> &Point3<S> {
+        &self.center
+    }
+
+    pub fn radius(&self) -> S {
+        self.radius
+    }
+
+    pub fn transformed(&self, global_from_query: &Isometry3<S>) -> Self {
+        Self::new(global_from_query * self.center, self.radius)
+    }
+
+    /// The smallest AABB containing this sphere.
+    pub fn bounding_box(&self) -> Aabb<S> {
+        let half_extent = Vector3::repeat(self.radius);
+        Aabb::new(self.center - half_extent, self.center + half_extent)
+    }
+}
+
+impl<S: RealField> PointCulling<S> for Sphere<S> {
+    fn contains(&self, p: &Point3<S>) -> bool {
+        (p - self.center).norm_squared() <= self.radius * self.radius
+    }
+}
+
+impl<S: RealField> IntersectAabb<S> for Sphere<S> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        let (mins, maxs) = (aabb.min(), aabb.max());
+        let mut closest_squared = S::zero();
+        let mut farthest_squared = S::zero();
+        for i in 0..3 {
+            let (to_min, to_max) = (mins[i] - self.center[i], maxs[i] - self.center[i]);
+            let closest = if to_min > S::zero() {
+                to_min
+            } else if to_max < S::zero() {
+                to_max
+            } else {
+                S::zero()
+            };
+            let farthest = if to_min.abs() > to_max.abs() {
+                to_min
+            } else {
+                to_max
+            };
+            closest_squared += closest * closest;
+            farthest_squared += farthest * farthest;
+        }
+        let radius_squared = self.radius * self.radius;
+        if closest_squared > radius_squared {
+            Relation::Out
+        } else if farthest_squared <= radius_squared {
+            Relation::In
+        } else {
+            Relation::Cross
+        }
+    }
+}
+
+impl<'a, S: RealField> HasAabbIntersector<'a, S> for Sphere<S> {
+    type Intersector = Sphere<S>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_intersects_aabb() {
+        let sphere = Sphere::new(Point3::new(1.0, 1.0, 1.0), 2.0);
+        let inside = Aabb::new(Point3::new(0.5, 0.5, 0.5), Point3::new(1.5, 1.5, 1.5));
+        assert_eq!(sphere.intersect_aabb(&inside), Relation::In);
+        let crossing = Aabb::new(Point3::new(2.0, 0.0, 0.0), Point3::new(4.0, 2.0, 2.0));
+        assert_eq!(sphere.intersect_aabb(&crossing), Relation::Cross);
+        // Inside the bounding box of the sphere, but outside the sphere itself.
+        let corner = Aabb::new(Point3::new(2.5, 2.5, 2.5), Point3::new(3.0, 3.0, 3.0));
+        assert_eq!(sphere.intersect_aabb(&corner), Relation::Out);
+        let enclosing = Aabb::new(Point3::new(-5.0, -5.0, -5.0), Point3::new(5.0, 5.0, 5.0));
+        assert_eq!(sphere.intersect_aabb(&enclosing), Relation::Cross);
+    }
+
+    #[test]
+    fn test_sphere_contains() {
+        let sphere = Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0);
+        assert!(sphere.contains(&Point3::new(2.0, 0.0, 0.0)));
+        assert!(sphere.contains(&Point3::new(1.5, 0.5, 0.5)));
+        assert!(!sphere.contains(&Point3::new(1.6, 0.6, 0.6)));
+        assert!(!sphere.contains(&Point3::new(-0.1, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_sphere_serialization() {
+        let sphere = Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0);
+        let serialized = serde_json::to_value(&sphere).unwrap();
+        let deserialized: Sphere<f64> = serde_json::from_value(serialized.clone()).unwrap();
+        assert_eq!(deserialized.radius(), 1.0);
+
+        let mut negative_radius = serialized;
+        negative_radius["radius"] = serde_json::json!(-1.0);
+        assert!(serde_json::from_value::<Sphere<f64>>(negative_radius).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! A Web Mercator axis-aligned rectangle.
 
-use crate::math::base::{HasAabbIntersector, PointCulling};
-use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Intersector};
+use super::aabb::Aabb;
+use crate::math::base::{HasAabbIntersector, IntersectAabb, PointCulling};
+use crate::math::sat::{CachedAxesIntersector, ConvexPolyhedron, Intersector, Relation};
 use crate::math::web_mercator::WebMercatorCoord;
 use arrayvec::ArrayVec;
 use nalgebra::{Point3, RealField, Unit, Vector2};
@@ -112,7 +113,31 @@ impl ConvexPolyhedron<f64> for WebMercatorRect {
     }
 }
 
-has_aabb_intersector_for_convex_polyhedron!(WebMercatorRect);
+/// Intersects AABBs with the polyhedron of a Web Mercator rect. The polyhedron only approximates
+/// the rect conservatively, it is padded in elevation and its north and south faces are planar
+/// chords instead of curves of constant latitude. So AABBs are never reported to be completely
+/// inside.
+pub struct WebMercatorRectIntersector {
+    polyhedron: CachedAxesIntersector<f64>,
+}
+
+impl IntersectAabb<f64> for WebMercatorRectIntersector {
+    fn intersect_aabb(&self, aabb: &Aabb<f64>) -> Relation {
+        match self.polyhedron.intersect_aabb(aabb) {
+            Relation::Out => Relation::Out,
+            _ => Relation::Cross,
+        }
+    }
+}
+
+impl<'a> HasAabbIntersector<'a, f64> for WebMercatorRect {
+    type Intersector = WebMercatorRectIntersector;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        WebMercatorRectIntersector {
+            polyhedron: self.intersector().cache_separating_axes_for_aabb(),
+        }
+    }
+}
 
 impl<S: RealField> PointCulling<S> for WebMercatorRect
 where
@@ -129,7 +154,6 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::math::sat::Relation;
 
     #[test]
     fn intersection_test() {
@@ -169,6 +193,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn aabb_is_never_inside_test() {
+        let rect = WebMercatorRect::from_zoomed_coordinates(
+            Vector2::new(0.1, 0.1),
+            Vector2::new(0.3, 0.3),
+            1,
+        )
+        .unwrap();
+        // A tiny box around the ECEF position of the center of the rect.
+        let center = WebMercatorCoord::from_zoomed_coordinate(Vector2::new(0.2, 0.2), 1)
+            .unwrap()
+            .to_lat_lng();
+        let ecef = ECEF::from(WGS84::new(
+            center.latitude_degrees(),
+            center.longitude_degrees(),
+            0.0,
+        ));
+        let center = Point3::new(ecef.x(), ecef.y(), ecef.z());
+        let offset = nalgebra::Vector3::new(1.0, 1.0, 1.0);
+        let aabb = Aabb::new(center - offset, center + offset);
+        assert!(rect.contains(&center));
+        assert_eq!(
+            rect.aabb_intersector().intersect_aabb(&aabb),
+            Relation::Cross
+        );
+    }
+
     #[test]
     fn sagitta_test() {
         let min_corner = Vector2::new(128.0 - 0.5, 128.0 - 0.5);
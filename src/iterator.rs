@@ -1,12 +1,28 @@
+use crate::aggregation::{self, Aggregate, Aggregations, PartialAggregate};
+use crate::attributes::{AttributeRanges, LabelDictionary};
 use crate::errors::*;
-use crate::geometry::{Aabb, CellUnion, Frustum, Obb, WebMercatorRect};
-use crate::math::{AllPoints, ClosedInterval, PointCulling};
+use crate::filter::{FilterExpression, Operand};
+use crate::geometry::{
+    Aabb, CellUnion, Corridor, Difference, Frustum, Intersection, Obb, Prism, Ray, Sphere, Union,
+    WebMercatorRect,
+};
+use crate::math::{
+    AllPoints, ClosedInterval, Crs, HasAabbIntersector, IntersectAabb, PointCulling, Relation,
+};
+use crate::nearest_neighbors::{self, Neighbors};
+use crate::picking::{self, PickedPoint};
 use crate::read_write::{Encoding, NodeIterator};
-use crate::{match_1d_attr_data, AttributeData, PointsBatch};
+use crate::{AttributeDataType, PointsBatch};
 use crossbeam::deque::{Injector, Steal, Worker};
-use num_traits::ToPrimitive;
+use futures::task::AtomicTask;
+use futures::{Async, Poll, Stream};
+use nalgebra::{Isometry3, Point3};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +33,12 @@ pub enum PointLocation {
     Obb(Obb<f64>),
     S2Cells(CellUnion),
     WebMercatorRect(WebMercatorRect),
+    Sphere(Sphere<f64>),
+    Prism(Prism<f64>),
+    Corridor(Corridor<f64>),
+    Union(Union<PointLocation>),
+    Intersection(Intersection<PointLocation>),
+    Difference(Difference<PointLocation>),
 }
 
 impl Default for PointLocation {
@@ -34,6 +56,12 @@ impl PointLocation {
             PointLocation::Obb(obb) => Box::new(obb.clone()),
             PointLocation::S2Cells(cell_union) => Box::new(cell_union.clone()),
             PointLocation::WebMercatorRect(wmr) => Box::new(wmr.clone()),
+            PointLocation::Sphere(sphere) => Box::new(sphere.clone()),
+            PointLocation::Prism(prism) => Box::new(prism.clone()),
+            PointLocation::Corridor(corridor) => Box::new(corridor.clone()),
+            PointLocation::Union(union) => Box::new(union.clone()),
+            PointLocation::Intersection(intersection) => Box::new(intersection.clone()),
+            PointLocation::Difference(difference) => Box::new(difference.clone()),
         }
     }
 }
@@ -58,10 +86,39 @@ macro_rules! dispatch_point_location {
             PointLocation::Obb(obb) => $func($($arg,)* obb),
             PointLocation::S2Cells(cu) => $func($($arg,)* cu),
             PointLocation::WebMercatorRect(wmr) => $func($($arg,)* wmr),
+            PointLocation::Sphere(sphere) => $func($($arg,)* sphere),
+            PointLocation::Prism(prism) => $func($($arg,)* prism),
+            PointLocation::Corridor(corridor) => $func($($arg,)* corridor),
+            PointLocation::Union(u) => $func($($arg,)* u),
+            PointLocation::Intersection(i) => $func($($arg,)* i),
+            PointLocation::Difference(d) => $func($($arg,)* d),
         }
     }
 }
 
+/// Point locations are only culled through this impl when they are part of a composite
+/// location. Other locations are dispatched to their concrete geometry directly.
+impl PointCulling<f64> for PointLocation {
+    fn contains(&self, p: &Point3<f64>) -> bool {
+        fn contains<T: PointCulling<f64>>(p: &Point3<f64>, geometry: &T) -> bool {
+            geometry.contains(p)
+        }
+        dispatch_point_location!(contains, self, p)
+    }
+}
+
+impl<'a> HasAabbIntersector<'a, f64> for PointLocation {
+    type Intersector = Box<dyn IntersectAabb<f64> + 'a>;
+    fn aabb_intersector(&'a self) -> Self::Intersector {
+        fn boxed<'a, T: HasAabbIntersector<'a, f64>>(
+            geometry: &'a T,
+        ) -> Box<dyn IntersectAabb<f64> + 'a> {
+            Box::new(geometry.aabb_intersector())
+        }
+        dispatch_point_location!(boxed, self)
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PointQuery<'a> {
     #[serde(borrow)]
@@ -69,53 +126,62 @@ pub struct PointQuery<'a> {
     pub location: PointLocation,
     #[serde(borrow)]
     pub filter_intervals: HashMap<&'a str, ClosedInterval<f64>>,
+    /// Only return points whose categorical attribute has one of the labels, which are looked up
+    /// in the label dictionaries of the point clouds. Raw values are filtered by
+    /// 'filter_intervals'.
+    #[serde(borrow, default)]
+    pub filter_labels: HashMap<&'a str, Vec<&'a str>>,
+    /// Only return points matching this expression, in addition to the filter intervals and
+    /// labels. The attributes they refer to are read from the nodes for filtering, but are only
+    /// returned if they are in 'attributes' as well.
+    pub filter: Option<FilterExpression>,
+    /// Only return points from nodes up to this level of detail. Coarser levels contain a
+    /// subsample of the points, so this trades resolution for speed. Ignored by point clouds
+    /// without levels of detail.
+    pub max_level: Option<u8>,
+    /// Stop descending into finer levels of detail once the nodes could contain more than this
+    /// number of points. Only whole levels are returned, so fewer points may be returned even if
+    /// the budget would allow some nodes of the next level. Ignored by point clouds without
+    /// levels of detail.
+    pub point_budget: Option<usize>,
+    /// Transforms the positions of the returned points from the global frame into another
+    /// frame, e.g. a local ENU frame from `local_frame_from_lat_lng`. The location is still given
+    /// in the global frame.
+    pub output_transform: Option<Isometry3<f64>>,
 }
 
 /// Iterator over the points of a point cloud node within the specified PointCulling
 /// Essentially a specialized version of the Filter iterator adapter
 pub struct FilteredIterator<'a, Culling: PointCulling<f64>> {
     pub culling: Culling,
-    pub filter_intervals: &'a HashMap<&'a str, ClosedInterval<f64>>,
+    pub filter: Option<&'a FilterExpression>,
     pub node_iterator: NodeIterator,
 }
 
-fn update_keep<T>(keep: &mut [bool], data: &[T], interval: &ClosedInterval<f64>)
-where
-    T: ToPrimitive,
-{
-    for (k, v) in keep.iter_mut().zip(data) {
-        if let Some(v) = v.to_f64() {
-            *k &= interval.contains(v);
-        }
-    }
-}
-
 impl<'a, Culling: PointCulling<f64>> Iterator for FilteredIterator<'a, Culling> {
-    type Item = PointsBatch;
+    type Item = Result<PointsBatch>;
 
-    fn next(&mut self) -> Option<PointsBatch> {
+    fn next(&mut self) -> Option<Result<PointsBatch>> {
         let culling = &self.culling;
-        self.node_iterator.next().map(|mut batch| {
-            let mut keep: Vec<bool> = batch
-                .position
-                .iter()
-                .map(|pos| culling.contains(&pos))
-                .collect();
-            macro_rules! rhs {
-                ($dtype:ident, $data:ident, $interval:expr) => {
-                    update_keep(&mut keep, $data, $interval)
-                };
-            }
-            for (attrib, interval) in self.filter_intervals {
-                let attr_data = batch
-                    .attributes
-                    .get(*attrib)
-                    .expect("Filter attribute needs to be specified as query attribute.");
-                match_1d_attr_data!(attr_data, rhs, interval)
-            }
-            batch.retain(&keep);
-            batch
-        })
+        let filter = self.filter;
+        self.node_iterator
+            .try_next()
+            .transpose()
+            .map(|batch| -> Result<PointsBatch> {
+                let mut batch = batch?;
+                let mut keep: Vec<bool> = batch
+                    .position
+                    .iter()
+                    .map(|pos| culling.contains(&pos))
+                    .collect();
+                if let Some(filter) = filter {
+                    for (k, m) in keep.iter_mut().zip(filter.evaluate(&batch)?) {
+                        *k &= m;
+                    }
+                }
+                batch.retain(&keep);
+                Ok(batch)
+            })
     }
 }
 
@@ -165,10 +231,53 @@ where
     }
 }
 
+/// Streams the points of a node matching the query into 'point_stream' and returns the number of
+/// bytes read. If failing nodes are skipped, the node is read completely before its points are
+/// pushed, so that none of the points of a node that fails halfway are passed on.
+fn stream_node<C, F>(
+    point_cloud: &C,
+    point_query: &PointQuery,
+    (node_id, relation): (C::Id, Relation),
+    batch_size: usize,
+    skip_failing_nodes: bool,
+    point_stream: &mut PointStream<F>,
+) -> Result<usize>
+where
+    C: PointCloud,
+    F: Fn(PointsBatch) -> Result<()>,
+{
+    if !skip_failing_nodes {
+        return point_cloud.stream_points_for_query_in_node(
+            point_query,
+            node_id,
+            relation,
+            batch_size,
+            |batch| point_stream.push_points_and_callback(batch),
+        );
+    }
+    let mut batches = Vec::new();
+    let num_bytes = point_cloud.stream_points_for_query_in_node(
+        point_query,
+        node_id,
+        relation,
+        batch_size,
+        |batch| {
+            batches.push(batch);
+            Ok(())
+        },
+    )?;
+    for batch in batches {
+        point_stream.push_points_and_callback(batch)?;
+    }
+    Ok(num_bytes)
+}
+
 // TODO(nnmm): Move this somewhere else
 pub trait PointCloud: Sync {
-    type Id: ToString + Send + Copy;
-    fn nodes_in_location(&self, location: &PointLocation) -> Vec<Self::Id>;
+    type Id: ToString + Send + Sync + Copy + Ord;
+    /// Return the nodes that intersect the location, together with whether they are completely
+    /// inside of it.
+    fn nodes_in_location(&self, location: &PointLocation) -> Vec<(Self::Id, Relation)>;
     fn encoding_for_node(&self, id: Self::Id) -> Encoding;
     /// Return all points in the selected node.
     fn points_in_node(
@@ -178,48 +287,455 @@ pub trait PointCloud: Sync {
         batch_size: usize,
     ) -> Result<NodeIterator>;
     fn bounding_box(&self) -> &Aabb<f64>;
+    /// Return the frame the positions are given in, if it is known.
+    fn crs(&self) -> Option<Crs>;
+    /// Return the data types of the attributes described by the meta.
+    fn attribute_data_types(&self) -> &HashMap<String, AttributeDataType>;
+    /// Return the labels of the values of a categorical attribute, if it has any.
+    fn label_dictionary(&self, attribute: &str) -> Option<&LabelDictionary>;
+    /// Return the value ranges of the scalar attributes in the selected node, if they are known.
+    fn attribute_ranges(&self, node_id: Self::Id) -> Option<&AttributeRanges>;
+    /// Return the number of points in the selected node.
+    fn num_points_in_node(&self, node_id: Self::Id) -> usize;
+    /// Return a lower bound of the distance from 'point' to the points in the selected node.
+    fn distance_to_node(&self, node_id: Self::Id, point: &Point3<f64>) -> f64;
+
+    /// Return the 'k' points closest to 'point' that are at most 'max_distance' away, see
+    /// `nearest_neighbors::nearest_neighbors`.
+    fn nearest_neighbors(
+        &self,
+        point: &Point3<f64>,
+        k: usize,
+        max_distance: f64,
+        attributes: &[&str],
+    ) -> Result<Neighbors> {
+        nearest_neighbors::nearest_neighbors(self, point, k, max_distance, attributes)
+    }
+
+    /// Like `nearest_neighbors`, for many query points at once.
+    fn nearest_neighbors_batch(
+        &self,
+        points: &[Point3<f64>],
+        k: usize,
+        max_distance: f64,
+        attributes: &[&str],
+    ) -> Result<Vec<Neighbors>> {
+        nearest_neighbors::nearest_neighbors_batch(self, points, k, max_distance, attributes)
+    }
+
+    /// Return the first point along 'ray' that is within 'radius' of it, see
+    /// `picking::pick_point`.
+    fn pick_point(
+        &self,
+        ray: &Ray<f64>,
+        radius: f64,
+        attributes: &[&str],
+    ) -> Result<Option<PickedPoint>> {
+        picking::pick_point(self, ray, radius, attributes, |_| true)
+    }
 
-    /// Return the points matching the query in the selected node.
+    /// Return the values of the labels the query filters by. Fails if a label is not in the
+    /// label dictionary of its attribute.
+    fn filter_values<'a>(&self, query: &PointQuery<'a>) -> Result<HashMap<&'a str, Vec<i64>>> {
+        query
+            .filter_labels
+            .iter()
+            .map(|(attribute, labels)| {
+                let dictionary = self.label_dictionary(attribute).ok_or_else(|| {
+                    ErrorKind::InvalidInput(format!(
+                        "Attribute '{}' has no label dictionary.",
+                        attribute
+                    ))
+                })?;
+                let values = labels
+                    .iter()
+                    .map(|label| {
+                        dictionary.value(label).ok_or_else(|| {
+                            ErrorKind::InvalidInput(format!(
+                                "Unknown label '{}' for attribute '{}'.",
+                                label, attribute
+                            ))
+                            .into()
+                        })
+                    })
+                    .collect::<Result<Vec<i64>>>()?;
+                Ok((*attribute, values))
+            })
+            .collect()
+    }
+
+    /// Return the filter intervals, labels and filter expression of the query combined into a
+    /// single expression, or None if the query does not filter by attributes. Fails if a label is
+    /// not in the label dictionary of its attribute.
+    fn filter_expression(&self, query: &PointQuery) -> Result<Option<FilterExpression>> {
+        let mut terms: Vec<FilterExpression> = query
+            .filter_intervals
+            .iter()
+            .map(|(attribute, interval)| {
+                FilterExpression::Interval(Operand::new(*attribute), *interval)
+            })
+            .collect();
+        terms.extend(
+            self.filter_values(query)?
+                .into_iter()
+                .map(|(attribute, values)| {
+                    let values = values.into_iter().map(|value| value as f64).collect();
+                    FilterExpression::In(Operand::new(attribute), values)
+                }),
+        );
+        terms.extend(query.filter.clone());
+        Ok(match terms.len() {
+            0 => None,
+            1 => terms.pop(),
+            _ => Some(FilterExpression::And(terms)),
+        })
+    }
+
+    /// Return 'query' with its filter intervals and labels combined into its filter expression,
+    /// see `filter_expression`. The labels of the returned query do not need to be looked up
+    /// again for every node.
+    fn resolve_filter<'a>(&self, query: &PointQuery<'a>) -> Result<PointQuery<'a>> {
+        Ok(PointQuery {
+            filter_intervals: HashMap::new(),
+            filter_labels: HashMap::new(),
+            filter: self.filter_expression(query)?,
+            ..query.clone()
+        })
+    }
+
+    /// Return whether all, some or none of the points in the selected node can match 'filter',
+    /// as returned by `filter_expression`.
+    fn filter_relation(&self, filter: Option<&FilterExpression>, node_id: Self::Id) -> Relation {
+        let filter = match filter {
+            Some(filter) => filter,
+            None => return Relation::In,
+        };
+        match self.attribute_ranges(node_id) {
+            Some(ranges) => filter.relation_to(ranges),
+            None => Relation::Cross,
+        }
+    }
+
+    /// Return the nodes in the query location that can contain points matching the query,
+    /// together with their relation to the query location.
+    fn nodes_for_query(&self, query: &PointQuery) -> Vec<(Self::Id, Relation)> {
+        // Unknown labels are reported when the points are read.
+        let filter = self.filter_expression(query).unwrap_or(None);
+        self.nodes_in_location(&query.location)
+            .into_iter()
+            .filter(|(node_id, _)| self.filter_relation(filter.as_ref(), *node_id) != Relation::Out)
+            .collect()
+    }
+
+    /// Return the points matching the query in the selected node. 'location_relation' is the
+    /// relation of the node to the query location as returned by `nodes_in_location`, points in
+    /// nodes that are completely inside are not tested individually.
     /// Why only a single node? Because the nodes are distributed to several `PointStream` instances
     /// working in parallel by the `ParallelIterator`.
+    /// The positions are transformed by the output transform of the query, if any.
+    /// Queries from `resolve_filter` avoid looking up their labels for every node.
+    /// Returns the number of bytes read from the node.
     fn stream_points_for_query_in_node<F>(
         &self,
         query: &PointQuery,
         node_id: Self::Id,
+        location_relation: Relation,
         batch_size: usize,
-        callback: F,
-    ) -> Result<()>
+        mut callback: F,
+    ) -> Result<usize>
     where
         F: FnMut(PointsBatch) -> Result<()>,
     {
-        let filter_intervals = &query.filter_intervals;
-        let node_iterator = self.points_in_node(&query.attributes, node_id, batch_size)?;
+        if location_relation == Relation::Out {
+            return Ok(0);
+        }
+        let filter = self.filter_expression(query)?;
+        let filter = match self.filter_relation(filter.as_ref(), node_id) {
+            Relation::Out => return Ok(0),
+            // All points match, so the values do not need to be checked.
+            Relation::In => None,
+            Relation::Cross => filter,
+        };
+        // The filter can refer to attributes that are only read for filtering, they are removed
+        // from the returned points again.
+        let mut attributes = query.attributes.clone();
+        let mut filter_attributes = Vec::new();
+        if let Some(filter) = &filter {
+            for attribute in filter.attributes() {
+                if !attributes.contains(&attribute) {
+                    attributes.push(attribute);
+                    filter_attributes.push(attribute.to_string());
+                }
+            }
+        }
+        let node_iterator = self.points_in_node(&attributes, node_id, batch_size)?;
+        let num_bytes = node_iterator.num_bytes();
+        let output_transform = query.output_transform;
+        let callback = move |mut batch: PointsBatch| {
+            for attribute in &filter_attributes {
+                batch.attributes.remove(attribute);
+            }
+            if let Some(output_transform) = &output_transform {
+                batch.transform(output_transform);
+            }
+            callback(batch)
+        };
 
+        if location_relation == Relation::In {
+            return stream(filter.as_ref(), node_iterator, callback, &AllPoints {})
+                .map(|_| num_bytes);
+        }
         dispatch_point_location!(
             stream,
             &query.location,
-            filter_intervals,
+            filter.as_ref(),
             node_iterator,
             callback
         )
+        .map(|_| num_bytes)
     }
 }
 
 // TODO(nnmm): Instead of having this helper function, make stream_points_for_query_in_node
 // accept a T: PointCulling, so we can dispatch to this function directly
 fn stream<'a, T: PointCulling<f64> + Clone, F: FnMut(PointsBatch) -> Result<()>>(
-    intv: &'a HashMap<&'a str, ClosedInterval<f64>>,
+    filter: Option<&'a FilterExpression>,
     itr: NodeIterator,
-    callback: F,
+    mut callback: F,
     culling: &T,
 ) -> Result<()> {
     let culling: T = culling.clone();
     FilteredIterator {
         culling,
-        filter_intervals: intv,
+        filter,
         node_iterator: itr,
     }
-    .try_for_each(callback)
+    .try_for_each(|batch| callback(batch?))
+}
+
+/// Follows the progress of a query and allows to cancel it, e.g. from another thread. A handle
+/// should only be used for a single query.
+#[derive(Debug, Default)]
+pub struct QueryHandle {
+    num_nodes: AtomicUsize,
+    num_nodes_completed: AtomicUsize,
+    num_points: AtomicUsize,
+    num_bytes_read: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl QueryHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of nodes to read, which is known once the query started.
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes.load(Ordering::SeqCst)
+    }
+
+    /// The number of nodes that were read or skipped.
+    pub fn num_nodes_completed(&self) -> usize {
+        self.num_nodes_completed.load(Ordering::SeqCst)
+    }
+
+    /// The number of points passed on so far.
+    pub fn num_points(&self) -> usize {
+        self.num_points.load(Ordering::SeqCst)
+    }
+
+    /// The number of bytes read from the data provider so far.
+    pub fn num_bytes_read(&self) -> usize {
+        self.num_bytes_read.load(Ordering::SeqCst)
+    }
+
+    /// Stops the query before it reads the next node, it then fails with `ErrorKind::Cancelled`.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn complete_node(&self, num_bytes: usize) {
+        self.num_bytes_read.fetch_add(num_bytes, Ordering::SeqCst);
+        self.num_nodes_completed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Counts the points of 'batch' before passing it on to 'func'.
+    fn count_and_call<F>(&self, batch: PointsBatch, func: &mut F) -> Result<()>
+    where
+        F: FnMut(PointsBatch) -> Result<()>,
+    {
+        self.num_points
+            .fetch_add(batch.position.len(), Ordering::SeqCst);
+        func(batch)
+    }
+
+    /// Turns a successful 'result' into an error if the query was stopped by `cancel` before
+    /// all nodes were read.
+    fn check_stopped_early(&self, result: Result<()>) -> Result<()> {
+        if result.is_ok() && self.is_cancelled() && self.num_nodes_completed() < self.num_nodes() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+        result
+    }
+}
+
+/// A node that was skipped because its points could not be read.
+#[derive(Clone, Debug)]
+pub struct SkippedNode {
+    pub node_id: String,
+    pub error: String,
+}
+
+/// Returns the query for every point cloud with its labels resolved, see `resolve_filter`.
+fn resolve_filters<'a, C: PointCloud>(
+    point_clouds: &[C],
+    point_query: &PointQuery<'a>,
+) -> Result<Vec<PointQuery<'a>>> {
+    point_clouds
+        .iter()
+        .map(|point_cloud| point_cloud.resolve_filter(point_query))
+        .collect()
+}
+
+/// The nodes to read for a query, shared by the worker threads of `ParallelIterator` and
+/// `PointsIterator`.
+struct Jobs<Id> {
+    queue: Injector<(usize, Id, Relation)>,
+    cancelled: AtomicBool,
+    skip_failing_nodes: bool,
+    skipped_nodes: Mutex<Vec<SkippedNode>>,
+    handle: Arc<QueryHandle>,
+    /// Notified whenever a worker sends a result or finishes, for polling `PointsStream`.
+    task: AtomicTask,
+}
+
+impl<Id: ToString + Send + Copy> Jobs<Id> {
+    /// 'point_queries' are the queries for every point cloud from `resolve_filters`.
+    fn new<C: PointCloud<Id = Id>>(
+        point_clouds: &[C],
+        point_queries: &[PointQuery],
+        skip_failing_nodes: bool,
+        handle: Arc<QueryHandle>,
+    ) -> Self {
+        let queue = Injector::new();
+        let mut num_nodes = 0;
+        for (index, (point_cloud, point_query)) in
+            point_clouds.iter().zip(point_queries).enumerate()
+        {
+            for (node_id, relation) in point_cloud.nodes_for_query(point_query) {
+                queue.push((index, node_id, relation));
+                num_nodes += 1;
+            }
+        }
+        handle.num_nodes.store(num_nodes, Ordering::SeqCst);
+        Jobs {
+            queue,
+            cancelled: AtomicBool::new(false),
+            skip_failing_nodes,
+            skipped_nodes: Mutex::new(Vec::new()),
+            handle,
+            task: AtomicTask::new(),
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.handle.is_cancelled()
+    }
+
+    /// Runs 'process_node' on the jobs until all jobs are done, and returns the number of bytes
+    /// it read. Fails with `ErrorKind::Cancelled` once the jobs are cancelled. A node error that
+    /// is not skipped cancels the remaining jobs and is returned, as is a channel error right
+    /// away, which means that nobody is listening anymore.
+    fn run<F>(&self, mut process_node: F) -> Result<()>
+    where
+        F: FnMut(usize, Id, Relation) -> Result<usize>,
+    {
+        let worker = Worker::new_fifo();
+
+        while let Some((index, node_id, relation)) = worker.pop().or_else(|| {
+            std::iter::repeat_with(|| self.queue.steal_batch_and_pop(&worker))
+                .find(|task| !task.is_retry())
+                .and_then(Steal::success)
+        }) {
+            if self.is_cancelled() {
+                return Err(ErrorKind::Cancelled.into());
+            }
+            match process_node(index, node_id, relation) {
+                Ok(num_bytes) => self.handle.complete_node(num_bytes),
+                Err(e @ Error(ErrorKind::Channel(_), _)) => return Err(e),
+                Err(e) => {
+                    if self.skip_failing_nodes {
+                        self.skipped_nodes.lock().unwrap().push(SkippedNode {
+                            node_id: node_id.to_string(),
+                            error: e.to_string(),
+                        });
+                        self.handle.complete_node(0);
+                    } else {
+                        self.cancel();
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the points of the jobs to 'tx' until all jobs are done, the jobs are cancelled or
+    /// the receiver is gone. A node error that is not skipped is sent as well and cancels the
+    /// remaining jobs.
+    fn work<C: PointCloud<Id = Id>>(
+        &self,
+        point_clouds: &[C],
+        point_queries: &[PointQuery],
+        batch_size: usize,
+        tx: &crossbeam::channel::Sender<Result<PointsBatch>>,
+    ) {
+        let send_func = |batch: PointsBatch| -> Result<()> {
+            let result = tx.send(Ok(batch));
+            self.task.notify();
+            result.map_err(|e| {
+                ErrorKind::Channel(format!(
+                    "Sending operation failed, nothing more to do {:?}",
+                    e
+                ))
+                .into()
+            })
+        };
+
+        // One `PointStream` per thread vs one per node allows to send more full point batches
+        let mut point_stream = PointStream::new(batch_size, &send_func);
+        let result = self.run(|index, node_id, relation| {
+            stream_node(
+                &point_clouds[index],
+                &point_queries[index],
+                (node_id, relation),
+                batch_size,
+                self.skip_failing_nodes,
+                &mut point_stream,
+            )
+        });
+        match result {
+            // last batch of points, which only fails if the receiver is gone
+            Ok(()) => {
+                let _ = point_stream.callback();
+            }
+            // done with the function computation
+            Err(Error(ErrorKind::Channel(_), _)) | Err(Error(ErrorKind::Cancelled, _)) => (),
+            Err(e) => {
+                // Nobody might be listening anymore, which is fine.
+                let _ = tx.send(Err(e));
+                self.task.notify();
+            }
+        }
+    }
 }
 
 /// Iterator on point batches
@@ -229,6 +745,10 @@ pub struct ParallelIterator<'a, C> {
     batch_size: usize,
     num_threads: usize,
     buffer_size: usize,
+    skip_failing_nodes: bool,
+    skipped_nodes: Vec<SkippedNode>,
+    ordered: bool,
+    handle: Arc<QueryHandle>,
 }
 
 impl<'a, C> ParallelIterator<'a, C>
@@ -248,87 +768,520 @@ where
             batch_size,
             num_threads,
             buffer_size,
+            skip_failing_nodes: false,
+            skipped_nodes: Vec::new(),
+            ordered: false,
+            handle: Arc::new(QueryHandle::new()),
+        }
+    }
+
+    /// Reports the progress of the iteration to 'handle', which can also cancel it.
+    pub fn query_handle(mut self, handle: Arc<QueryHandle>) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// By default, batches arrive in whatever order the threads finish them. With this option,
+    /// the batches are passed on in a stable order: by point cloud, then by node id, then in the
+    /// order of the points in the node. Nodes are still read in parallel, but at most one node
+    /// per thread is buffered.
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// By default, the first node that fails to be read aborts the iteration and its error is
+    /// returned. With this option, failing nodes are skipped instead and can be inspected with
+    /// `skipped_nodes` afterwards. None of the points of a skipped node are passed on, so every
+    /// node is read completely before its points are.
+    pub fn skip_failing_nodes(mut self, skip_failing_nodes: bool) -> Self {
+        self.skip_failing_nodes = skip_failing_nodes;
+        self
+    }
+
+    /// The nodes skipped during the last call to `try_for_each_batch` or `aggregate`.
+    pub fn skipped_nodes(&self) -> &[SkippedNode] {
+        &self.skipped_nodes
+    }
+
+    /// Aggregates the points matching the query without collecting them. Every thread aggregates
+    /// the nodes it takes from a shared queue, and the partial aggregates are merged in the end.
+    /// The attributes needed for the aggregations are read in addition to the attributes of the
+    /// query. Failing nodes are handled like in `try_for_each_batch`.
+    pub fn aggregate(&mut self, aggregations: &Aggregations) -> Result<Aggregate> {
+        let point_query = aggregations.query(self.point_query);
+        let point_queries = resolve_filters(self.point_clouds, &point_query)?;
+        let jobs = Jobs::new(
+            self.point_clouds,
+            &point_queries,
+            self.skip_failing_nodes,
+            Arc::clone(&self.handle),
+        );
+
+        let partial_aggregates = crossbeam::scope(|s| {
+            let threads: Vec<_> = (0..std::cmp::max(1, self.num_threads))
+                .map(|_| {
+                    let (jobs, point_queries) = (&jobs, &point_queries);
+                    let point_clouds = self.point_clouds;
+                    let batch_size = self.batch_size;
+                    s.spawn(move |_| {
+                        let mut partial_aggregate = PartialAggregate::default();
+                        jobs.run(|index, node_id, relation| {
+                            // The points of a node are only counted once all of them were read.
+                            let (node_aggregate, num_bytes) = aggregation::aggregate_node(
+                                &point_clouds[index],
+                                &point_queries[index],
+                                aggregations,
+                                node_id,
+                                relation,
+                                batch_size,
+                            )?;
+                            partial_aggregate.merge(node_aggregate);
+                            Ok(num_bytes)
+                        })
+                        .map(|_| partial_aggregate)
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("aggregate: Panic in child thread"))
+                .collect::<Vec<_>>()
+        })
+        .expect("ParallelIterator: Panic in aggregate child thread");
+
+        self.skipped_nodes = jobs.skipped_nodes.into_inner().unwrap();
+        let mut aggregate = PartialAggregate::default();
+        for partial_aggregate in partial_aggregates {
+            match partial_aggregate {
+                Ok(partial_aggregate) => aggregate.merge(partial_aggregate),
+                // Either another thread failed, or the query was cancelled, see below.
+                Err(Error(ErrorKind::Cancelled, _)) => (),
+                Err(e) => return Err(e),
+            }
         }
+        self.handle.check_stopped_early(Ok(()))?;
+        Ok(aggregate.finish())
     }
 
     /// compute a function while iterating on a batch of points
-    pub fn try_for_each_batch<F>(&mut self, func: F) -> Result<()>
+    pub fn try_for_each_batch<F>(&mut self, mut func: F) -> Result<()>
     where
         F: FnMut(PointsBatch) -> Result<()>,
     {
+        if self.ordered {
+            return self.try_for_each_batch_ordered(func);
+        }
+
         // get thread safe fifo
-        let jobs = Injector::<(&C, C::Id)>::new();
-        let mut number_of_jobs = 0;
-        self.point_clouds
-            .iter()
-            .flat_map(|point_cloud| {
-                std::iter::repeat(point_cloud)
-                    .zip(point_cloud.nodes_in_location(&self.point_query.location))
-            })
-            .for_each(|(node_id, point_cloud)| {
-                jobs.push((node_id, point_cloud));
-                number_of_jobs += 1;
-            });
+        let point_queries = resolve_filters(self.point_clouds, self.point_query)?;
+        let jobs = Jobs::new(
+            self.point_clouds,
+            &point_queries,
+            self.skip_failing_nodes,
+            Arc::clone(&self.handle),
+        );
 
         // operate on nodes with limited number of threads
-        crossbeam::scope(|s| {
-            let (tx, rx) = crossbeam::channel::bounded::<PointsBatch>(self.buffer_size);
-            for curr_thread in 0..self.num_threads {
+        let result = crossbeam::scope(|s| {
+            let (tx, rx) = crossbeam::channel::bounded::<Result<PointsBatch>>(self.buffer_size);
+            for _ in 0..self.num_threads {
                 let tx = tx.clone();
-                let point_query = &self.point_query;
+                let (jobs, point_queries) = (&jobs, &point_queries);
+                let point_clouds = self.point_clouds;
                 let batch_size = self.batch_size;
-                let worker = Worker::new_fifo();
-                let jobs = &jobs;
-
-                s.spawn(move |_| {
-                    let send_func = |batch: PointsBatch| match tx.send(batch) {
-                        Ok(_) => Ok(()),
-                        Err(e) => Err(ErrorKind::Channel(format!(
-                            "Thread {}: sending operation failed, nothing more to do {:?}",
-                            curr_thread, e,
-                        ))
-                        .into()),
-                    };
+                s.spawn(move |_| jobs.work(point_clouds, point_queries, batch_size, &tx));
+            }
+            // ensure to close the channel after the threads exit
+            drop(tx);
 
-                    // One `PointStream` per thread vs one per node allows to send more full point batches
-                    let mut point_stream = PointStream::new(batch_size, &send_func);
+            // receiver collects all the messages
+            let handle = &self.handle;
+            let result = rx
+                .iter()
+                .try_for_each(|batch| handle.count_and_call(batch?, &mut func));
+            // The remaining jobs are not needed if the iteration was aborted.
+            jobs.cancel();
+            result
+        })
+        .expect("ParallelIterator: Panic in try_for_each_batch child thread");
 
-                    while let Some((point_cloud, node_id)) = worker.pop().or_else(|| {
-                        std::iter::repeat_with(|| jobs.steal_batch_and_pop(&worker))
-                            .find(|task| !task.is_retry())
-                            .and_then(Steal::success)
-                    }) {
-                        // executing on the available next task if the function still requires it
-                        match point_cloud.stream_points_for_query_in_node(
-                            &point_query,
-                            node_id,
-                            batch_size,
-                            |batch| point_stream.push_points_and_callback(batch),
-                        ) {
-                            Ok(_) => continue,
-                            Err(ref e) => {
-                                match e.kind() {
-                                    ErrorKind::Channel(ref _s) => break, // done with the function computation
-                                    _ => panic!("ParallelIterator: Thread error {}", e), //some other error
-                                }
-                            }
-                        }
+        self.skipped_nodes = jobs.skipped_nodes.into_inner().unwrap();
+        self.handle.check_stopped_early(result)
+    }
+
+    /// Every node gets its own bounded channel, and the channels are read in order. The threads
+    /// take the nodes in the same order, so they can only be ahead by one node each.
+    fn try_for_each_batch_ordered<F>(&mut self, mut func: F) -> Result<()>
+    where
+        F: FnMut(PointsBatch) -> Result<()>,
+    {
+        let point_queries = resolve_filters(self.point_clouds, self.point_query)?;
+        let mut nodes: Vec<(usize, C::Id, Relation)> = Vec::new();
+        for (index, (point_cloud, point_query)) in
+            self.point_clouds.iter().zip(&point_queries).enumerate()
+        {
+            for (node_id, relation) in point_cloud.nodes_for_query(point_query) {
+                nodes.push((index, node_id, relation));
+            }
+        }
+        nodes.sort_by_key(|(index, node_id, _)| (*index, *node_id));
+        self.handle.num_nodes.store(nodes.len(), Ordering::SeqCst);
+        let (senders, receivers): (Vec<_>, Vec<_>) = nodes
+            .iter()
+            .map(|_| {
+                let (tx, rx) = crossbeam::channel::bounded::<Result<PointsBatch>>(self.buffer_size);
+                (Mutex::new(Some(tx)), rx)
+            })
+            .unzip();
+        let next_node = AtomicUsize::new(0);
+        // The index of the first node that failed. The nodes before it are still passed on
+        // completely, so only the nodes after it are skipped.
+        let first_failed_node = AtomicUsize::new(usize::MAX);
+        // Set once the receiving side stops, after which no node is needed anymore.
+        let aborted = AtomicBool::new(false);
+        let skipped_nodes: Mutex<Vec<SkippedNode>> = Mutex::new(Vec::new());
+
+        let result = crossbeam::scope(|s| {
+            for _ in 0..self.num_threads {
+                let (nodes, senders, next_node) = (&nodes, &senders, &next_node);
+                let (first_failed_node, aborted) = (&first_failed_node, &aborted);
+                let (skipped_nodes, handle) = (&skipped_nodes, &self.handle);
+                let (point_clouds, point_queries) = (self.point_clouds, &point_queries);
+                let batch_size = self.batch_size;
+                let skip_failing_nodes = self.skip_failing_nodes;
+                s.spawn(move |_| loop {
+                    let i = next_node.fetch_add(1, Ordering::SeqCst);
+                    if i >= nodes.len() {
+                        return;
                     }
-                    // last batch of points: calling callback
-                    if let Err(ref e) = point_stream.callback() {
-                        match e.kind() {
-                            ErrorKind::Channel(ref _s) => (), // done with the function computation
-                            _ => panic!("ParallelIterator: Thread error {}", e), //some other error
+                    // The channel is closed when 'tx' is dropped, which the receiving side waits
+                    // for. Every node is claimed by exactly one thread, so all channels are
+                    // closed eventually, even those of nodes that are skipped below.
+                    let tx = senders[i].lock().unwrap().take().unwrap();
+                    if i > first_failed_node.load(Ordering::SeqCst)
+                        || aborted.load(Ordering::SeqCst)
+                        || handle.is_cancelled()
+                    {
+                        continue;
+                    }
+                    let send_func = |batch: PointsBatch| -> Result<()> {
+                        tx.send(Ok(batch)).map_err(|e| {
+                            ErrorKind::Channel(format!(
+                                "Sending operation failed, nothing more to do {:?}",
+                                e
+                            ))
+                            .into()
+                        })
+                    };
+                    let mut point_stream = PointStream::new(batch_size, &send_func);
+                    let (index, node_id, relation) = nodes[i];
+                    match stream_node(
+                        &point_clouds[index],
+                        &point_queries[index],
+                        (node_id, relation),
+                        batch_size,
+                        skip_failing_nodes,
+                        &mut point_stream,
+                    )
+                    .and_then(|num_bytes| point_stream.callback().map(|_| num_bytes))
+                    {
+                        Ok(num_bytes) => handle.complete_node(num_bytes),
+                        // done with the function computation
+                        Err(Error(ErrorKind::Channel(_), _)) => return,
+                        Err(e) => {
+                            if skip_failing_nodes {
+                                skipped_nodes.lock().unwrap().push(SkippedNode {
+                                    node_id: node_id.to_string(),
+                                    error: e.to_string(),
+                                });
+                                handle.complete_node(0);
+                            } else {
+                                // The receiving side reports the error after passing on the
+                                // nodes before this one, which are all read completely.
+                                first_failed_node.fetch_min(i, Ordering::SeqCst);
+                                let _ = tx.send(Err(e));
+                            }
                         }
                     }
                 });
             }
-            // ensure to close the channel after the threads exit
-            drop(tx);
 
-            // receiver collects all the messages
-            rx.iter().try_for_each(func)
+            let handle = &self.handle;
+            let result = receivers.iter().try_for_each(|rx| {
+                rx.iter()
+                    .try_for_each(|batch| handle.count_and_call(batch?, &mut func))
+            });
+            // The remaining nodes are not needed if the iteration was aborted, and dropping the
+            // receivers unblocks the threads.
+            aborted.store(true, Ordering::SeqCst);
+            drop(receivers);
+            result
         })
-        .expect("ParallelIterator: Panic in try_for_each_batch child thread")
+        .expect("ParallelIterator: Panic in try_for_each_batch child thread");
+
+        self.skipped_nodes = skipped_nodes.into_inner().unwrap();
+        self.handle.check_stopped_early(result)
+    }
+}
+
+/// A `PointQuery` that owns its strings, so that it can be moved to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct OwnedPointQuery {
+    pub attributes: Vec<String>,
+    pub location: PointLocation,
+    pub filter_intervals: HashMap<String, ClosedInterval<f64>>,
+    pub filter_labels: HashMap<String, Vec<String>>,
+    pub filter: Option<FilterExpression>,
+    pub max_level: Option<u8>,
+    pub point_budget: Option<usize>,
+    pub output_transform: Option<Isometry3<f64>>,
+}
+
+impl OwnedPointQuery {
+    pub fn as_point_query(&self) -> PointQuery<'_> {
+        PointQuery {
+            attributes: self.attributes.iter().map(String::as_str).collect(),
+            location: self.location.clone(),
+            filter_intervals: self
+                .filter_intervals
+                .iter()
+                .map(|(attribute, interval)| (attribute.as_str(), *interval))
+                .collect(),
+            filter_labels: self
+                .filter_labels
+                .iter()
+                .map(|(attribute, labels)| {
+                    (
+                        attribute.as_str(),
+                        labels.iter().map(String::as_str).collect(),
+                    )
+                })
+                .collect(),
+            filter: self.filter.clone(),
+            max_level: self.max_level,
+            point_budget: self.point_budget,
+            output_transform: self.output_transform,
+        }
+    }
+}
+
+impl<'a> From<&PointQuery<'a>> for OwnedPointQuery {
+    fn from(query: &PointQuery<'a>) -> Self {
+        OwnedPointQuery {
+            attributes: query.attributes.iter().map(|a| a.to_string()).collect(),
+            location: query.location.clone(),
+            filter_intervals: query
+                .filter_intervals
+                .iter()
+                .map(|(attribute, interval)| (attribute.to_string(), *interval))
+                .collect(),
+            filter_labels: query
+                .filter_labels
+                .iter()
+                .map(|(attribute, labels)| {
+                    let labels = labels.iter().map(|label| label.to_string()).collect();
+                    (attribute.to_string(), labels)
+                })
+                .collect(),
+            filter: query.filter.clone(),
+            max_level: query.max_level,
+            point_budget: query.point_budget,
+            output_transform: query.output_transform,
+        }
+    }
+}
+
+/// An owned, pull-based iterator over the points matching a query, read by worker threads like
+/// in `ParallelIterator`. The bounded buffer between the workers and the iterator provides
+/// backpressure, and dropping the iterator cancels the remaining jobs. The iterator ends after
+/// the first error.
+pub struct PointsIterator<C: PointCloud> {
+    jobs: Arc<Jobs<C::Id>>,
+    receiver: crossbeam::channel::Receiver<Result<PointsBatch>>,
+    threads: Vec<JoinHandle<()>>,
+    done: bool,
+}
+
+impl<C> PointsIterator<C>
+where
+    C: PointCloud + Send + 'static,
+    C::Id: 'static,
+{
+    /// Starts reading the points, see `ParallelIterator::new` for the parameters and
+    /// `ParallelIterator::skip_failing_nodes` for the handling of failing nodes. The progress is
+    /// reported to 'handle', which can also cancel the iteration, see
+    /// `ParallelIterator::query_handle`. Fails if a label of the query is unknown.
+    pub fn new(
+        point_clouds: Arc<[C]>,
+        point_query: impl Into<OwnedPointQuery>,
+        batch_size: usize,
+        num_threads: usize,
+        buffer_size: usize,
+        skip_failing_nodes: bool,
+        handle: Arc<QueryHandle>,
+    ) -> Result<Self> {
+        let point_query: OwnedPointQuery = point_query.into();
+        let point_queries = resolve_filters(&*point_clouds, &point_query.as_point_query())?;
+        let jobs = Arc::new(Jobs::new(
+            &*point_clouds,
+            &point_queries,
+            skip_failing_nodes,
+            handle,
+        ));
+        let point_queries: Arc<[OwnedPointQuery]> = point_queries
+            .iter()
+            .map(OwnedPointQuery::from)
+            .collect::<Vec<_>>()
+            .into();
+        let (tx, receiver) = crossbeam::channel::bounded(buffer_size);
+        let threads = (0..num_threads)
+            .map(|_| {
+                let jobs = Arc::clone(&jobs);
+                let point_clouds = Arc::clone(&point_clouds);
+                let point_queries = Arc::clone(&point_queries);
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let point_queries: Vec<PointQuery> = point_queries
+                        .iter()
+                        .map(OwnedPointQuery::as_point_query)
+                        .collect();
+                    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        jobs.work(&*point_clouds, &point_queries, batch_size, &tx)
+                    }));
+                    // A polling stream needs to notice when the last worker closes the channel.
+                    drop(tx);
+                    jobs.task.notify();
+                    if let Err(panic) = result {
+                        std::panic::resume_unwind(panic);
+                    }
+                })
+            })
+            .collect();
+        Ok(PointsIterator {
+            jobs,
+            receiver,
+            threads,
+            done: false,
+        })
+    }
+
+    /// The nodes that were skipped so far.
+    pub fn skipped_nodes(&self) -> Vec<SkippedNode> {
+        self.jobs.skipped_nodes.lock().unwrap().clone()
+    }
+
+    /// Turns this into an asynchronous stream, see `PointsStream`.
+    pub fn into_stream(self) -> PointsStream<C> {
+        PointsStream { points: self }
+    }
+}
+
+impl<C: PointCloud> PointsIterator<C> {
+    /// Handles a result from the workers. Returns None once all workers are finished.
+    fn handle_result(
+        &mut self,
+        result: std::result::Result<Result<PointsBatch>, crossbeam::channel::RecvError>,
+    ) -> Option<Result<PointsBatch>> {
+        match result {
+            Ok(Ok(batch)) => {
+                self.jobs
+                    .handle
+                    .num_points
+                    .fetch_add(batch.position.len(), Ordering::SeqCst);
+                Some(Ok(batch))
+            }
+            Ok(Err(e)) => {
+                self.done = true;
+                self.jobs.cancel();
+                Some(Err(e))
+            }
+            // All workers are finished.
+            Err(_) => {
+                self.done = true;
+                let mut panicked = false;
+                for thread in self.threads.drain(..) {
+                    panicked |= thread.join().is_err();
+                }
+                if panicked {
+                    Some(Err("PointsIterator: Panic in worker thread".into()))
+                } else {
+                    self.jobs.handle.check_stopped_early(Ok(())).err().map(Err)
+                }
+            }
+        }
+    }
+}
+
+impl<C: PointCloud> Iterator for PointsIterator<C> {
+    type Item = Result<PointsBatch>;
+
+    fn next(&mut self) -> Option<Result<PointsBatch>> {
+        if self.done {
+            return None;
+        }
+        let result = self.receiver.recv();
+        self.handle_result(result)
+    }
+}
+
+impl<C: PointCloud> Drop for PointsIterator<C> {
+    fn drop(&mut self) {
+        // Workers blocked on the full buffer stop as soon as the receiver is dropped.
+        self.jobs.cancel();
+    }
+}
+
+/// A `futures::Stream` over the points matching a query. It is polled from the buffer of a
+/// `PointsIterator`, whose workers wake up the task, so backpressure and cancellation on drop
+/// carry over.
+pub struct PointsStream<C: PointCloud> {
+    points: PointsIterator<C>,
+}
+
+impl<C: PointCloud> PointsStream<C> {
+    /// The nodes that were skipped so far.
+    pub fn skipped_nodes(&self) -> Vec<SkippedNode> {
+        self.points.jobs.skipped_nodes.lock().unwrap().clone()
+    }
+}
+
+impl<C: PointCloud> Stream for PointsStream<C> {
+    type Item = PointsBatch;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<PointsBatch>, Error> {
+        if self.points.done {
+            return Ok(Async::Ready(None));
+        }
+        // Registering before looking at the buffer makes sure that no notification is missed.
+        self.points.jobs.task.register();
+        let result = match self.points.receiver.try_recv() {
+            Ok(result) => Ok(result),
+            Err(crossbeam::channel::TryRecvError::Empty) => return Ok(Async::NotReady),
+            Err(crossbeam::channel::TryRecvError::Disconnected) => {
+                Err(crossbeam::channel::RecvError)
+            }
+        };
+        match self.points.handle_result(result) {
+            Some(Ok(batch)) => Ok(Async::Ready(Some(batch))),
+            Some(Err(e)) => Err(e),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_query_without_labels() {
+        let query: PointQuery = serde_json::from_str(
+            r#"{"attributes": ["intensity"], "location": "AllPoints", "filter_intervals": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(query.attributes, vec!["intensity"]);
+        assert!(query.filter_labels.is_empty());
     }
 }
@@ -17,6 +17,7 @@
 #[macro_use]
 pub mod math;
 
+pub mod aggregation;
 #[macro_use]
 pub mod attributes;
 pub mod color;
@@ -24,16 +25,20 @@ pub mod data_provider;
 // Workaround for https://github.com/rust-lang-nursery/error-chain/issues/254
 #[allow(deprecated)]
 pub mod errors;
+pub mod filter;
 pub mod geometry;
 #[macro_use]
 pub mod iterator;
+pub mod nearest_neighbors;
 pub mod octree;
+pub mod picking;
 pub mod read_write;
 pub mod s2_cells;
+pub mod statistics;
 pub mod utils;
 
 use errors::Result;
-use nalgebra::Point3;
+use nalgebra::{Isometry3, Point3};
 use std::collections::{BTreeMap, HashMap};
 use std::convert::{TryFrom, TryInto};
 
@@ -47,6 +52,8 @@ use std::convert::{TryFrom, TryInto};
 // We are able to convert the proto on read, so the tools can still read version 9/10/11/12.
 pub const CURRENT_VERSION: i32 = 13;
 pub const META_FILENAME: &str = "meta.pb";
+/// Present in a point cloud directory while it is being built.
+pub const BUILD_IN_PROGRESS_FILENAME: &str = "BUILD_IN_PROGRESS";
 
 /// size for batch
 pub const NUM_POINTS_PER_BATCH: usize = 500_000;
@@ -150,6 +157,34 @@ impl PointsBatch {
         }
     }
 
+    /// Transforms the positions of all points, e.g. into a local frame.
+    pub fn transform(&mut self, isometry: &Isometry3<f64>) {
+        for p in &mut self.position {
+            *p = isometry.transform_point(p);
+        }
+    }
+
+    /// Returns the points at 'indices', in this order.
+    pub fn select(&self, indices: &[usize]) -> Self {
+        let position = indices.iter().map(|i| self.position[*i]).collect();
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|(name, data)| {
+                macro_rules! rhs {
+                    ($dtype:ident, $data:ident, $indices:ident) => {
+                        AttributeData::$dtype($indices.iter().map(|i| $data[*i]).collect())
+                    };
+                }
+                (name.clone(), match_attr_data!(data, rhs, indices))
+            })
+            .collect();
+        Self {
+            position,
+            attributes,
+        }
+    }
+
     pub fn get_attribute_vec<'a, T>(
         &'a self,
         key: impl AsRef<str>,
@@ -12,8 +12,9 @@ where
 
 /// Something that can perform an intersection test with an AABB.
 pub trait IntersectAabb<S: RealField> {
-    // TODO(nnmm): return Relation
-    fn intersect_aabb(&self, aabb: &Aabb<S>) -> bool;
+    /// Returns whether the AABB is inside, crosses or is outside of this geometry. Implementations
+    /// may return `Cross` when they cannot cheaply tell that the AABB is completely inside.
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation;
 }
 
 /// We use this trait to allow an indirection: The geometry itself does not need to be able to
@@ -31,8 +32,15 @@ pub trait HasAabbIntersector<'a, S: RealField> {
 }
 
 impl<S: RealField + Bounded> IntersectAabb<S> for CachedAxesIntersector<S> {
-    fn intersect_aabb(&self, aabb: &Aabb<S>) -> bool {
-        self.intersect(&aabb.compute_corners()) != Relation::Out
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        self.intersect(&aabb.compute_corners())
+    }
+}
+
+/// Allows choosing the intersector at runtime, e.g. for the parts of a composite location.
+impl<S: RealField, T: IntersectAabb<S> + ?Sized> IntersectAabb<S> for Box<T> {
+    fn intersect_aabb(&self, aabb: &Aabb<S>) -> Relation {
+        (**self).intersect_aabb(aabb)
     }
 }
 
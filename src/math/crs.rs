@@ -0,0 +1,342 @@
+//! Coordinate reference systems of point positions and conversions between them.
+
+use crate::errors::*;
+use crate::math::local_frame_from_lat_lng;
+use crate::proto;
+use nalgebra::Point3;
+use nav_types::{ECEF, WGS84};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Semi-major axis of the WGS84 ellipsoid.
+const WGS84_A: f64 = 6_378_137.0;
+/// Flattening of the WGS84 ellipsoid.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Scale factor on the central meridian of a UTM zone.
+const UTM_K0: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500_000.0;
+/// Added to the northing on the southern hemisphere to keep it positive.
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+/// The frame that positions are given in. All of them use meters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Crs {
+    /// Earth-centered, earth-fixed.
+    Ecef,
+    /// Easting, northing and height above the WGS84 ellipsoid in a UTM zone from 1 to 60.
+    Utm { zone: u8, north: bool },
+    /// East, north, up relative to a point on the WGS84 ellipsoid given in degrees, like the
+    /// frame of `local_frame_from_lat_lng`.
+    LocalEnu { latitude: f64, longitude: f64 },
+}
+
+/// The coefficients of the Krüger series of the transverse Mercator projection, see
+/// https://en.wikipedia.org/wiki/Universal_Transverse_Mercator_coordinate_system#Simplified_formulae
+struct TransverseMercator {
+    /// Radius of the rectifying sphere.
+    a: f64,
+    eccentricity: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+impl TransverseMercator {
+    fn wgs84() -> Self {
+        let n = WGS84_F / (2.0 - WGS84_F);
+        let (n2, n3) = (n * n, n * n * n);
+        TransverseMercator {
+            a: WGS84_A / (1.0 + n) * (1.0 + n2 / 4.0 + n2 * n2 / 64.0),
+            eccentricity: 2.0 * n.sqrt() / (1.0 + n),
+            alpha: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0,
+                13.0 * n2 / 48.0 - 3.0 * n3 / 5.0,
+                61.0 * n3 / 240.0,
+            ],
+            beta: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0,
+                n2 / 48.0 + n3 / 15.0,
+                17.0 * n3 / 480.0,
+            ],
+            delta: [
+                2.0 * n - 2.0 * n2 / 3.0 - 2.0 * n3,
+                7.0 * n2 / 3.0 - 8.0 * n3 / 5.0,
+                56.0 * n3 / 15.0,
+            ],
+        }
+    }
+}
+
+/// The longitude of the central meridian of a UTM zone in radians.
+fn utm_central_meridian(zone: u8) -> f64 {
+    (f64::from(zone) * 6.0 - 183.0).to_radians()
+}
+
+/// Returns the UTM zone and hemisphere that contain 'lat_lng', ignoring the exceptions around
+/// Norway and Svalbard.
+pub fn utm_zone(lat_lng: &WGS84<f64>) -> (u8, bool) {
+    let zone = ((lat_lng.longitude_degrees() + 180.0) / 6.0).floor() as i64 % 60 + 1;
+    (zone as u8, lat_lng.latitude() >= 0.0)
+}
+
+/// Projects 'lat_lng' into the given UTM zone. The z coordinate is the altitude.
+pub fn utm_from_wgs84(lat_lng: &WGS84<f64>, zone: u8, north: bool) -> Point3<f64> {
+    let tm = TransverseMercator::wgs84();
+    let longitude = lat_lng.longitude() - utm_central_meridian(zone);
+    let sin_latitude = lat_lng.latitude().sin();
+    let t =
+        (sin_latitude.atanh() - tm.eccentricity * (tm.eccentricity * sin_latitude).atanh()).sinh();
+    let xi = (t / longitude.cos()).atan();
+    let eta = (longitude.sin() / (1.0 + t * t).sqrt()).atanh();
+    let (mut easting, mut northing) = (eta, xi);
+    for (j, alpha) in tm.alpha.iter().enumerate() {
+        let k = 2.0 * (j + 1) as f64;
+        easting += alpha * (k * xi).cos() * (k * eta).sinh();
+        northing += alpha * (k * xi).sin() * (k * eta).cosh();
+    }
+    let false_northing = if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH };
+    Point3::new(
+        UTM_FALSE_EASTING + UTM_K0 * tm.a * easting,
+        false_northing + UTM_K0 * tm.a * northing,
+        lat_lng.altitude(),
+    )
+}
+
+/// The inverse of `utm_from_wgs84`.
+pub fn wgs84_from_utm(p: &Point3<f64>, zone: u8, north: bool) -> WGS84<f64> {
+    let tm = TransverseMercator::wgs84();
+    let false_northing = if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH };
+    let xi = (p.y - false_northing) / (UTM_K0 * tm.a);
+    let eta = (p.x - UTM_FALSE_EASTING) / (UTM_K0 * tm.a);
+    let (mut xi_prime, mut eta_prime) = (xi, eta);
+    for (j, beta) in tm.beta.iter().enumerate() {
+        let k = 2.0 * (j + 1) as f64;
+        xi_prime -= beta * (k * xi).sin() * (k * eta).cosh();
+        eta_prime -= beta * (k * xi).cos() * (k * eta).sinh();
+    }
+    let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+    let mut latitude = chi;
+    for (j, delta) in tm.delta.iter().enumerate() {
+        latitude += delta * (2.0 * (j + 1) as f64 * chi).sin();
+    }
+    let longitude = utm_central_meridian(zone) + (eta_prime.sinh() / xi_prime.cos()).atan();
+    WGS84::new(latitude.to_degrees(), longitude.to_degrees(), p.z)
+}
+
+impl Crs {
+    /// Converts 'p' from this frame to ECEF.
+    pub fn point_to_ecef(&self, p: &Point3<f64>) -> Point3<f64> {
+        match self {
+            Crs::Ecef => *p,
+            Crs::Utm { zone, north } => {
+                let ecef = ECEF::from(wgs84_from_utm(p, *zone, *north));
+                Point3::new(ecef.x(), ecef.y(), ecef.z())
+            }
+            Crs::LocalEnu {
+                latitude,
+                longitude,
+            } => local_frame_from_lat_lng(*latitude, *longitude).inverse_transform_point(p),
+        }
+    }
+
+    /// Converts 'p' from ECEF to this frame.
+    pub fn point_from_ecef(&self, p: &Point3<f64>) -> Point3<f64> {
+        match self {
+            Crs::Ecef => *p,
+            Crs::Utm { zone, north } => {
+                utm_from_wgs84(&WGS84::from(ECEF::new(p.x, p.y, p.z)), *zone, *north)
+            }
+            Crs::LocalEnu {
+                latitude,
+                longitude,
+            } => local_frame_from_lat_lng(*latitude, *longitude).transform_point(p),
+        }
+    }
+
+    /// Converts 'p' from this frame to 'target'.
+    pub fn transform_to(&self, target: &Crs, p: &Point3<f64>) -> Point3<f64> {
+        if self == target {
+            return *p;
+        }
+        target.point_from_ecef(&self.point_to_ecef(p))
+    }
+
+    pub fn to_proto(self) -> proto::CoordinateReferenceSystem {
+        let mut crs = proto::CoordinateReferenceSystem::new();
+        match self {
+            Crs::Ecef => crs.set_kind(proto::CrsKind::ECEF),
+            Crs::Utm { zone, north } => {
+                crs.set_kind(proto::CrsKind::UTM);
+                crs.set_utm_zone(u32::from(zone));
+                crs.set_utm_north(north);
+            }
+            Crs::LocalEnu {
+                latitude,
+                longitude,
+            } => {
+                crs.set_kind(proto::CrsKind::LOCAL_ENU);
+                crs.set_origin_latitude(latitude);
+                crs.set_origin_longitude(longitude);
+            }
+        }
+        crs
+    }
+
+    /// Returns None if the proto does not specify a frame.
+    pub fn from_proto(crs: &proto::CoordinateReferenceSystem) -> Result<Option<Self>> {
+        let crs = match crs.kind {
+            proto::CrsKind::UNKNOWN_CRS => return Ok(None),
+            proto::CrsKind::ECEF => Crs::Ecef,
+            proto::CrsKind::UTM => {
+                if !(1..=60).contains(&crs.utm_zone) {
+                    return Err(ErrorKind::InvalidInput(format!(
+                        "Invalid UTM zone {}",
+                        crs.utm_zone
+                    ))
+                    .into());
+                }
+                Crs::Utm {
+                    zone: crs.utm_zone as u8,
+                    north: crs.utm_north,
+                }
+            }
+            proto::CrsKind::LOCAL_ENU => Crs::LocalEnu {
+                latitude: crs.origin_latitude,
+                longitude: crs.origin_longitude,
+            },
+        };
+        Ok(Some(crs))
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Crs::Ecef => write!(f, "ecef"),
+            Crs::Utm { zone, north } => write!(f, "utm:{}{}", zone, if *north { 'N' } else { 'S' }),
+            Crs::LocalEnu {
+                latitude,
+                longitude,
+            } => write!(f, "enu:{},{}", latitude, longitude),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseCrsError(String);
+
+impl std::error::Error for ParseCrsError {}
+
+impl fmt::Display for ParseCrsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parses "ecef", UTM zones like "utm:10N" and local frames like "enu:37.4,-122.1".
+impl FromStr for Crs {
+    type Err = ParseCrsError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || ParseCrsError(format!("Invalid coordinate reference system '{}'.", s));
+        if s == "ecef" {
+            return Ok(Crs::Ecef);
+        }
+        if s.starts_with("utm:") {
+            let zone = &s[4..];
+            let north = match zone.chars().last() {
+                Some('N') => true,
+                Some('S') => false,
+                _ => return Err(invalid()),
+            };
+            let zone: u8 = zone[..zone.len() - 1].parse().map_err(|_| invalid())?;
+            if !(1..=60).contains(&zone) {
+                return Err(invalid());
+            }
+            return Ok(Crs::Utm { zone, north });
+        }
+        if s.starts_with("enu:") {
+            let coords = s[4..]
+                .split(',')
+                .map(|c| c.trim().parse::<f64>().map_err(|_| invalid()))
+                .collect::<std::result::Result<Vec<f64>, _>>()?;
+            if coords.len() != 2 {
+                return Err(invalid());
+            }
+            return Ok(Crs::LocalEnu {
+                latitude: coords[0],
+                longitude: coords[1],
+            });
+        }
+        Err(invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Point3<f64>, b: &Point3<f64>, tolerance: f64) {
+        assert!(nalgebra::distance(a, b) < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_utm_known_points() {
+        // On the central meridian of zone 31 at the equator.
+        let utm = utm_from_wgs84(&WGS84::new(0.0, 3.0, 0.0), 31, true);
+        assert_close(&utm, &Point3::new(500_000.0, 0.0, 0.0), 1e-6);
+        // On the central meridian, the northing is the scaled meridian arc.
+        let utm = utm_from_wgs84(&WGS84::new(45.0, 3.0, 0.0), 31, true);
+        assert_close(
+            &utm,
+            &Point3::new(500_000.0, UTM_K0 * 4_984_944.378, 0.0),
+            1e-2,
+        );
+        assert_eq!(utm_zone(&WGS84::new(37.4, -122.1, 0.0)), (10, true));
+        assert_eq!(utm_zone(&WGS84::new(-33.9, 151.2, 0.0)), (56, false));
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let ecef = ECEF::from(WGS84::new(37.407204, -122.147604, 30.0));
+        let ecef = Point3::new(ecef.x(), ecef.y(), ecef.z());
+        let frames = [
+            Crs::Ecef,
+            Crs::Utm {
+                zone: 10,
+                north: true,
+            },
+            Crs::Utm {
+                zone: 10,
+                north: false,
+            },
+            Crs::LocalEnu {
+                latitude: 37.4,
+                longitude: -122.1,
+            },
+        ];
+        // The UTM series are accurate to about a millimeter.
+        for crs in &frames {
+            assert_close(&crs.point_to_ecef(&crs.point_from_ecef(&ecef)), &ecef, 1e-3);
+            assert_eq!(Crs::from_proto(&crs.to_proto()).unwrap(), Some(*crs));
+            assert_eq!(crs.to_string().parse::<Crs>().unwrap(), *crs);
+        }
+        // The origin of a local frame is at zero.
+        let local = Crs::LocalEnu {
+            latitude: 37.4,
+            longitude: -122.1,
+        };
+        let utm = Crs::Utm {
+            zone: 10,
+            north: true,
+        };
+        let origin = local.transform_to(&utm, &Point3::origin());
+        assert_close(&utm.transform_to(&local, &origin), &Point3::origin(), 1e-3);
+        assert_close(
+            &origin,
+            &utm_from_wgs84(&WGS84::new(37.4, -122.1, 0.0), 10, true),
+            1e-3,
+        );
+    }
+}
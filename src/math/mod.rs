@@ -21,9 +21,11 @@ use std::str::FromStr;
 
 #[macro_use]
 pub mod base;
+pub mod crs;
 pub mod sat;
 pub mod web_mercator;
 pub use base::*;
+pub use crs::*;
 pub use sat::*;
 pub use web_mercator::*;
 
@@ -63,7 +65,7 @@ impl From<std::num::ParseFloatError> for ParseClosedIntervalError {
 
 /// An interval, intended to be read from a command line argument
 /// and to be used in filtering the point cloud via an attribute.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct ClosedInterval<T> {
     lower_bound: T,
     upper_bound: T,
@@ -89,6 +91,41 @@ where
     }
 }
 
+impl<T> ClosedInterval<T>
+where
+    T: PartialOrd + Copy,
+{
+    pub fn lower_bound(self) -> T {
+        self.lower_bound
+    }
+
+    pub fn upper_bound(self) -> T {
+        self.upper_bound
+    }
+
+    /// Grows the interval so that it contains 'value'.
+    pub fn extend(&mut self, value: T) {
+        if value < self.lower_bound {
+            self.lower_bound = value;
+        }
+        if value > self.upper_bound {
+            self.upper_bound = value;
+        }
+    }
+
+    /// Returns whether this interval lies completely inside, partially inside or outside of
+    /// 'other'.
+    pub fn relation_to(self, other: ClosedInterval<T>) -> Relation {
+        if self.upper_bound < other.lower_bound || other.upper_bound < self.lower_bound {
+            Relation::Out
+        } else if other.lower_bound <= self.lower_bound && self.upper_bound <= other.upper_bound {
+            Relation::In
+        } else {
+            Relation::Cross
+        }
+    }
+}
+
 impl<T> FromStr for ClosedInterval<T>
 where
     T: std::str::FromStr,
@@ -141,8 +178,8 @@ impl<S: RealField> FromPoint3<S> for ECEF<S> {
 pub struct AllPoints {}
 
 impl<S: RealField> IntersectAabb<S> for AllPoints {
-    fn intersect_aabb(&self, _aabb: &Aabb<S>) -> bool {
-        true
+    fn intersect_aabb(&self, _aabb: &Aabb<S>) -> Relation {
+        Relation::In
     }
 }
 
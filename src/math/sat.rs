@@ -168,11 +168,32 @@ impl<S: RealField + Bounded> CachedAxesIntersector<S> {
     pub fn intersect(&self, corners: &[Point3<S>]) -> Relation {
         sat(self.axes.iter().cloned(), &self.corners, corners)
     }
+
+    /// Like [`intersect`](#method.intersect), but the other object is only reported to be inside
+    /// if it does not touch the boundary of the self object. Use this for objects that do not
+    /// contain the points on their boundary.
+    pub fn intersect_interior(&self, corners: &[Point3<S>]) -> Relation {
+        sat_impl(self.axes.iter().cloned(), &self.corners, corners, true)
+    }
 }
 
 /// See https://www.gamedev.net/forums/topic/694911-separating-axis-theorem-3d-polygons/ for more detail
 /// Return `Relation::In` if B is contained in A
 pub fn sat<S, I>(separating_axes: I, corners_a: &[Point3<S>], corners_b: &[Point3<S>]) -> Relation
+where
+    S: RealField + Bounded,
+    I: IntoIterator<Item = Unit<Vector3<S>>>,
+{
+    sat_impl(separating_axes, corners_a, corners_b, false)
+}
+
+/// If 'exclude_boundary' is set, B touching the boundary of A is not considered to be inside.
+fn sat_impl<S, I>(
+    separating_axes: I,
+    corners_a: &[Point3<S>],
+    corners_b: &[Point3<S>],
+    exclude_boundary: bool,
+) -> Relation
 where
     S: RealField + Bounded,
     I: IntoIterator<Item = Unit<Vector3<S>>>,
@@ -188,7 +209,12 @@ where
             return Relation::Out;
         }
         // If B is not inside A wrt that axis, the only choice is between Cross and Out.
-        if a_min_proj > b_min_proj || b_max_proj > a_max_proj {
+        let not_inside = if exclude_boundary {
+            a_min_proj >= b_min_proj || b_max_proj >= a_max_proj
+        } else {
+            a_min_proj > b_min_proj || b_max_proj > a_max_proj
+        };
+        if not_inside {
             rel = Relation::Cross;
         }
     }
@@ -0,0 +1,134 @@
+//! Nearest neighbor and radius search in point clouds.
+
+use crate::errors::*;
+use crate::geometry::Sphere;
+use crate::iterator::{PointCloud, PointLocation};
+use crate::PointsBatch;
+use nalgebra::Point3;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// The result of a nearest neighbor search, sorted by increasing distance.
+#[derive(Debug, Clone)]
+pub struct Neighbors {
+    pub points: PointsBatch,
+    /// The distance of each point in 'points' to the query point.
+    pub distances: Vec<f64>,
+}
+
+impl Neighbors {
+    fn new() -> Self {
+        Neighbors {
+            points: PointsBatch {
+                position: Vec::new(),
+                attributes: BTreeMap::new(),
+            },
+            distances: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.distances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+
+    /// Sorts the neighbors by distance and keeps only the 'k' closest.
+    fn sort_and_truncate(&mut self, k: usize) {
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        indices.sort_by(|a, b| {
+            self.distances[*a]
+                .partial_cmp(&self.distances[*b])
+                .unwrap_or(Ordering::Equal)
+        });
+        indices.truncate(k);
+        self.points = self.points.select(&indices);
+        self.distances = indices.iter().map(|i| self.distances[*i]).collect();
+    }
+
+    /// Adds the points of 'batch' that may be among the 'k' nearest neighbors of 'point'.
+    fn add(&mut self, mut batch: PointsBatch, point: &Point3<f64>, k: usize, max_distance: f64) {
+        // Once there are 'k' neighbors, they are sorted and the last one is the one to beat.
+        let worst_distance = if self.len() >= k {
+            self.distances[self.len() - 1]
+        } else {
+            max_distance
+        };
+        let distances: Vec<f64> = batch
+            .position
+            .iter()
+            .map(|p| nalgebra::distance(p, point))
+            .collect();
+        let keep: Vec<bool> = distances.iter().map(|d| *d <= worst_distance).collect();
+        batch.retain(&keep);
+        self.distances
+            .extend(distances.into_iter().filter(|d| *d <= worst_distance));
+        self.points
+            .append(&mut batch)
+            .expect("Batches of the same point cloud must have the same attributes.");
+        if self.len() >= k {
+            self.sort_and_truncate(k);
+        }
+    }
+}
+
+/// Finds the 'k' points of 'point_cloud' closest to 'point' that are at most 'max_distance'
+/// away, with the given attributes. Nodes are read in order of increasing distance to 'point'
+/// until no node can contain a closer point, so usually only a few nodes are read.
+///
+/// With an infinite 'max_distance' this is a k-nearest-neighbor search, with 'k' set to
+/// `usize::MAX` it is a radius search.
+pub fn nearest_neighbors<C: PointCloud + ?Sized>(
+    point_cloud: &C,
+    point: &Point3<f64>,
+    k: usize,
+    max_distance: f64,
+    attributes: &[&str],
+) -> Result<Neighbors> {
+    let mut neighbors = Neighbors::new();
+    if k == 0 || max_distance < 0. {
+        return Ok(neighbors);
+    }
+    let location = if max_distance.is_finite() {
+        PointLocation::Sphere(Sphere::new(*point, max_distance))
+    } else {
+        PointLocation::AllPoints
+    };
+    let mut nodes: Vec<(f64, C::Id)> = point_cloud
+        .nodes_in_location(&location)
+        .into_iter()
+        .map(|(node_id, _)| (point_cloud.distance_to_node(node_id, point), node_id))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    nodes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    for (node_distance, node_id) in nodes {
+        if neighbors.len() >= k && node_distance > neighbors.distances[neighbors.len() - 1] {
+            break;
+        }
+        let mut node_iterator =
+            point_cloud.points_in_node(attributes, node_id, crate::NUM_POINTS_PER_BATCH)?;
+        while let Some(batch) = node_iterator.try_next()? {
+            neighbors.add(batch, point, k, max_distance);
+        }
+    }
+    neighbors.sort_and_truncate(k);
+    Ok(neighbors)
+}
+
+/// Like `nearest_neighbors`, for many query points at once. The searches run in parallel.
+pub fn nearest_neighbors_batch<C: PointCloud + ?Sized>(
+    point_cloud: &C,
+    points: &[Point3<f64>],
+    k: usize,
+    max_distance: f64,
+    attributes: &[&str],
+) -> Result<Vec<Neighbors>> {
+    points
+        .par_iter()
+        .map(|point| nearest_neighbors(point_cloud, point, k, max_distance, attributes))
+        .collect()
+}
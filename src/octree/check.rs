@@ -0,0 +1,347 @@
+//! Verifying that the data of an octree on disk matches its meta.
+
+use crate::attributes::AttributeRanges;
+use crate::data_provider::OnDiskDataProvider;
+use crate::errors::*;
+use crate::geometry::Aabb;
+use crate::iterator::PointCloud;
+use crate::octree::{to_meta_proto, to_node_proto, NodeId, Octree};
+use crate::read_write::write_meta;
+use crate::{attribute_extension, PointCloudMeta, META_FILENAME};
+use fnv::{FnvHashMap, FnvHashSet};
+use nalgebra::Vector3;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const BATCH_SIZE: usize = 100_000;
+
+/// An inconsistency between the meta of an octree and its data.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Problem {
+    /// A node in the meta is missing the file for 'attribute'.
+    MissingFile { node: String, attribute: String },
+    /// The size of an attribute file does not match the number of points in the meta.
+    FileSizeMismatch {
+        node: String,
+        attribute: String,
+        expected_bytes: u64,
+        actual_bytes: u64,
+    },
+    /// Some points of the node decode to positions outside of its bounding cube.
+    PositionsOutsideNode { node: String, num_points: u64 },
+    /// The parent of a node is not in the meta, so the node cannot be reached from the root.
+    MissingParent { node: String, parent: String },
+    /// A file in the octree directory that does not belong to any node in the meta.
+    OrphanFile { file: String },
+}
+
+/// The result of checking an octree.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CheckReport {
+    pub num_nodes: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Sizes of the attribute files of a node, or None if a file does not exist.
+struct NodeFiles {
+    sizes: Vec<(String, Option<u64>)>,
+}
+
+fn open_octree(directory: &Path) -> Result<Octree> {
+    Octree::from_data_provider(Box::new(OnDiskDataProvider::new(directory.to_path_buf())))
+}
+
+/// Returns the attributes every node of 'octree' has to have: Positions are required, and the
+/// attributes of the meta are expected if any node has them, since the meta does not record which
+/// of them were built.
+fn expected_attributes(octree: &Octree, directory: &Path) -> Vec<String> {
+    let mut attributes = vec!["position".to_string()];
+    let mut names: Vec<&String> = octree.meta.attribute_data_types().keys().collect();
+    names.sort();
+    for name in names {
+        let exists = octree.nodes.keys().any(|id| {
+            directory
+                .join(id.to_string())
+                .with_extension(attribute_extension(name))
+                .exists()
+        });
+        if exists {
+            attributes.push(name.clone());
+        }
+    }
+    attributes
+}
+
+fn bytes_per_point(octree: &Octree, id: &NodeId, attribute: &str) -> u64 {
+    if attribute == "position" {
+        3 * octree.nodes[id].position_encoding.bytes_per_coordinate() as u64
+    } else {
+        octree.meta.attribute_data_types()[attribute].size_of() as u64
+    }
+}
+
+fn node_files(directory: &Path, id: &NodeId, attributes: &[String]) -> NodeFiles {
+    let sizes = attributes
+        .iter()
+        .map(|attribute| {
+            let path = directory
+                .join(id.to_string())
+                .with_extension(attribute_extension(attribute));
+            (
+                attribute.clone(),
+                fs::metadata(path).ok().map(|metadata| metadata.len()),
+            )
+        })
+        .collect();
+    NodeFiles { sizes }
+}
+
+/// Returns the number of points that decode to a position outside of the node's bounding cube.
+fn count_positions_outside(octree: &Octree, id: NodeId) -> Result<u64> {
+    let node_meta = &octree.nodes[&id];
+    // Allow for rounding errors in the encoding.
+    let slack = Vector3::repeat(octree.meta.resolution);
+    let bounds = Aabb::new(
+        node_meta.bounding_cube.min() - slack,
+        node_meta.bounding_cube.max() + slack,
+    );
+    let mut num_outside = 0;
+    for batch in octree.points_in_node(&[], id, BATCH_SIZE)? {
+        num_outside += batch
+            .position
+            .iter()
+            .filter(|p| !bounds.contains(p))
+            .count() as u64;
+    }
+    Ok(num_outside)
+}
+
+fn check_node(
+    octree: &Octree,
+    directory: &Path,
+    id: NodeId,
+    attributes: &[String],
+    problems: &mut Vec<Problem>,
+) -> Result<()> {
+    let node = id.to_string();
+    let num_points = octree.nodes[&id].num_points as u64;
+    let mut files_ok = true;
+    for (attribute, size) in node_files(directory, &id, attributes).sizes {
+        let expected_bytes = num_points * bytes_per_point(octree, &id, &attribute);
+        match size {
+            // Subsampling can move all points of a node into its parent, which leaves no files.
+            None if num_points == 0 => (),
+            None => {
+                files_ok = false;
+                problems.push(Problem::MissingFile {
+                    node: node.clone(),
+                    attribute,
+                });
+            }
+            Some(actual_bytes) if actual_bytes != expected_bytes => {
+                files_ok = false;
+                problems.push(Problem::FileSizeMismatch {
+                    node: node.clone(),
+                    attribute,
+                    expected_bytes,
+                    actual_bytes,
+                });
+            }
+            Some(_) => (),
+        }
+    }
+
+    // Only decode the positions if reading them cannot run past the end of the files.
+    if files_ok && num_points > 0 {
+        let num_outside = count_positions_outside(octree, id)?;
+        if num_outside > 0 {
+            problems.push(Problem::PositionsOutsideNode {
+                node: node.clone(),
+                num_points: num_outside,
+            });
+        }
+    }
+
+    if let Some(parent) = id.parent_id() {
+        if !octree.nodes.contains_key(&parent) {
+            problems.push(Problem::MissingParent {
+                node,
+                parent: parent.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the node id if 'name' is the canonical name of a node.
+fn parse_node_id(name: &str) -> Option<NodeId> {
+    if !name.starts_with('r') {
+        return None;
+    }
+    NodeId::from_str(name)
+        .ok()
+        .filter(|id| id.to_string() == name)
+}
+
+fn find_orphan_files(
+    octree: &Octree,
+    directory: &Path,
+    attributes: &[String],
+) -> Result<Vec<Problem>> {
+    let extensions: FnvHashSet<&str> = attributes
+        .iter()
+        .map(|attribute| attribute_extension(attribute))
+        .collect();
+    let mut orphans = BTreeSet::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name == META_FILENAME {
+            continue;
+        }
+        let belongs_to_node = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => {
+                extensions.contains(extension.to_string_lossy().as_ref())
+                    && parse_node_id(&stem.to_string_lossy())
+                        .map_or(false, |id| octree.nodes.contains_key(&id))
+            }
+            _ => false,
+        };
+        if !belongs_to_node {
+            orphans.insert(file_name);
+        }
+    }
+    Ok(orphans
+        .into_iter()
+        .map(|file| Problem::OrphanFile { file })
+        .collect())
+}
+
+/// Checks the octree in 'directory' for missing or truncated node files, corrupted positions,
+/// unreachable nodes and files that are not referenced by the meta.
+pub fn check_octree(directory: impl AsRef<Path>) -> Result<CheckReport> {
+    let directory = directory.as_ref();
+    let octree = open_octree(directory)?;
+    let attributes = expected_attributes(&octree, directory);
+
+    let mut node_ids: Vec<NodeId> = octree.nodes.keys().cloned().collect();
+    node_ids.sort_by_key(|id| (id.level(), id.index()));
+    let mut problems = Vec::new();
+    for id in &node_ids {
+        check_node(&octree, directory, *id, &attributes, &mut problems)?;
+    }
+    problems.extend(find_orphan_files(&octree, directory, &attributes)?);
+    Ok(CheckReport {
+        num_nodes: node_ids.len(),
+        problems,
+    })
+}
+
+/// Returns the number of points a node actually has if all its files agree on it.
+fn consistent_num_points(
+    octree: &Octree,
+    directory: &Path,
+    id: &NodeId,
+    attributes: &[String],
+) -> Option<i64> {
+    let mut num_points = None;
+    for (attribute, size) in node_files(directory, id, attributes).sizes {
+        let bytes_per_point = bytes_per_point(octree, id, &attribute);
+        let size = size?;
+        if size % bytes_per_point != 0 {
+            return None;
+        }
+        let n = size / bytes_per_point;
+        if *num_points.get_or_insert(n) != n {
+            return None;
+        }
+    }
+    num_points.map(|n| n as i64)
+}
+
+/// Checks the octree in 'directory' and rewrites its meta to match the data: Nodes whose files
+/// agree on a different number of points get that number, all other broken nodes are removed from
+/// the meta together with their descendants, which could not be reached anymore. Data files are
+/// never deleted. Returns the report from before the repair.
+pub fn repair_octree(directory: impl AsRef<Path>) -> Result<CheckReport> {
+    let directory = directory.as_ref();
+    let report = check_octree(directory)?;
+    if report.is_ok() {
+        return Ok(report);
+    }
+    let octree = open_octree(directory)?;
+    let attributes = expected_attributes(&octree, directory);
+
+    let mut num_points: FnvHashMap<NodeId, Option<i64>> = octree
+        .nodes
+        .iter()
+        .map(|(id, node_meta)| (*id, Some(node_meta.num_points)))
+        .collect();
+    for problem in &report.problems {
+        match problem {
+            Problem::MissingFile { node, .. } | Problem::PositionsOutsideNode { node, .. } => {
+                num_points.insert(NodeId::from_str(node).unwrap(), None);
+            }
+            Problem::FileSizeMismatch { node, .. } => {
+                let id = NodeId::from_str(node).unwrap();
+                if num_points[&id].is_some() {
+                    num_points.insert(
+                        id,
+                        consistent_num_points(&octree, directory, &id, &attributes),
+                    );
+                }
+            }
+            Problem::MissingParent { .. } | Problem::OrphanFile { .. } => (),
+        }
+    }
+
+    // Whole subtrees are pruned, as are nodes whose parent was already missing.
+    let kept: FnvHashSet<NodeId> = num_points
+        .iter()
+        .filter(|(_, num_points)| num_points.is_some())
+        .map(|(id, _)| *id)
+        .collect();
+    let is_reachable = |id: &NodeId| {
+        let mut parent = id.parent_id();
+        while let Some(id) = parent {
+            if !kept.contains(&id) {
+                return false;
+            }
+            parent = id.parent_id();
+        }
+        true
+    };
+
+    let nodes = num_points
+        .into_iter()
+        .filter(|(id, _)| is_reachable(id))
+        .filter_map(|(id, num_points)| {
+            let node_meta = &octree.nodes[&id];
+            num_points.map(|n| {
+                // The ranges are only known to be right for the points they were computed from.
+                let attribute_ranges = if n == node_meta.num_points {
+                    node_meta.attribute_ranges.clone()
+                } else {
+                    AttributeRanges::new()
+                };
+                to_node_proto(&id, n, &node_meta.position_encoding, &attribute_ranges)
+            })
+        })
+        .collect();
+    write_meta(directory, &to_meta_proto(&octree.meta, nodes))?;
+    Ok(report)
+}
@@ -0,0 +1,385 @@
+//! Progress records for resuming an interrupted octree build.
+//!
+//! The checkpoint is a plain text file in the output directory with one record per line. Records
+//! are only ever appended and flushed to disk right away, so after a crash, every complete line
+//! describes work that is durably done.
+
+use crate::errors::*;
+use crate::geometry::Aabb;
+use crate::math::Crs;
+use crate::octree::{DeduplicationPolicy, NodeId};
+use fnv::{FnvHashMap, FnvHashSet};
+use nalgebra::Point3;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+pub const CHECKPOINT_FILENAME: &str = "build_checkpoint.txt";
+
+#[cfg(test)]
+lazy_static::lazy_static! {
+    /// Lets tests simulate a crash: A build panics right after appending the first record that
+    /// starts with the given prefix to the checkpoint file at the given path.
+    pub static ref CRASH_AFTER: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+}
+
+/// The state of a build as reconstructed from its checkpoint file.
+#[derive(Debug, Default)]
+pub struct CheckpointState {
+    pub resolution: Option<f64>,
+    pub bounding_box: Option<Aabb<f64>>,
+    /// The frames of the octree and of the input that the build was started with.
+    pub crs: Option<Crs>,
+    pub input_crs: Option<Crs>,
+    /// The attributes that are stored with the points.
+    pub attributes: Vec<String>,
+    pub deduplication: Option<DeduplicationPolicy>,
+    /// The number of points of the input, to tell whether a resumed build is given the same input.
+    pub num_input_points: Option<usize>,
+    /// Nodes whose points have been fully distributed to their children.
+    pub split_nodes: FnvHashSet<NodeId>,
+    /// Children of split nodes that were found to be too large and need splitting themselves.
+    pub nodes_to_split: FnvHashSet<NodeId>,
+    pub leaf_nodes: Vec<NodeId>,
+    /// Levels whose subsampled nodes have been written to the staging directory, with the number
+    /// of points of every written node.
+    pub staged_levels: BTreeMap<u8, Vec<(NodeId, i64)>>,
+    /// Levels whose subsampled nodes have been moved into the output directory.
+    pub finished_levels: FnvHashSet<u8>,
+}
+
+impl CheckpointState {
+    /// Returns the nodes that still need to be split, i.e. that were scheduled for splitting, but
+    /// never finished.
+    pub fn unfinished_splits(&self) -> Vec<NodeId> {
+        self.nodes_to_split
+            .difference(&self.split_nodes)
+            .cloned()
+            .collect()
+    }
+
+    /// Number of points per node after all finished levels were subsampled.
+    pub fn finished_nodes(&self) -> FnvHashMap<NodeId, i64> {
+        // Deeper levels are written first, and parents are rewritten on the next level, so the
+        // records need to be applied from the deepest level upwards.
+        self.staged_levels
+            .iter()
+            .rev()
+            .filter(|(level, _)| self.finished_levels.contains(*level))
+            .flat_map(|(_, nodes)| nodes.iter().cloned())
+            .collect()
+    }
+}
+
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+fn parse_error(line: &str) -> Error {
+    ErrorKind::InvalidInput(format!("Invalid checkpoint record: '{}'", line)).into()
+}
+
+// An empty list is written as '-', so that every record has a fixed number of fields.
+fn parse_node_ids(ids: &str, line: &str) -> Result<Vec<NodeId>> {
+    if ids == "-" {
+        return Ok(Vec::new());
+    }
+    ids.split(',')
+        .map(|id| NodeId::from_str(id).map_err(|_| parse_error(line)))
+        .collect()
+}
+
+fn parse_attributes(attributes: &str) -> Vec<String> {
+    if attributes == "-" {
+        return Vec::new();
+    }
+    attributes.split(',').map(str::to_string).collect()
+}
+
+fn format_attributes(attributes: &[&str]) -> String {
+    if attributes.is_empty() {
+        return "-".to_string();
+    }
+    attributes.join(",")
+}
+
+fn format_node_ids(ids: &[NodeId]) -> String {
+    if ids.is_empty() {
+        return "-".to_string();
+    }
+    ids.iter()
+        .map(NodeId::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// A missing frame is written as '-' as well.
+fn parse_crs(crs: &str, line: &str) -> Result<Option<Crs>> {
+    if crs == "-" {
+        return Ok(None);
+    }
+    Crs::from_str(crs).map(Some).map_err(|_| parse_error(line))
+}
+
+fn format_crs(crs: Option<Crs>) -> String {
+    crs.map_or_else(|| "-".to_string(), |crs| crs.to_string())
+}
+
+// So is building without deduplication.
+fn parse_deduplication(policy: &str, line: &str) -> Result<Option<DeduplicationPolicy>> {
+    if policy == "-" {
+        return Ok(None);
+    }
+    DeduplicationPolicy::from_str(policy)
+        .map(Some)
+        .map_err(|_| parse_error(line))
+}
+
+fn format_deduplication(policy: Option<DeduplicationPolicy>) -> String {
+    policy.map_or_else(|| "-".to_string(), |policy| policy.to_string())
+}
+
+fn parse_record(line: &str, state: &mut CheckpointState) -> Result<()> {
+    let entries: Vec<&str> = line.split_whitespace().collect();
+    let parse_f64 = |s: &str| s.parse::<f64>().map_err(|_| parse_error(line));
+    match entries.as_slice() {
+        ["octree", resolution, min_x, min_y, min_z, max_x, max_y, max_z, "crs", crs, "input_crs", input_crs, "attributes", attributes, "deduplication", deduplication, "points", num_input_points] =>
+        {
+            state.resolution = Some(parse_f64(resolution)?);
+            state.bounding_box = Some(Aabb::new(
+                Point3::new(parse_f64(min_x)?, parse_f64(min_y)?, parse_f64(min_z)?),
+                Point3::new(parse_f64(max_x)?, parse_f64(max_y)?, parse_f64(max_z)?),
+            ));
+            state.crs = parse_crs(crs, line)?;
+            state.input_crs = parse_crs(input_crs, line)?;
+            state.attributes = parse_attributes(attributes);
+            state.deduplication = parse_deduplication(deduplication, line)?;
+            state.num_input_points = Some(
+                num_input_points
+                    .parse::<usize>()
+                    .map_err(|_| parse_error(line))?,
+            );
+        }
+        ["split", id, "leaves", leaves, "splits", splits] => {
+            state
+                .split_nodes
+                .insert(NodeId::from_str(id).map_err(|_| parse_error(line))?);
+            state.leaf_nodes.extend(parse_node_ids(leaves, line)?);
+            state.nodes_to_split.extend(parse_node_ids(splits, line)?);
+        }
+        ["level", level, "staged", nodes @ ..] => {
+            let level = level.parse::<u8>().map_err(|_| parse_error(line))?;
+            let nodes = nodes
+                .iter()
+                .map(|entry| {
+                    let mut parts = entry.splitn(2, ':');
+                    let id = parts.next().and_then(|id| NodeId::from_str(id).ok());
+                    let num_points = parts.next().and_then(|n| n.parse::<i64>().ok());
+                    id.and_then(|id| num_points.map(|n| (id, n)))
+                        .ok_or_else(|| parse_error(line))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            state.staged_levels.insert(level, nodes);
+        }
+        ["level", level, "done"] => {
+            let level = level.parse::<u8>().map_err(|_| parse_error(line))?;
+            state.finished_levels.insert(level);
+        }
+        _ => return Err(parse_error(line)),
+    }
+    Ok(())
+}
+
+impl Checkpoint {
+    pub fn path(directory: &Path) -> PathBuf {
+        directory.join(CHECKPOINT_FILENAME)
+    }
+
+    /// Reads the checkpoint in 'directory'. Returns None if there is none.
+    pub fn load(directory: &Path) -> Result<Option<CheckpointState>> {
+        let mut contents = String::new();
+        match File::open(Self::path(directory)) {
+            Ok(mut file) => file.read_to_string(&mut contents)?,
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        // The last line might be incomplete if we crashed while writing it. Since every record is
+        // terminated by a newline, we simply ignore everything after the last one.
+        let complete_records = &contents[..contents.rfind('\n').map_or(0, |end| end + 1)];
+        let mut state = CheckpointState::default();
+        for line in complete_records.lines() {
+            if !line.trim().is_empty() {
+                parse_record(line, &mut state)?;
+            }
+        }
+        Ok(Some(state))
+    }
+
+    /// Opens the checkpoint in 'directory' for appending new records. If 'truncate' is set, all
+    /// previous records are discarded.
+    pub fn open(directory: &Path, truncate: bool) -> Result<Self> {
+        let path = Self::path(directory);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(!truncate)
+            .write(true)
+            .truncate(truncate)
+            .open(&path)?;
+        Ok(Checkpoint {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn append(&self, record: &str) -> Result<()> {
+        {
+            let mut file = self.file.lock().unwrap();
+            file.write_all(record.as_bytes())?;
+            file.write_all(b"\n")?;
+            file.sync_data()?;
+        }
+        #[cfg(test)]
+        {
+            let mut crash_after = CRASH_AFTER.lock().unwrap();
+            let crash = crash_after.iter().position(|(path, prefix)| {
+                *path == self.path && record.starts_with(prefix.as_str())
+            });
+            if let Some(index) = crash {
+                crash_after.remove(index);
+                drop(crash_after);
+                panic!("Simulated crash after checkpoint record '{}'.", record);
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_parameters(
+        &self,
+        resolution: f64,
+        bounding_box: &Aabb<f64>,
+        crs: Option<Crs>,
+        input_crs: Option<Crs>,
+        attributes: &[&str],
+        deduplication: Option<DeduplicationPolicy>,
+        num_input_points: usize,
+    ) -> Result<()> {
+        let (min, max) = (bounding_box.min(), bounding_box.max());
+        self.append(&format!(
+            "octree {} {} {} {} {} {} {} crs {} input_crs {} attributes {} deduplication {} \
+             points {}",
+            resolution,
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z,
+            format_crs(crs),
+            format_crs(input_crs),
+            format_attributes(attributes),
+            format_deduplication(deduplication),
+            num_input_points
+        ))
+    }
+
+    pub fn record_split(
+        &self,
+        node_id: &NodeId,
+        leaf_nodes: &[NodeId],
+        split_nodes: &[NodeId],
+    ) -> Result<()> {
+        self.append(&format!(
+            "split {} leaves {} splits {}",
+            node_id,
+            format_node_ids(leaf_nodes),
+            format_node_ids(split_nodes)
+        ))
+    }
+
+    pub fn record_level_staged(&self, level: u8, nodes: &[(NodeId, i64)]) -> Result<()> {
+        let nodes: Vec<String> = nodes
+            .iter()
+            .map(|(id, num_points)| format!("{}:{}", id, num_points))
+            .collect();
+        self.append(&format!("level {} staged {}", level, nodes.join(" ")))
+    }
+
+    pub fn record_level_done(&self, level: u8) -> Result<()> {
+        self.append(&format!("level {} done", level))
+    }
+
+    /// Deletes the checkpoint file, to be called once the build is complete.
+    pub fn remove(self) -> Result<()> {
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_checkpoint_roundtrip() {
+        let tmp_dir = TempDir::new("checkpoint").unwrap();
+        let id = |s: &str| NodeId::from_str(s).unwrap();
+        let bounding_box = Aabb::new(Point3::new(-1.5, 0.1, 2.0), Point3::new(3.0, 4.25, 5.0));
+        let input_crs = Crs::LocalEnu {
+            latitude: 37.4,
+            longitude: -122.1,
+        };
+        {
+            let checkpoint = Checkpoint::open(tmp_dir.path(), true).unwrap();
+            checkpoint
+                .record_parameters(
+                    0.001,
+                    &bounding_box,
+                    Some(Crs::Ecef),
+                    Some(input_crs),
+                    &["color", "intensity"],
+                    Some(DeduplicationPolicy::Average),
+                    42,
+                )
+                .unwrap();
+            checkpoint
+                .record_split(&id("r"), &[id("r0")], &[id("r1"), id("r2")])
+                .unwrap();
+            checkpoint
+                .record_split(&id("r1"), &[id("r10"), id("r17")], &[])
+                .unwrap();
+            checkpoint
+                .record_level_staged(2, &[(id("r10"), 7), (id("r1"), 2)])
+                .unwrap();
+            checkpoint.record_level_done(2).unwrap();
+            checkpoint.record_level_staged(1, &[(id("r1"), 1)]).unwrap();
+        }
+        // Simulate a crash in the middle of writing a record.
+        {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(Checkpoint::path(tmp_dir.path()))
+                .unwrap();
+            file.write_all(b"level 1 do").unwrap();
+        }
+        let state = Checkpoint::load(tmp_dir.path()).unwrap().unwrap();
+        assert_eq!(state.resolution, Some(0.001));
+        assert_eq!(state.bounding_box, Some(bounding_box));
+        assert_eq!(state.crs, Some(Crs::Ecef));
+        assert_eq!(state.input_crs, Some(input_crs));
+        assert_eq!(state.attributes, vec!["color", "intensity"]);
+        assert_eq!(state.deduplication, Some(DeduplicationPolicy::Average));
+        assert_eq!(state.num_input_points, Some(42));
+        assert_eq!(state.unfinished_splits(), vec![id("r2")]);
+        assert_eq!(state.leaf_nodes, vec![id("r0"), id("r10"), id("r17")]);
+        // Level 1 is staged, but not done, so its counts must not be used yet.
+        let finished_nodes = state.finished_nodes();
+        assert_eq!(finished_nodes[&id("r1")], 2);
+        assert_eq!(finished_nodes[&id("r10")], 7);
+    }
+}
@@ -0,0 +1,208 @@
+//! Merging points that cannot be told apart at the resolution of an octree.
+
+use crate::errors::*;
+use crate::geometry::Cube;
+use crate::{AttributeData, PointsBatch};
+use fnv::FnvHashMap;
+use nalgebra::Vector3;
+use std::fmt;
+use std::str::FromStr;
+
+/// How to combine the attributes of points that fall into the same voxel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeduplicationPolicy {
+    /// Keeps the first point of every voxel.
+    KeepFirst,
+    /// Keeps the first point of every voxel, but with the average color and intensity of all
+    /// points in the voxel.
+    Average,
+    /// Keeps the point with the highest intensity of every voxel. Without intensities, this is
+    /// the same as 'KeepFirst'.
+    MaxIntensity,
+}
+
+impl FromStr for DeduplicationPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep_first" => Ok(DeduplicationPolicy::KeepFirst),
+            "average" => Ok(DeduplicationPolicy::Average),
+            "max_intensity" => Ok(DeduplicationPolicy::MaxIntensity),
+            _ => Err(ErrorKind::InvalidInput(format!(
+                "Unknown deduplication policy '{}', expected one of keep_first, average, \
+                 max_intensity.",
+                s
+            ))
+            .into()),
+        }
+    }
+}
+
+impl fmt::Display for DeduplicationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeduplicationPolicy::KeepFirst => write!(f, "keep_first"),
+            DeduplicationPolicy::Average => write!(f, "average"),
+            DeduplicationPolicy::MaxIntensity => write!(f, "max_intensity"),
+        }
+    }
+}
+
+/// Returns for every point the index of the first point in the same voxel.
+fn first_point_in_voxel(batch: &PointsBatch, bounding_cube: &Cube, resolution: f64) -> Vec<usize> {
+    let min = bounding_cube.min();
+    let mut voxels = FnvHashMap::default();
+    batch
+        .position
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let voxel = (
+                ((p.x - min.x) / resolution).floor() as i64,
+                ((p.y - min.y) / resolution).floor() as i64,
+                ((p.z - min.z) / resolution).floor() as i64,
+            );
+            *voxels.entry(voxel).or_insert(i)
+        })
+        .collect()
+}
+
+fn average_into_first(batch: &mut PointsBatch, first: &[usize]) {
+    let mut counts = vec![0u32; first.len()];
+    for f in first {
+        counts[*f] += 1;
+    }
+    if let Some(AttributeData::U8Vec3(colors)) = batch.attributes.get_mut("color") {
+        let mut sums = vec![Vector3::<u32>::zeros(); colors.len()];
+        for (color, f) in colors.iter().zip(first) {
+            sums[*f] += color.map(u32::from);
+        }
+        for (i, sum) in sums.iter().enumerate().filter(|(i, _)| counts[*i] > 0) {
+            colors[i] = (sum / counts[i]).map(|c| c as u8);
+        }
+    }
+    if let Some(AttributeData::F32(intensities)) = batch.attributes.get_mut("intensity") {
+        let mut sums = vec![0f64; intensities.len()];
+        for (intensity, f) in intensities.iter().zip(first) {
+            sums[*f] += f64::from(*intensity);
+        }
+        for (i, sum) in sums.iter().enumerate().filter(|(i, _)| counts[*i] > 0) {
+            intensities[i] = (sum / f64::from(counts[i])) as f32;
+        }
+    }
+}
+
+/// Merges the points of 'batch' that fall into the same voxel of size 'resolution', with the
+/// voxel grid aligned to 'bounding_cube'. The remaining points keep their relative order.
+pub fn deduplicate(
+    batch: &mut PointsBatch,
+    bounding_cube: &Cube,
+    resolution: f64,
+    policy: DeduplicationPolicy,
+) {
+    let first = first_point_in_voxel(batch, bounding_cube, resolution);
+    let mut kept: Vec<usize> = (0..first.len()).collect();
+    match policy {
+        DeduplicationPolicy::KeepFirst => (),
+        DeduplicationPolicy::Average => average_into_first(batch, &first),
+        DeduplicationPolicy::MaxIntensity => {
+            if let Some(AttributeData::F32(intensities)) = batch.attributes.get("intensity") {
+                for (i, f) in first.iter().enumerate() {
+                    if intensities[i] > intensities[kept[*f]] {
+                        kept[*f] = i;
+                    }
+                }
+            }
+        }
+    }
+    let mut keep = vec![false; first.len()];
+    for f in &first {
+        keep[kept[*f]] = true;
+    }
+    batch.retain(&keep);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point3;
+    use std::collections::BTreeMap;
+
+    fn batch_with_duplicates() -> PointsBatch {
+        let mut attributes = BTreeMap::new();
+        attributes.insert(
+            "color".to_string(),
+            AttributeData::U8Vec3(vec![
+                Vector3::new(0, 0, 0),
+                Vector3::new(100, 0, 0),
+                Vector3::new(200, 50, 10),
+            ]),
+        );
+        attributes.insert(
+            "intensity".to_string(),
+            AttributeData::F32(vec![1., 2., 6.]),
+        );
+        PointsBatch {
+            // The first and last point are in the same voxel.
+            position: vec![
+                Point3::new(0.1, 0.1, 0.1),
+                Point3::new(1.5, 0.1, 0.1),
+                Point3::new(0.9, 0.2, 0.3),
+            ],
+            attributes,
+        }
+    }
+
+    fn colors(batch: &PointsBatch) -> Vec<Vector3<u8>> {
+        match &batch.attributes["color"] {
+            AttributeData::U8Vec3(colors) => colors.clone(),
+            _ => panic!("Unexpected color type."),
+        }
+    }
+
+    fn intensities(batch: &PointsBatch) -> Vec<f32> {
+        match &batch.attributes["intensity"] {
+            AttributeData::F32(intensities) => intensities.clone(),
+            _ => panic!("Unexpected intensity type."),
+        }
+    }
+
+    fn deduplicated(policy: DeduplicationPolicy) -> PointsBatch {
+        let mut batch = batch_with_duplicates();
+        let bounding_cube = Cube::new(Point3::new(0., 0., 0.), 4.);
+        deduplicate(&mut batch, &bounding_cube, 1., policy);
+        batch
+    }
+
+    #[test]
+    fn test_keep_first() {
+        let batch = deduplicated(DeduplicationPolicy::KeepFirst);
+        assert_eq!(
+            batch.position,
+            vec![Point3::new(0.1, 0.1, 0.1), Point3::new(1.5, 0.1, 0.1)]
+        );
+        assert_eq!(intensities(&batch), vec![1., 2.]);
+    }
+
+    #[test]
+    fn test_average() {
+        let batch = deduplicated(DeduplicationPolicy::Average);
+        assert_eq!(batch.position.len(), 2);
+        assert_eq!(
+            colors(&batch),
+            vec![Vector3::new(100, 25, 5), Vector3::new(100, 0, 0)]
+        );
+        assert_eq!(intensities(&batch), vec![3.5, 2.]);
+    }
+
+    #[test]
+    fn test_max_intensity() {
+        let batch = deduplicated(DeduplicationPolicy::MaxIntensity);
+        assert_eq!(
+            batch.position,
+            vec![Point3::new(1.5, 0.1, 0.1), Point3::new(0.9, 0.2, 0.3)]
+        );
+        assert_eq!(intensities(&batch), vec![2., 6.]);
+    }
+}
@@ -12,30 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::attributes::{AttributeRanges, LabelDictionaries};
 use crate::data_provider::OnDiskDataProvider;
 use crate::errors::*;
 use crate::geometry::{Aabb, Cube};
+use crate::math::Crs;
+use crate::octree::checkpoint::{Checkpoint, CheckpointState};
+use crate::octree::deduplication::{deduplicate, DeduplicationPolicy};
 use crate::octree::{self, to_meta_proto, to_node_proto, ChildIndex, NodeId, OctreeMeta};
 use crate::proto;
 use crate::read_write::{
-    attempt_increasing_rlimit_to_max, Encoding, NodeIterator, NodeWriter, OpenMode, PlyIterator,
-    PositionEncoding, RawNodeWriter,
+    attempt_increasing_rlimit_to_max, is_publication_interrupted, publish_staged_build,
+    staging_directory, start_staged_build, write_meta, Encoding, NodeIterator, NodeWriter,
+    OpenMode, PlyIterator, PositionEncoding, RawNodeWriter, Reproject,
 };
 use crate::utils::create_progress_bar;
-use crate::META_FILENAME;
+use crate::{attribute_extension, META_FILENAME};
 use crate::{AttributeDataType, NumberOfPoints, PointCloudMeta, PointsBatch, NUM_POINTS_PER_BATCH};
 use fnv::{FnvHashMap, FnvHashSet};
-use protobuf::Message;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use rayon::Scope;
-use std::cmp;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::BufWriter;
+use std::fs;
+use std::io;
+use std::iter;
 use std::path::Path;
+use std::sync::Mutex;
 
 const MAX_POINTS_PER_NODE: i64 = 100_000;
 
+/// Subdirectory of the build directory that holds the nodes of the level currently being
+/// subsampled, until they are moved into place.
+const SUBSAMPLING_DIRECTORY: &str = "subsampling";
+
 impl RawNodeWriter {
     fn from_data_provider(
         octree_data_provider: &OnDiskDataProvider,
@@ -60,7 +69,7 @@ fn split<P>(
     octree_meta: &octree::OctreeMeta,
     node_id: &octree::NodeId,
     stream: P,
-) -> (Vec<octree::NodeId>, Vec<octree::NodeId>)
+) -> Result<(Vec<octree::NodeId>, Vec<octree::NodeId>)>
 where
     P: Iterator<Item = PointsBatch> + NumberOfPoints,
 {
@@ -75,7 +84,7 @@ where
     );
 
     let bounding_cube = node_id.find_bounding_cube(&Cube::bounding(&octree_meta.bounding_box));
-    stream.for_each(|batch| {
+    for batch in stream {
         let child_indices: Vec<_> = batch
             .position
             .iter()
@@ -96,16 +105,10 @@ where
                         &node_id.get_child_id(ChildIndex::from_u8(array_index as u8)),
                     ));
                 }
-                child_writer.as_mut().unwrap().write(&child_batch).unwrap();
+                child_writer.as_mut().unwrap().write(&child_batch)?;
             }
         }
-    });
-
-    // Remove the node file on disk by reopening the node and immediately dropping it again without
-    // writing a point. This only saves some disk space during processing - all nodes will be
-    // rewritten by subsampling the children in the second step anyways. We also ignore file
-    // removing error. For example, we never write out the root, so it cannot be removed.
-    RawNodeWriter::from_data_provider(octree_data_provider, octree_meta, node_id);
+    }
 
     let mut leaf_nodes = Vec::new();
     let mut split_nodes = Vec::new();
@@ -122,7 +125,7 @@ where
             leaf_nodes.push(child_id);
         }
     }
-    (leaf_nodes, split_nodes)
+    Ok((leaf_nodes, split_nodes))
 }
 
 fn should_split_node(
@@ -149,58 +152,161 @@ fn should_split_node(
     true
 }
 
-fn split_node<'a, P>(
-    scope: &Scope<'a>,
+/// Everything splitting needs to know about the build.
+#[derive(Clone, Copy)]
+struct SplitContext<'a> {
     octree_data_provider: &'a OnDiskDataProvider,
     octree_meta: &'a octree::OctreeMeta,
     attribute_data_types: &'a HashMap<String, AttributeDataType>,
+    checkpoint: &'a Checkpoint,
+    deduplication: Option<DeduplicationPolicy>,
+    /// The first error of any split, after which no more nodes are split.
+    first_error: &'a Mutex<Option<Error>>,
+}
+
+impl<'a> SplitContext<'a> {
+    fn has_failed(&self) -> bool {
+        self.first_error.lock().unwrap().is_some()
+    }
+
+    fn record_error(&self, result: Result<()>) {
+        if let Err(err) = result {
+            self.first_error.lock().unwrap().get_or_insert(err);
+        }
+    }
+}
+
+fn split_node<'a, P>(
+    scope: &Scope<'a>,
+    context: SplitContext<'a>,
     node_id: &octree::NodeId,
     stream: P,
     leaf_nodes_sender: &crossbeam::channel::Sender<octree::NodeId>,
-) where
+) -> Result<()>
+where
     P: Iterator<Item = PointsBatch> + NumberOfPoints,
 {
-    let (leaf_nodes, split_nodes) = split(octree_data_provider, octree_meta, node_id, stream);
+    let (leaf_nodes, split_nodes) = split(
+        context.octree_data_provider,
+        context.octree_meta,
+        node_id,
+        stream,
+    )?;
+    if let Some(policy) = context.deduplication {
+        for id in &leaf_nodes {
+            deduplicate_node(context, id, policy)?;
+        }
+    }
+    // From here on, the children contain all points of this node, so we never need to split it
+    // again when resuming.
+    context
+        .checkpoint
+        .record_split(node_id, &leaf_nodes, &split_nodes)?;
+
+    // Remove the node file on disk by reopening the node and immediately dropping it again without
+    // writing a point. This only saves some disk space during processing - all nodes will be
+    // rewritten by subsampling the children in the second step anyways. We also ignore file
+    // removing error. For example, we never write out the root, so it cannot be removed.
+    RawNodeWriter::from_data_provider(context.octree_data_provider, context.octree_meta, node_id);
+
     for child_id in split_nodes {
-        let leaf_nodes_sender_clone = leaf_nodes_sender.clone();
-        scope.spawn(move |scope| {
-            let stream = NodeIterator::from_data_provider(
-                octree_data_provider,
-                attribute_data_types,
-                octree_meta.encoding_for_node(child_id),
-                &child_id,
-                octree_data_provider
-                    .number_of_points(&child_id.to_string())
-                    .unwrap() as usize,
-                NUM_POINTS_PER_BATCH,
-            )
-            .unwrap();
-            split_node(
-                scope,
-                octree_data_provider,
-                octree_meta,
-                attribute_data_types,
-                &child_id,
-                stream,
-                &leaf_nodes_sender_clone,
-            );
-        });
+        spawn_split_node_from_disk(scope, context, child_id, leaf_nodes_sender.clone());
     }
 
     for id in leaf_nodes {
         leaf_nodes_sender.send(id).unwrap();
     }
+    Ok(())
 }
 
+fn read_node(context: SplitContext, node_id: &octree::NodeId) -> Result<NodeIterator> {
+    NodeIterator::from_data_provider(
+        context.octree_data_provider,
+        context.attribute_data_types,
+        context.octree_meta.encoding_for_node(*node_id),
+        node_id,
+        context
+            .octree_data_provider
+            .number_of_points(&node_id.to_string())? as usize,
+        NUM_POINTS_PER_BATCH,
+    )
+}
+
+/// Splits a node that has already been written to disk in a new task. Errors are recorded in the
+/// context.
+fn spawn_split_node_from_disk<'a>(
+    scope: &Scope<'a>,
+    context: SplitContext<'a>,
+    node_id: octree::NodeId,
+    leaf_nodes_sender: crossbeam::channel::Sender<octree::NodeId>,
+) {
+    scope.spawn(move |scope| {
+        if context.has_failed() {
+            return;
+        }
+        context.record_error(
+            read_node(context, &node_id).and_then(|stream| {
+                split_node(scope, context, &node_id, stream, &leaf_nodes_sender)
+            }),
+        );
+    });
+}
+
+/// Rewrites a leaf with all its coincident points merged. Returns the attribute ranges of the
+/// remaining points.
+fn deduplicate_node(
+    context: SplitContext,
+    node_id: &octree::NodeId,
+    policy: DeduplicationPolicy,
+) -> Result<AttributeRanges> {
+    // We read all points into memory, because the new node writer will rewrite this node's
+    // file(s).
+    let mut node_iterator = read_node(context, node_id)?;
+    let mut attribute_ranges = AttributeRanges::new();
+    let mut batch = match node_iterator.try_next()? {
+        Some(batch) => batch,
+        None => return Ok(attribute_ranges),
+    };
+    while let Some(mut b) = node_iterator.try_next()? {
+        batch.append(&mut b)?;
+    }
+    let num_points = batch.position.len();
+
+    let bounding_cube =
+        node_id.find_bounding_cube(&Cube::bounding(&context.octree_meta.bounding_box));
+    deduplicate(
+        &mut batch,
+        &bounding_cube,
+        context.octree_meta.resolution,
+        policy,
+    );
+    if batch.position.len() < num_points {
+        let mut writer = RawNodeWriter::from_data_provider(
+            context.octree_data_provider,
+            context.octree_meta,
+            node_id,
+        );
+        writer.write(&batch)?;
+    }
+    attribute_ranges.update(&batch);
+    Ok(attribute_ranges)
+}
+
+/// Reads the children of 'node_id' and moves every 8th point into the parent. The rewritten
+/// parent and children are written to 'staging_data_provider', so that the input stays intact
+/// until the whole level is done. Every written node is sent with its number of points and
+/// attribute ranges.
 fn subsample_children_into(
     octree_data_provider: &OnDiskDataProvider,
+    staging_data_provider: &OnDiskDataProvider,
     octree_meta: &octree::OctreeMeta,
     attribute_data_types: &HashMap<String, AttributeDataType>,
     node_id: &octree::NodeId,
-    nodes_sender: &crossbeam::channel::Sender<(octree::NodeId, i64)>,
+    nodes_sender: &crossbeam::channel::Sender<(octree::NodeId, i64, AttributeRanges)>,
 ) -> Result<()> {
     let mut parent_writer =
-        RawNodeWriter::from_data_provider(octree_data_provider, octree_meta, node_id);
+        RawNodeWriter::from_data_provider(staging_data_provider, octree_meta, node_id);
+    let mut parent_ranges = AttributeRanges::new();
     for i in 0..8 {
         let child_id = node_id.get_child_id(octree::ChildIndex::from_u8(i));
         let num_points = match octree_data_provider.number_of_points(&child_id.to_string()) {
@@ -217,10 +323,17 @@ fn subsample_children_into(
             NUM_POINTS_PER_BATCH,
         )?;
 
-        // We read all points into memory, because the new node writer will rewrite this child's
-        // file(s).
-        let mut batch = node_iterator.next().unwrap();
-        node_iterator.for_each(|mut b| batch.append(&mut b).unwrap());
+        let mut batch = match node_iterator.try_next()? {
+            Some(batch) => batch,
+            None => {
+                return Err(
+                    ErrorKind::InvalidInput(format!("Node {} has no points.", child_id)).into(),
+                )
+            }
+        };
+        while let Some(mut b) = node_iterator.try_next()? {
+            batch.append(&mut b)?;
+        }
         let (keep_parent, keep_child): (Vec<bool>, Vec<bool>) = (0..batch.position.len())
             .map(|i| {
                 let in_parent = i % 8 == 0;
@@ -233,29 +346,116 @@ fn subsample_children_into(
         child_batch.retain(&keep_child);
 
         let mut child_writer =
-            RawNodeWriter::from_data_provider(octree_data_provider, octree_meta, &child_id);
+            RawNodeWriter::from_data_provider(staging_data_provider, octree_meta, &child_id);
         parent_writer.write(&parent_batch)?;
         child_writer.write(&child_batch)?;
+        parent_ranges.update(&parent_batch);
+        let mut child_ranges = AttributeRanges::new();
+        child_ranges.update(&child_batch);
 
         // Update child.
         nodes_sender
-            .send((child_id, child_writer.num_written()))
+            .send((child_id, child_writer.num_written(), child_ranges))
             .unwrap();
     }
 
-    // Make sure the root node is also tracked as an existing node.
-    if node_id.level() == 0 {
-        nodes_sender
-            .send((*node_id, parent_writer.num_written()))
-            .unwrap();
+    // The parent is tracked as well, so that it is moved into place together with its children.
+    // Unless it is the root, its number of points and ranges will be updated again on the next
+    // level.
+    nodes_sender
+        .send((*node_id, parent_writer.num_written(), parent_ranges))
+        .unwrap();
+    Ok(())
+}
+
+/// Moves the nodes written to 'staging_data_provider' into 'octree_data_provider', replacing their
+/// previous versions. Nodes that were already moved are skipped, so this can be repeated after an
+/// interruption.
+fn publish_staged_nodes(
+    staging_data_provider: &OnDiskDataProvider,
+    octree_data_provider: &OnDiskDataProvider,
+    attributes: &[&str],
+    nodes: &[(octree::NodeId, i64)],
+) -> Result<()> {
+    for (id, num_points) in nodes {
+        for attribute in iter::once(&"position").chain(attributes) {
+            let extension = attribute_extension(attribute);
+            let staged_path = staging_data_provider
+                .stem(&id.to_string())
+                .with_extension(extension);
+            let path = octree_data_provider
+                .stem(&id.to_string())
+                .with_extension(extension);
+            if staged_path.exists() {
+                fs::rename(&staged_path, &path)?;
+            } else if *num_points == 0 {
+                // Empty nodes are never written, so remove the previous version of this node.
+                match fs::remove_file(&path) {
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => (),
+                    res => res?,
+                }
+            }
+        }
     }
     Ok(())
 }
 
+/// Subsamples the children of all 'parent_ids' into the staging directory and returns the number
+/// of points and the attribute ranges of every node that was written.
+fn subsample_level(
+    octree_data_provider: &OnDiskDataProvider,
+    staging_data_provider: &OnDiskDataProvider,
+    octree_meta: &octree::OctreeMeta,
+    attribute_data_types: &HashMap<String, AttributeDataType>,
+    parent_ids: &FnvHashSet<octree::NodeId>,
+    current_level: u8,
+) -> Result<Vec<(octree::NodeId, i64, AttributeRanges)>> {
+    let mut progress_bar = create_progress_bar(
+        parent_ids.len(),
+        &format!("Building level {}", current_level - 1),
+    );
+
+    let mut staged_nodes = Vec::new();
+    let (staged_nodes_sender, staged_nodes_receiver) = crossbeam::channel::unbounded();
+    let (progress_tx, progress_rx) = crossbeam::channel::unbounded();
+    let result = rayon::scope(|scope| {
+        scope.spawn(|_| {
+            for node in staged_nodes_receiver {
+                staged_nodes.push(node);
+            }
+        });
+
+        scope.spawn(|_| {
+            for _ in progress_rx {
+                progress_bar.inc();
+            }
+        });
+
+        let result = parent_ids.par_iter().try_for_each(|id| {
+            subsample_children_into(
+                octree_data_provider,
+                staging_data_provider,
+                octree_meta,
+                attribute_data_types,
+                id,
+                &staged_nodes_sender,
+            )?;
+            progress_tx.send(()).unwrap();
+            Ok(())
+        });
+        drop(staged_nodes_sender);
+        drop(progress_tx);
+        result
+    });
+    progress_bar.finish();
+    result.map(|()| staged_nodes)
+}
+
 /// Returns the bounding box containing all points
-fn find_bounding_box(filename: impl AsRef<Path>) -> Aabb<f64> {
+pub(super) fn find_bounding_box(
+    stream: impl Iterator<Item = PointsBatch> + NumberOfPoints,
+) -> Aabb<f64> {
     let mut bounding_box = None;
-    let stream = PlyIterator::from_file(filename, NUM_POINTS_PER_BATCH).unwrap();
     let mut progress_bar = create_progress_bar(stream.num_points(), "Determining bounding box");
 
     stream.for_each(|batch| {
@@ -269,135 +469,531 @@ fn find_bounding_box(filename: impl AsRef<Path>) -> Aabb<f64> {
     bounding_box.unwrap_or_else(Aabb::zero)
 }
 
-pub fn build_octree_from_file(
-    output_directory: impl AsRef<Path>,
-    resolution: f64,
-    filename: impl AsRef<Path>,
-    attributes: &[&str],
-) {
-    let bounding_box = find_bounding_box(filename.as_ref());
-    let stream = PlyIterator::from_file(filename, NUM_POINTS_PER_BATCH).unwrap();
-    build_octree(
-        output_directory,
-        resolution,
-        bounding_box,
-        stream,
-        attributes,
+/// Options for octree generation that are not needed for most builds.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// Continue an interrupted build from the checkpoint in the output directory instead of
+    /// starting from scratch.
+    pub resume: bool,
+    /// If set, points in the same leaf that fall into the same voxel of size 'resolution' are
+    /// merged into one.
+    pub deduplication: Option<DeduplicationPolicy>,
+    /// The frame of the octree positions, which is recorded in the meta.
+    pub crs: Option<Crs>,
+    /// The frame of the input positions. If it differs from 'crs', the input is converted while
+    /// it is read.
+    pub input_crs: Option<Crs>,
+    /// The label dictionaries of the categorical attributes, which are recorded in the meta.
+    pub label_dictionaries: LabelDictionaries,
+}
+
+impl BuildOptions {
+    /// Converts the positions of 'input' from 'input_crs' to 'crs'.
+    pub(super) fn reproject<I>(&self, input: I) -> Reproject<I> {
+        Reproject::new(input, self.input_crs, self.crs)
+    }
+}
+
+/// What a previous build left behind in an output directory.
+pub(super) enum PreviousBuild {
+    /// The build was completed, so there is nothing left to do.
+    Finished,
+    /// The build was interrupted and continues from its checkpoint.
+    Interrupted(CheckpointState),
+    /// There is nothing to resume, so the build starts from scratch.
+    NotFound,
+}
+
+/// Looks for a previous build of 'output_directory' to resume. A publication that was interrupted
+/// is finished first. Without 'options.resume', every build starts from scratch.
+pub(super) fn find_previous_build(
+    output_directory: &Path,
+    options: &BuildOptions,
+) -> Result<PreviousBuild> {
+    if !options.resume {
+        return Ok(PreviousBuild::NotFound);
+    }
+    if is_publication_interrupted(output_directory)? {
+        eprintln!("Finishing the interrupted publication of the build.");
+        publish_staged_build(output_directory)?;
+    }
+    let build_directory = staging_directory(output_directory)?;
+    if output_directory.join(META_FILENAME).exists() && !build_directory.exists() {
+        eprintln!("Octree build is already complete.");
+        return Ok(PreviousBuild::Finished);
+    }
+    // A checkpoint without parameters was interrupted before doing any work.
+    Ok(
+        match Checkpoint::load(&build_directory)?.filter(|state| state.bounding_box.is_some()) {
+            Some(checkpoint_state) => PreviousBuild::Interrupted(checkpoint_state),
+            None => {
+                eprintln!(
+                    "No checkpoint found in {}, starting from scratch.",
+                    build_directory.display()
+                );
+                PreviousBuild::NotFound
+            }
+        },
     )
 }
 
-pub fn build_octree(
-    output_directory: impl AsRef<Path>,
+/// Prepares the staging directory of 'output_directory' to continue 'previous', or for a new
+/// build. Returns None if there is nothing left to do. Fails if 'previous' was started with
+/// different parameters, a different bounding box or a different number of input points. The
+/// returned state always contains the resolution and bounding box of the build.
+pub(super) fn open_build(
+    output_directory: &Path,
     resolution: f64,
     bounding_box: Aabb<f64>,
-    input: impl Iterator<Item = PointsBatch> + NumberOfPoints + Send,
     attributes: &[&str],
-) {
+    num_input_points: usize,
+    options: &BuildOptions,
+    previous: PreviousBuild,
+) -> Result<Option<(Checkpoint, CheckpointState)>> {
     attempt_increasing_rlimit_to_max();
 
-    let octree_meta =
-        &octree::OctreeMeta::new_with_standard_attributes(resolution, bounding_box.clone());
-    let attribute_data_types = &octree_meta.attribute_data_types_for(attributes).unwrap();
+    let build_directory = staging_directory(output_directory)?;
+    match previous {
+        PreviousBuild::Finished => Ok(None),
+        PreviousBuild::Interrupted(checkpoint_state) => {
+            let recorded_attributes: FnvHashSet<&str> = checkpoint_state
+                .attributes
+                .iter()
+                .map(String::as_str)
+                .collect();
+            let mismatch = if checkpoint_state.resolution != Some(resolution) {
+                Some("resolution")
+            } else if checkpoint_state.bounding_box.as_ref() != Some(&bounding_box) {
+                Some("bounding box")
+            } else if checkpoint_state.crs != options.crs
+                || checkpoint_state.input_crs != options.input_crs
+            {
+                Some("coordinate reference system")
+            } else if recorded_attributes != attributes.iter().cloned().collect() {
+                Some("set of attributes")
+            } else if checkpoint_state.deduplication != options.deduplication {
+                Some("deduplication policy")
+            } else if checkpoint_state.num_input_points != Some(num_input_points) {
+                Some("input")
+            } else {
+                None
+            };
+            if let Some(mismatch) = mismatch {
+                return Err(ErrorKind::InvalidInput(format!(
+                    "Cannot resume the build in {} with a different {}.",
+                    output_directory.display(),
+                    mismatch
+                ))
+                .into());
+            }
+            eprintln!("Resuming octree build in {}.", output_directory.display());
+            let checkpoint = Checkpoint::open(&build_directory, false)?;
+            Ok(Some((checkpoint, checkpoint_state)))
+        }
+        PreviousBuild::NotFound => {
+            start_staged_build(output_directory)?;
+            let checkpoint = Checkpoint::open(&build_directory, true)?;
+            checkpoint.record_parameters(
+                resolution,
+                &bounding_box,
+                options.crs,
+                options.input_crs,
+                attributes,
+                options.deduplication,
+                num_input_points,
+            )?;
+            let checkpoint_state = CheckpointState {
+                resolution: Some(resolution),
+                bounding_box: Some(bounding_box),
+                crs: options.crs,
+                input_crs: options.input_crs,
+                attributes: attributes
+                    .iter()
+                    .map(|attribute| attribute.to_string())
+                    .collect(),
+                deduplication: options.deduplication,
+                num_input_points: Some(num_input_points),
+                ..Default::default()
+            };
+            Ok(Some((checkpoint, checkpoint_state)))
+        }
+    }
+}
+
+/// Splits 'input' into the subtree below 'root_id' and subsamples it up to and including
+/// 'root_id'. Returns the number of points of every node that was written, and the attribute
+/// ranges of the nodes that were written by this process.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn build_subtree(
+    build_directory: &Path,
+    octree_meta: &octree::OctreeMeta,
+    attributes: &[&str],
+    root_id: octree::NodeId,
+    input: impl Iterator<Item = PointsBatch> + NumberOfPoints + Send,
+    checkpoint: &Checkpoint,
+    checkpoint_state: &CheckpointState,
+    options: &BuildOptions,
+) -> Result<(
+    FnvHashMap<octree::NodeId, i64>,
+    FnvHashMap<octree::NodeId, AttributeRanges>,
+)> {
+    let attribute_data_types = &octree_meta.attribute_data_types_for(attributes)?;
     let octree_data_provider = OnDiskDataProvider {
-        directory: output_directory.as_ref().to_path_buf(),
+        directory: build_directory.to_path_buf(),
     };
     let octree_data_provider = &octree_data_provider;
+    let first_error = &Mutex::new(None);
+    let context = SplitContext {
+        octree_data_provider,
+        octree_meta,
+        attribute_data_types,
+        checkpoint,
+        deduplication: options.deduplication,
+        first_error,
+    };
 
-    // Ignore errors, maybe directory is already there.
-    let _ = fs::create_dir(output_directory.as_ref());
+    // Subtrees of a partition can be small enough to be a single leaf, just like they would be in
+    // a build that is not partitioned.
+    let num_points = input.num_points() as i64;
+    if root_id.level() > 0 && !should_split_node(&root_id, num_points, octree_meta) {
+        let mut writer =
+            RawNodeWriter::from_data_provider(octree_data_provider, octree_meta, &root_id);
+        let mut attribute_ranges = AttributeRanges::new();
+        for batch in input {
+            writer.write(&batch)?;
+            attribute_ranges.update(&batch);
+        }
+        drop(writer);
+        if let Some(policy) = options.deduplication {
+            attribute_ranges = deduplicate_node(context, &root_id, policy)?;
+        }
+        let num_points = octree_data_provider.number_of_points(&root_id.to_string())?;
+        return Ok((
+            iter::once((root_id, num_points)).collect(),
+            iter::once((root_id, attribute_ranges)).collect(),
+        ));
+    }
 
     eprintln!("Creating octree structure.");
 
+    let root_is_split = checkpoint_state.split_nodes.contains(&root_id);
+    let unfinished_splits = checkpoint_state.unfinished_splits();
     let (leaf_nodes_sender, leaf_nodes_receiver) = crossbeam::channel::unbounded();
     rayon::scope(move |scope| {
-        let root_node = octree::Node::root_with_bounding_cube(Cube::bounding(&bounding_box));
-        split_node(
-            scope,
-            octree_data_provider,
-            octree_meta,
-            attribute_data_types,
-            &root_node.id,
-            input,
-            &leaf_nodes_sender,
-        );
+        if root_is_split {
+            // The input has been fully distributed already, continue with the nodes from disk.
+            for node_id in unfinished_splits {
+                spawn_split_node_from_disk(scope, context, node_id, leaf_nodes_sender.clone());
+            }
+        } else {
+            context.record_error(split_node(
+                scope,
+                context,
+                &root_id,
+                input,
+                &leaf_nodes_sender,
+            ));
+        }
     });
-
-    let mut nodes_to_subsample = Vec::new();
-    let mut deepest_level = 0u8;
-    for id in leaf_nodes_receiver {
-        deepest_level = cmp::max(deepest_level, id.level());
-        nodes_to_subsample.push(id);
+    if let Some(err) = first_error.lock().unwrap().take() {
+        return Err(err);
     }
-    let mut finished_nodes = FnvHashMap::default();
+
+    let nodes_to_subsample = leaf_nodes_receiver
+        .into_iter()
+        .chain(checkpoint_state.leaf_nodes.iter().cloned())
+        .collect();
+    let mut finished_nodes = checkpoint_state.finished_nodes();
+    let mut attribute_ranges = FnvHashMap::default();
+    subsample_levels(
+        build_directory,
+        octree_meta,
+        attributes,
+        nodes_to_subsample,
+        root_id.level(),
+        checkpoint,
+        checkpoint_state,
+        &mut finished_nodes,
+        &mut attribute_ranges,
+    )?;
+    Ok((finished_nodes, attribute_ranges))
+}
+
+/// Builds all nodes above 'nodes_to_subsample' down to 'min_level' by subsampling, one level at a
+/// time, and adds them to 'finished_nodes'. The attribute ranges of the subsampled nodes are
+/// added to 'attribute_ranges', unless they were subsampled before the build was resumed.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn subsample_levels(
+    build_directory: &Path,
+    octree_meta: &octree::OctreeMeta,
+    attributes: &[&str],
+    mut nodes_to_subsample: Vec<octree::NodeId>,
+    min_level: u8,
+    checkpoint: &Checkpoint,
+    checkpoint_state: &CheckpointState,
+    finished_nodes: &mut FnvHashMap<octree::NodeId, i64>,
+    attribute_ranges: &mut FnvHashMap<octree::NodeId, AttributeRanges>,
+) -> Result<()> {
+    let attribute_data_types = &octree_meta.attribute_data_types_for(attributes)?;
+    let octree_data_provider = &OnDiskDataProvider {
+        directory: build_directory.to_path_buf(),
+    };
+    let staging_data_provider = &OnDiskDataProvider {
+        directory: build_directory.join(SUBSAMPLING_DIRECTORY),
+    };
+    let deepest_level = nodes_to_subsample
+        .iter()
+        .map(|id| id.level())
+        .max()
+        .unwrap_or(min_level);
 
     // sub sampling returns the list of finished nodes including all meta data
     // We start on the deepest level and work our way up the tree.
-    for current_level in (1..=deepest_level).rev() {
+    for current_level in (min_level + 1..=deepest_level).rev() {
         // All nodes on the same level can be subsampled in parallel.
         let res = nodes_to_subsample
             .into_iter()
             .partition(|n| n.level() == current_level);
         nodes_to_subsample = res.1;
 
-        // Unwrap is safe, since we stop at current_level = 1, so the root can never appear.
+        // Unwrap is safe, since we stop at current_level = min_level + 1, so the root can never
+        // appear.
         let parent_ids: FnvHashSet<_> = res
             .0
             .into_iter()
             .map(|id| id.parent_id().unwrap())
             .collect();
-        let mut progress_bar = create_progress_bar(
-            parent_ids.len(),
-            &format!("Building level {}", current_level - 1),
-        );
 
-        let (finished_nodes_sender, finished_nodes_receiver) = crossbeam::channel::unbounded();
-        let (progress_tx, progress_rx) = crossbeam::channel::unbounded();
-        rayon::scope(|scope| {
-            scope.spawn(|_| {
-                for (id, num_points) in finished_nodes_receiver {
-                    finished_nodes.insert(id, num_points);
+        if !checkpoint_state.finished_levels.contains(&current_level) {
+            let staged_nodes = match checkpoint_state.staged_levels.get(&current_level) {
+                Some(staged_nodes) => {
+                    // The points of these nodes were not in memory, so their ranges are unknown.
+                    for (id, _) in staged_nodes {
+                        attribute_ranges.remove(id);
+                    }
+                    staged_nodes.clone()
                 }
-            });
-
-            scope.spawn(|_| {
-                for _ in progress_rx {
-                    progress_bar.inc();
+                None => {
+                    // Discard whatever an interrupted attempt at this level left behind.
+                    let _ = fs::remove_dir_all(&staging_data_provider.directory);
+                    fs::create_dir(&staging_data_provider.directory)?;
+                    let staged_nodes: Vec<(octree::NodeId, i64)> = subsample_level(
+                        octree_data_provider,
+                        staging_data_provider,
+                        octree_meta,
+                        attribute_data_types,
+                        &parent_ids,
+                        current_level,
+                    )?
+                    .into_iter()
+                    .map(|(id, num_points, ranges)| {
+                        attribute_ranges.insert(id, ranges);
+                        (id, num_points)
+                    })
+                    .collect();
+                    checkpoint.record_level_staged(current_level, &staged_nodes)?;
+                    staged_nodes
                 }
-            });
-
-            parent_ids.par_iter().for_each(|id| {
-                subsample_children_into(
-                    octree_data_provider,
-                    octree_meta,
-                    attribute_data_types,
-                    id,
-                    &finished_nodes_sender,
-                )
-                .unwrap();
-                progress_tx.send(()).unwrap();
-            });
-            drop(finished_nodes_sender);
-            drop(progress_tx);
-        });
-        progress_bar.finish();
+            };
+            publish_staged_nodes(
+                staging_data_provider,
+                octree_data_provider,
+                attributes,
+                &staged_nodes,
+            )?;
+            checkpoint.record_level_done(current_level)?;
+            finished_nodes.extend(staged_nodes);
+        }
 
         // The nodes that were just now created through sub-sampling will be required to create
         // their parents.
         nodes_to_subsample.extend(parent_ids.into_iter());
     }
+    let _ = fs::remove_dir_all(&staging_data_provider.directory);
+    Ok(())
+}
+
+/// Reads the 'finished_nodes' whose attribute ranges are unknown once more to find the ranges of
+/// their scalar attributes, which queries use to skip nodes. This is only needed for nodes that
+/// were written before the build was resumed.
+fn compute_missing_attribute_ranges(
+    build_directory: &Path,
+    octree_meta: &octree::OctreeMeta,
+    attributes: &[&str],
+    finished_nodes: &FnvHashMap<octree::NodeId, i64>,
+    attribute_ranges: &mut FnvHashMap<octree::NodeId, AttributeRanges>,
+) -> Result<()> {
+    let data_provider = OnDiskDataProvider {
+        directory: build_directory.to_path_buf(),
+    };
+    let attribute_data_types = octree_meta.attribute_data_types_for(attributes)?;
+    let node_ids: Vec<octree::NodeId> = finished_nodes
+        .keys()
+        .filter(|id| !attribute_ranges.contains_key(id))
+        .cloned()
+        .collect();
+    let missing_attribute_ranges: Vec<(octree::NodeId, AttributeRanges)> = node_ids
+        .par_iter()
+        .map(|id| {
+            let mut ranges = AttributeRanges::new();
+            let num_points = finished_nodes[id] as usize;
+            if num_points > 0 {
+                let mut node_iterator = NodeIterator::from_data_provider(
+                    &data_provider,
+                    &attribute_data_types,
+                    octree_meta.encoding_for_node(*id),
+                    id,
+                    num_points,
+                    NUM_POINTS_PER_BATCH,
+                )?;
+                while let Some(batch) = node_iterator.try_next()? {
+                    ranges.update(&batch);
+                }
+            }
+            Ok((*id, ranges))
+        })
+        .collect::<Result<_>>()?;
+    attribute_ranges.extend(missing_attribute_ranges);
+    Ok(())
+}
 
+/// Writes the meta for 'finished_nodes' and moves the build into 'output_directory'.
+/// 'attribute_ranges' may be missing the ranges of some nodes, which are then read from disk.
+pub(super) fn finish_build(
+    output_directory: &Path,
+    octree_meta: &octree::OctreeMeta,
+    attributes: &[&str],
+    finished_nodes: &FnvHashMap<octree::NodeId, i64>,
+    mut attribute_ranges: FnvHashMap<octree::NodeId, AttributeRanges>,
+    checkpoint: Checkpoint,
+) -> Result<()> {
+    let build_directory = staging_directory(output_directory)?;
+    compute_missing_attribute_ranges(
+        &build_directory,
+        octree_meta,
+        attributes,
+        finished_nodes,
+        &mut attribute_ranges,
+    )?;
     // Add all non-zero node meta data to meta file
     let nodes: Vec<proto::OctreeNode> = finished_nodes
         .iter()
         .map(|(id, num_points)| {
             let bounding_cube = id.find_bounding_cube(&Cube::bounding(&octree_meta.bounding_box));
             let position_encoding = PositionEncoding::new(&bounding_cube, octree_meta.resolution);
-            to_node_proto(&id, *num_points, &position_encoding)
+            to_node_proto(&id, *num_points, &position_encoding, &attribute_ranges[id])
         })
         .collect();
     let meta = to_meta_proto(&octree_meta, nodes);
 
-    let mut buf_writer =
-        BufWriter::new(File::create(&output_directory.as_ref().join(META_FILENAME)).unwrap());
-    meta.write_to_writer(&mut buf_writer).unwrap();
+    write_meta(&build_directory, &meta)?;
+    checkpoint.remove()?;
+    publish_staged_build(output_directory)
+}
+
+pub fn build_octree_from_file(
+    output_directory: impl AsRef<Path>,
+    resolution: f64,
+    filename: impl AsRef<Path>,
+    attributes: &[&str],
+    options: &BuildOptions,
+) -> Result<()> {
+    let output_directory = output_directory.as_ref();
+    let previous = find_previous_build(output_directory, options)?;
+    // When resuming, the bounding box is part of the checkpoint, and we can skip a full pass over
+    // the input.
+    let bounding_box = match &previous {
+        PreviousBuild::Finished => return Ok(()),
+        PreviousBuild::Interrupted(checkpoint_state) => {
+            checkpoint_state.bounding_box.clone().unwrap()
+        }
+        PreviousBuild::NotFound => find_bounding_box(options.reproject(PlyIterator::from_file(
+            filename.as_ref(),
+            NUM_POINTS_PER_BATCH,
+        )?)),
+    };
+    let stream = PlyIterator::from_file(filename, NUM_POINTS_PER_BATCH)?;
+    continue_build(
+        output_directory,
+        resolution,
+        bounding_box,
+        stream,
+        attributes,
+        options,
+        previous,
+    )
+}
+
+/// Builds an octree in a staging directory next to 'output_directory' and moves it into place once
+/// it is complete. Progress is recorded in a checkpoint file, so that an interrupted build can be
+/// continued by setting 'options.resume'. The 'bounding_box' is given in the frame of
+/// 'options.crs', and 'input' in the frame of 'options.input_crs'.
+pub fn build_octree(
+    output_directory: impl AsRef<Path>,
+    resolution: f64,
+    bounding_box: Aabb<f64>,
+    input: impl Iterator<Item = PointsBatch> + NumberOfPoints + Send,
+    attributes: &[&str],
+    options: &BuildOptions,
+) -> Result<()> {
+    let output_directory = output_directory.as_ref();
+    let previous = find_previous_build(output_directory, options)?;
+    continue_build(
+        output_directory,
+        resolution,
+        bounding_box,
+        input,
+        attributes,
+        options,
+        previous,
+    )
+}
+
+/// Builds the octree for 'build_octree', continuing 'previous' if there is one.
+fn continue_build(
+    output_directory: &Path,
+    resolution: f64,
+    bounding_box: Aabb<f64>,
+    input: impl Iterator<Item = PointsBatch> + NumberOfPoints + Send,
+    attributes: &[&str],
+    options: &BuildOptions,
+    previous: PreviousBuild,
+) -> Result<()> {
+    let (checkpoint, checkpoint_state) = match open_build(
+        output_directory,
+        resolution,
+        bounding_box,
+        attributes,
+        input.num_points(),
+        options,
+        previous,
+    )? {
+        Some(build) => build,
+        None => return Ok(()),
+    };
+    let bounding_box = checkpoint_state.bounding_box.clone().unwrap();
+    let mut octree_meta =
+        octree::OctreeMeta::new_with_standard_attributes(resolution, bounding_box);
+    octree_meta.crs = options.crs;
+    octree_meta.label_dictionaries = options.label_dictionaries.clone();
+    let root_id =
+        octree::Node::root_with_bounding_cube(Cube::bounding(&octree_meta.bounding_box)).id;
+    let (finished_nodes, attribute_ranges) = build_subtree(
+        &staging_directory(output_directory)?,
+        &octree_meta,
+        attributes,
+        root_id,
+        options.reproject(input),
+        &checkpoint,
+        &checkpoint_state,
+        options,
+    )?;
+    finish_build(
+        output_directory,
+        &octree_meta,
+        attributes,
+        &finished_nodes,
+        attribute_ranges,
+        checkpoint,
+    )
 }
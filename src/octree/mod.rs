@@ -11,13 +11,15 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::attributes::{AttributeRanges, LabelDictionaries, LabelDictionary};
 use crate::data_provider::DataProvider;
 use crate::errors::*;
-use crate::geometry::{Aabb, Cube, Frustum};
-use crate::iterator::{PointCloud, PointLocation};
+use crate::geometry::{Aabb, Cube, Frustum, Ray};
+use crate::iterator::{PointCloud, PointLocation, PointQuery};
 use crate::math::base::{HasAabbIntersector, IntersectAabb};
 use crate::math::sat::{ConvexPolyhedron, Relation};
-use crate::math::AllPoints;
+use crate::math::{AllPoints, Crs};
+use crate::picking::{self, PickedPoint};
 use crate::proto;
 use crate::read_write::{Encoding, NodeIterator, PositionEncoding};
 use crate::{AttributeDataType, PointCloudMeta, CURRENT_VERSION};
@@ -28,8 +30,16 @@ use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::io::{BufReader, Read};
 
+mod check;
+pub use self::check::{check_octree, repair_octree, CheckReport, Problem};
+
+mod checkpoint;
+
+mod deduplication;
+pub use self::deduplication::{deduplicate, DeduplicationPolicy};
+
 mod generation;
-pub use self::generation::{build_octree, build_octree_from_file};
+pub use self::generation::{build_octree, build_octree_from_file, BuildOptions};
 
 mod node;
 pub use self::node::{to_node_proto, ChildIndex, Node, NodeId, NodeMeta};
@@ -37,13 +47,21 @@ pub use self::node::{to_node_proto, ChildIndex, Node, NodeId, NodeMeta};
 mod octree_iterator;
 pub use self::octree_iterator::NodeIdsIterator;
 
+mod sharding;
+pub use self::sharding::{
+    build_subtree_from_partition, merge_subtrees, partition_octree, partition_octree_from_file,
+};
+
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
 
 #[derive(Clone, Debug)]
 pub struct OctreeMeta {
     pub resolution: f64,
     pub bounding_box: Aabb<f64>,
+    /// The frame of the positions, if known.
+    pub crs: Option<Crs>,
+    pub label_dictionaries: LabelDictionaries,
     attribute_data_types: HashMap<String, AttributeDataType>,
 }
 
@@ -69,6 +87,8 @@ impl OctreeMeta {
         Self {
             resolution,
             bounding_box,
+            crs: None,
+            label_dictionaries: LabelDictionaries::new(),
             attribute_data_types,
         }
     }
@@ -95,6 +115,12 @@ pub fn to_meta_proto(octree_meta: &OctreeMeta, nodes: Vec<proto::OctreeNode>) ->
     meta.set_version(CURRENT_VERSION);
     meta.set_bounding_box(proto::AxisAlignedCuboid::from(&octree_meta.bounding_box));
     meta.set_octree(octree_proto);
+    if let Some(crs) = octree_meta.crs {
+        meta.set_crs(crs.to_proto());
+    }
+    meta.set_label_dictionaries(::protobuf::RepeatedField::from_vec(
+        octree_meta.label_dictionaries.to_proto(),
+    ));
     meta
 }
 
@@ -154,6 +180,7 @@ pub struct NodeData {
 impl Octree {
     // TODO(sirver): This creates an object that is only partially usable.
     pub fn from_data_provider(data_provider: Box<dyn DataProvider>) -> Result<Self> {
+        data_provider.check_complete()?;
         let meta_proto = data_provider.meta_proto()?;
         if meta_proto.version < CURRENT_VERSION {
             eprintln!(
@@ -162,7 +189,7 @@ impl Octree {
                 meta_proto.version, CURRENT_VERSION
             );
         }
-        let (bounding_box, meta, nodes_proto) = match meta_proto.version {
+        let (bounding_box, mut meta, nodes_proto) = match meta_proto.version {
             9 | 10 | 11 => {
                 let bounding_box = Aabb::from(meta_proto.get_bounding_box());
                 (
@@ -192,6 +219,9 @@ impl Octree {
             }
             _ => return Err(ErrorKind::InvalidVersion(meta_proto.version).into()),
         };
+        meta.crs = Crs::from_proto(meta_proto.get_crs())?;
+        meta.label_dictionaries =
+            LabelDictionaries::from_proto(meta_proto.get_label_dictionaries());
 
         let mut nodes = FnvHashMap::default();
 
@@ -203,6 +233,9 @@ impl Octree {
                     num_points: node_proto.num_points,
                     position_encoding: PositionEncoding::from_proto(node_proto.position_encoding)?,
                     bounding_cube: node_id.find_bounding_cube(&Cube::bounding(&bounding_box)),
+                    attribute_ranges: AttributeRanges::from_proto(
+                        node_proto.get_attribute_ranges(),
+                    ),
                 },
             );
         }
@@ -219,12 +252,31 @@ impl Octree {
             .nodes
             .iter()
             .map(|(id, node_meta)| {
-                to_node_proto(&id, node_meta.num_points, &node_meta.position_encoding)
+                to_node_proto(
+                    &id,
+                    node_meta.num_points,
+                    &node_meta.position_encoding,
+                    &node_meta.attribute_ranges,
+                )
             })
             .collect();
         to_meta_proto(&self.meta, nodes)
     }
 
+    /// Like `PointCloud::pick_point`, but only considers nodes up to 'max_level', i.e. the
+    /// coarser levels of detail.
+    pub fn pick_point_up_to_level(
+        &self,
+        ray: &Ray<f64>,
+        radius: f64,
+        max_level: u8,
+        attributes: &[&str],
+    ) -> Result<Option<PickedPoint>> {
+        picking::pick_point(self, ray, radius, attributes, |node_id: NodeId| {
+            node_id.level() <= max_level
+        })
+    }
+
     pub fn get_visible_nodes(&self, projection_matrix: &Matrix4<f64>) -> Vec<NodeId> {
         let frustum =
             Frustum::from_matrix4(*projection_matrix).expect("Invalid projection matrix.");
@@ -311,26 +363,74 @@ impl Octree {
 
     fn nodes_in_location_impl<'a, T: HasAabbIntersector<'a, f64>>(
         &self,
+        max_level: u8,
         location: &'a T,
-    ) -> Vec<NodeId> {
-        // TODO(nnmm): Once intersection tests use Relation, this function can traverse the octree
-        // with the same strategy as get_visible_nodes(), skipping over fully-included nodes. Then
-        // it's a generalized version of get_visible_nodes(), and get_visible_nodes() can use this
-        // function instead.
+    ) -> Vec<(NodeId, Relation)> {
+        // TODO(nnmm): This is now a generalized version of get_visible_nodes(), apart from the
+        // size on screen, so get_visible_nodes() could use this function instead.
         let isec = location.aabb_intersector();
+        // The iterator is breadth-first, so all following nodes are deeper.
         NodeIdsIterator::new(&self, |node_id, octree| {
             let aabb = octree.nodes[&node_id].bounding_cube.to_aabb();
             isec.intersect_aabb(&aabb)
         })
+        .take_while(|(node_id, _)| node_id.level() <= max_level)
         .collect()
     }
+
+    /// Returns how many of the leading 'nodes', which are sorted by level, make up the most
+    /// levels whose total number of points is within 'point_budget'. The first level is always
+    /// included, so that a small budget still returns the coarsest points instead of none.
+    fn num_nodes_within_budget(&self, nodes: &[(NodeId, Relation)], point_budget: usize) -> usize {
+        let mut num_points = 0;
+        let mut num_nodes = 0;
+        for (i, (node_id, _)) in nodes.iter().enumerate() {
+            if i > 0 && node_id.level() != nodes[i - 1].0.level() {
+                // Only the first level can exceed the budget here.
+                if num_points > point_budget {
+                    return i;
+                }
+                num_nodes = i;
+            }
+            num_points += self.nodes[node_id].num_points as usize;
+            if num_points > point_budget && num_nodes > 0 {
+                return num_nodes;
+            }
+        }
+        nodes.len()
+    }
 }
 
 impl PointCloud for Octree {
     type Id = NodeId;
 
-    fn nodes_in_location(&self, location: &PointLocation) -> Vec<Self::Id> {
-        dispatch_point_location!(Octree::nodes_in_location_impl, location, &self)
+    fn nodes_in_location(&self, location: &PointLocation) -> Vec<(Self::Id, Relation)> {
+        dispatch_point_location!(
+            Octree::nodes_in_location_impl,
+            location,
+            &self,
+            std::u8::MAX
+        )
+    }
+
+    fn nodes_for_query(&self, query: &PointQuery) -> Vec<(Self::Id, Relation)> {
+        let max_level = query.max_level.unwrap_or(std::u8::MAX);
+        // Unknown labels are reported when the points are read.
+        let filter = self.filter_expression(query).unwrap_or(None);
+        let mut nodes: Vec<(NodeId, Relation)> = dispatch_point_location!(
+            Octree::nodes_in_location_impl,
+            &query.location,
+            &self,
+            max_level
+        )
+        .into_iter()
+        .filter(|(node_id, _)| self.filter_relation(filter.as_ref(), *node_id) != Relation::Out)
+        .collect();
+        if let Some(point_budget) = query.point_budget {
+            let num_nodes = self.num_nodes_within_budget(&nodes, point_budget);
+            nodes.truncate(num_nodes);
+        }
+        nodes
     }
 
     fn encoding_for_node(&self, id: Self::Id) -> Encoding {
@@ -358,6 +458,32 @@ impl PointCloud for Octree {
     fn bounding_box(&self) -> &Aabb<f64> {
         &self.meta.bounding_box
     }
+
+    fn crs(&self) -> Option<Crs> {
+        self.meta.crs
+    }
+
+    fn attribute_data_types(&self) -> &HashMap<String, AttributeDataType> {
+        self.meta.attribute_data_types()
+    }
+
+    fn label_dictionary(&self, attribute: &str) -> Option<&LabelDictionary> {
+        self.meta.label_dictionaries.get(attribute)
+    }
+
+    fn attribute_ranges(&self, node_id: Self::Id) -> Option<&AttributeRanges> {
+        self.nodes
+            .get(&node_id)
+            .map(|node_meta| &node_meta.attribute_ranges)
+    }
+
+    fn num_points_in_node(&self, node_id: Self::Id) -> usize {
+        self.nodes[&node_id].num_points as usize
+    }
+
+    fn distance_to_node(&self, node_id: Self::Id, point: &Point3<f64>) -> f64 {
+        self.nodes[&node_id].bounding_cube.to_aabb().distance(point)
+    }
 }
 
 struct OpenNode {
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::attributes::AttributeRanges;
 use crate::geometry::Cube;
 use crate::proto;
 use crate::read_write::PositionEncoding;
@@ -51,8 +52,8 @@ impl ChildIndex {
 /// might change though.
 // Top 8 bits of the value are level, the rest is the index.
 // The root has level = 0, its children 1 and so on. Multiple nodes can have the same index,
-// but none can have the same index and level.
-#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
+// but none can have the same index and level. Hence node ids are ordered by level, then index.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NodeId(u128);
 
 impl FromStr for NodeId {
@@ -249,6 +250,7 @@ pub struct NodeMeta {
     pub num_points: i64,
     pub position_encoding: PositionEncoding,
     pub bounding_cube: Cube,
+    pub attribute_ranges: AttributeRanges,
 }
 
 impl NodeMeta {
@@ -261,11 +263,15 @@ pub fn to_node_proto(
     node_id: &NodeId,
     num_points: i64,
     position_encoding: &PositionEncoding,
+    attribute_ranges: &AttributeRanges,
 ) -> proto::OctreeNode {
     let mut proto = proto::OctreeNode::new();
     *proto.mut_id() = node_id.to_proto();
     proto.set_num_points(num_points);
     proto.set_position_encoding(position_encoding.to_proto());
+    proto.set_attribute_ranges(::protobuf::RepeatedField::from_vec(
+        attribute_ranges.to_proto(),
+    ));
     proto
 }
 
@@ -1,42 +1,49 @@
+use crate::math::Relation;
 use crate::octree::{ChildIndex, NodeId, Octree};
 use std::collections::VecDeque;
 
+/// Iterates over the nodes of an octree top-down, together with their relation to a query
+/// location. Children of nodes that are outside are skipped, and children of nodes that are
+/// completely inside are inside as well, so they are not tested again.
 pub struct NodeIdsIterator<'a, F> {
     octree: &'a Octree,
-    filter_func: F,
-    node_ids: VecDeque<NodeId>,
+    relation_func: F,
+    node_ids: VecDeque<(NodeId, Relation)>,
 }
 
-// TODO(nnmm): Make more efficient by using Relation
 impl<'a, F> NodeIdsIterator<'a, F>
 where
-    F: Fn(&NodeId, &Octree) -> bool,
+    F: Fn(&NodeId, &Octree) -> Relation,
 {
-    pub fn new(octree: &'a Octree, filter_func: F) -> NodeIdsIterator<'a, F> {
+    pub fn new(octree: &'a Octree, relation_func: F) -> NodeIdsIterator<'a, F> {
         NodeIdsIterator {
             octree,
-            node_ids: vec![NodeId::from_level_index(0, 0)].into(),
-            filter_func,
+            node_ids: vec![(NodeId::from_level_index(0, 0), Relation::Cross)].into(),
+            relation_func,
         }
     }
 }
 
 impl<'a, F> Iterator for NodeIdsIterator<'a, F>
 where
-    F: Fn(&NodeId, &'a Octree) -> bool,
+    F: Fn(&NodeId, &'a Octree) -> Relation,
 {
-    type Item = NodeId;
+    type Item = (NodeId, Relation);
 
-    fn next(&mut self) -> Option<NodeId> {
-        while let Some(current) = self.node_ids.pop_front() {
-            if (self.filter_func)(&current, &self.octree) {
+    fn next(&mut self) -> Option<(NodeId, Relation)> {
+        while let Some((current, parent_relation)) = self.node_ids.pop_front() {
+            let relation = match parent_relation {
+                Relation::In => Relation::In,
+                _ => (self.relation_func)(&current, &self.octree),
+            };
+            if relation != Relation::Out {
                 for child_index in 0..8 {
                     let child_id = current.get_child_id(ChildIndex::from_u8(child_index));
                     if self.octree.nodes.contains_key(&child_id) {
-                        self.node_ids.push_back(child_id);
+                        self.node_ids.push_back((child_id, relation));
                     }
                 }
-                return Some(current);
+                return Some((current, relation));
             }
         }
         None
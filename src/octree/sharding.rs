@@ -0,0 +1,367 @@
+//! Building an octree in independent pieces, e.g. on several machines.
+//!
+//! 1. `partition_octree` distributes the input into the nodes at a chosen partition level.
+//! 2. `build_subtree_from_partition` builds the complete subtree below one of these nodes. The
+//!    subtrees share the bounding cube of the partition, so they can be built in any order and
+//!    in separate processes.
+//! 3. `merge_subtrees` combines all subtrees and builds the levels above the partition level.
+
+use crate::attributes::AttributeRanges;
+use crate::data_provider::OnDiskDataProvider;
+use crate::errors::*;
+use crate::geometry::{Aabb, Cube};
+use crate::octree::generation::{
+    build_subtree, find_bounding_box, find_previous_build, finish_build, open_build,
+    subsample_levels, BuildOptions,
+};
+use crate::octree::{self, to_meta_proto, to_node_proto, ChildIndex, NodeId, Octree};
+use crate::proto;
+use crate::read_write::{
+    attempt_increasing_rlimit_to_max, publish_staged_build, staging_directory, start_staged_build,
+    write_meta, NodeIterator, NodeWriter, OpenMode, PlyIterator, PositionEncoding, RawNodeWriter,
+};
+use crate::{
+    attribute_extension, NumberOfPoints, PointCloudMeta, PointsBatch, NUM_POINTS_PER_BATCH,
+};
+use fnv::{FnvHashMap, FnvHashSet};
+use lru::LruCache;
+use nalgebra::Point3;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::Path;
+
+/// The actual number of open files is MAX_NUM_NODE_WRITERS * num_attributes.
+const MAX_NUM_NODE_WRITERS: usize = 256;
+
+fn open_octree(directory: &Path) -> Result<Octree> {
+    Octree::from_data_provider(Box::new(OnDiskDataProvider::new(directory.to_path_buf())))
+        .chain_err(|| format!("Could not open octree in {}", directory.display()))
+}
+
+/// Returns the node at 'level' that contains 'position'.
+fn node_at_level(root_bounding_cube: &Cube, position: &Point3<f64>, level: u8) -> NodeId {
+    let mut node = octree::Node::root_with_bounding_cube(root_bounding_cube.clone());
+    for _ in 0..level {
+        node = node.get_child(ChildIndex::from_bounding_cube(
+            &node.bounding_cube,
+            position,
+        ));
+    }
+    node.id
+}
+
+/// Distributes the points of 'input' into the nodes at 'partition_level' and writes them into
+/// 'output_directory'. Each of these nodes is the root of a subtree that can then be built with
+/// 'build_subtree_from_partition'. Returns the ids of these subtree roots.
+/// The input is converted from 'options.input_crs' to 'options.crs', which is recorded in the meta
+/// of the partition together with 'options.label_dictionaries', and from there in the subtrees and
+/// the merged octree. The 'bounding_box' is given in the frame of 'options.crs'. Resuming and
+/// deduplication only apply to the subtrees.
+pub fn partition_octree(
+    output_directory: impl AsRef<Path>,
+    resolution: f64,
+    bounding_box: Aabb<f64>,
+    input: impl Iterator<Item = PointsBatch> + NumberOfPoints,
+    attributes: &[&str],
+    partition_level: u8,
+    options: &BuildOptions,
+) -> Result<Vec<NodeId>> {
+    attempt_increasing_rlimit_to_max();
+
+    let output_directory = output_directory.as_ref();
+    let build_directory = start_staged_build(output_directory)?;
+    let mut octree_meta =
+        octree::OctreeMeta::new_with_standard_attributes(resolution, bounding_box);
+    octree_meta.crs = options.crs;
+    octree_meta.label_dictionaries = options.label_dictionaries.clone();
+    // Check early that all attributes are supported.
+    octree_meta.attribute_data_types_for(attributes)?;
+    let data_provider = OnDiskDataProvider {
+        directory: build_directory,
+    };
+    let root_bounding_cube = Cube::bounding(&octree_meta.bounding_box);
+
+    eprintln!(
+        "Partitioning {} points into subtrees at level {}.",
+        input.num_points(),
+        partition_level
+    );
+    let mut writers: LruCache<NodeId, RawNodeWriter> = LruCache::new(MAX_NUM_NODE_WRITERS);
+    let mut num_points: FnvHashMap<NodeId, i64> = FnvHashMap::default();
+    for batch in options.reproject(input) {
+        let node_ids: Vec<NodeId> = batch
+            .position
+            .iter()
+            .map(|p| node_at_level(&root_bounding_cube, p, partition_level))
+            .collect();
+        let distinct_node_ids: FnvHashSet<NodeId> = node_ids.iter().cloned().collect();
+        for node_id in distinct_node_ids {
+            let keep: Vec<bool> = node_ids.iter().map(|id| *id == node_id).collect();
+            let mut node_batch = batch.clone();
+            node_batch.retain(&keep);
+            if !writers.contains(&node_id) {
+                // Nodes that were evicted from the cache before must not lose their points.
+                let open_mode = if num_points.contains_key(&node_id) {
+                    OpenMode::Append
+                } else {
+                    OpenMode::Truncate
+                };
+                let writer = RawNodeWriter::new(
+                    data_provider.stem(&node_id.to_string()),
+                    octree_meta.encoding_for_node(node_id),
+                    open_mode,
+                );
+                writers.put(node_id, writer);
+            }
+            writers.get_mut(&node_id).unwrap().write(&node_batch)?;
+            *num_points.entry(node_id).or_insert(0) += node_batch.position.len() as i64;
+        }
+    }
+    // Flush all nodes before publishing.
+    drop(writers);
+
+    let nodes: Vec<proto::OctreeNode> = num_points
+        .iter()
+        .map(|(id, num_points)| {
+            let bounding_cube = id.find_bounding_cube(&root_bounding_cube);
+            let position_encoding = PositionEncoding::new(&bounding_cube, octree_meta.resolution);
+            // Partitions are only an intermediate step, so their attribute ranges are not needed.
+            to_node_proto(id, *num_points, &position_encoding, &AttributeRanges::new())
+        })
+        .collect();
+    write_meta(
+        &data_provider.directory,
+        &to_meta_proto(&octree_meta, nodes),
+    )?;
+    publish_staged_build(output_directory)?;
+
+    let mut subtree_roots: Vec<NodeId> = num_points.keys().cloned().collect();
+    subtree_roots.sort_by_key(|id| id.index());
+    Ok(subtree_roots)
+}
+
+pub fn partition_octree_from_file(
+    output_directory: impl AsRef<Path>,
+    resolution: f64,
+    filename: impl AsRef<Path>,
+    attributes: &[&str],
+    partition_level: u8,
+    options: &BuildOptions,
+) -> Result<Vec<NodeId>> {
+    let bounding_box = find_bounding_box(options.reproject(PlyIterator::from_file(
+        filename.as_ref(),
+        NUM_POINTS_PER_BATCH,
+    )?));
+    let stream = PlyIterator::from_file(filename, NUM_POINTS_PER_BATCH)?;
+    partition_octree(
+        output_directory,
+        resolution,
+        bounding_box,
+        stream,
+        attributes,
+        partition_level,
+        options,
+    )
+}
+
+/// Builds the subtree below 'root_id' from the partition in 'partition_directory' into
+/// 'output_directory'. The subtree takes its frame and label dictionaries from the partition,
+/// which is already converted, so 'options.crs', 'options.input_crs' and
+/// 'options.label_dictionaries' are ignored.
+pub fn build_subtree_from_partition(
+    output_directory: impl AsRef<Path>,
+    partition_directory: impl AsRef<Path>,
+    root_id: NodeId,
+    attributes: &[&str],
+    options: &BuildOptions,
+) -> Result<()> {
+    let partition = open_octree(partition_directory.as_ref())?;
+    let num_points = partition
+        .nodes
+        .get(&root_id)
+        .ok_or_else(|| {
+            ErrorKind::InvalidInput(format!("{} is not a subtree of the partition.", root_id))
+        })?
+        .num_points;
+    let input = NodeIterator::from_data_provider(
+        &*partition.data_provider,
+        &partition.meta.attribute_data_types_for(attributes)?,
+        partition.meta.encoding_for_node(root_id),
+        &root_id,
+        num_points as usize,
+        NUM_POINTS_PER_BATCH,
+    )?;
+
+    let options = BuildOptions {
+        crs: partition.meta.crs,
+        input_crs: None,
+        ..options.clone()
+    };
+    let output_directory = output_directory.as_ref();
+    let (checkpoint, checkpoint_state) = match open_build(
+        output_directory,
+        partition.meta.resolution,
+        partition.meta.bounding_box.clone(),
+        attributes,
+        num_points as usize,
+        &options,
+        find_previous_build(output_directory, &options)?,
+    )? {
+        Some(build) => build,
+        None => return Ok(()),
+    };
+    let (finished_nodes, attribute_ranges) = build_subtree(
+        &staging_directory(output_directory)?,
+        &partition.meta,
+        attributes,
+        root_id,
+        input,
+        &checkpoint,
+        &checkpoint_state,
+        &options,
+    )?;
+    finish_build(
+        output_directory,
+        &partition.meta,
+        attributes,
+        &finished_nodes,
+        attribute_ranges,
+        checkpoint,
+    )
+}
+
+/// Hard links 'from' to 'to', and falls back to copying if that is not possible, e.g. because the
+/// files are on different file systems.
+fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Combines the subtrees in 'subtree_directories', which must have been built from the same
+/// partition, into one octree in 'output_directory'. The subtrees are left untouched.
+pub fn merge_subtrees(
+    output_directory: impl AsRef<Path>,
+    subtree_directories: &[impl AsRef<Path>],
+    attributes: &[&str],
+) -> Result<()> {
+    let output_directory = output_directory.as_ref();
+    let subtrees = subtree_directories
+        .iter()
+        .map(|directory| open_octree(directory.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let first = subtrees
+        .first()
+        .ok_or_else(|| ErrorKind::InvalidInput("No subtrees to merge.".to_string()))?;
+    let octree_meta = first.meta.clone();
+    octree_meta.attribute_data_types_for(attributes)?;
+
+    let mut subtree_roots = Vec::new();
+    for (subtree, directory) in subtrees.iter().zip(subtree_directories) {
+        if subtree.meta.resolution != octree_meta.resolution
+            || subtree.meta.bounding_box != octree_meta.bounding_box
+            || subtree.meta.crs != octree_meta.crs
+            || subtree.meta.label_dictionaries != octree_meta.label_dictionaries
+        {
+            return Err(ErrorKind::InvalidInput(format!(
+                "The subtree in {} was built from a different partition.",
+                directory.as_ref().display()
+            ))
+            .into());
+        }
+        // The root is the only node on the topmost level of a subtree.
+        let root_id = subtree
+            .nodes
+            .keys()
+            .min_by_key(|id| id.level())
+            .cloned()
+            .ok_or_else(|| {
+                ErrorKind::InvalidInput(format!(
+                    "The subtree in {} is empty.",
+                    directory.as_ref().display()
+                ))
+            })?;
+        if subtree_roots
+            .first()
+            .map_or(false, |first: &NodeId| first.level() != root_id.level())
+        {
+            return Err(ErrorKind::InvalidInput(
+                "All subtrees need to start at the same level.".to_string(),
+            )
+            .into());
+        }
+        if subtree_roots.contains(&root_id) {
+            return Err(ErrorKind::InvalidInput(format!(
+                "The subtree below {} was given more than once.",
+                root_id
+            ))
+            .into());
+        }
+        subtree_roots.push(root_id);
+    }
+
+    // The subtrees are the input of the merge.
+    let num_input_points = subtrees
+        .iter()
+        .flat_map(|subtree| subtree.nodes.values())
+        .map(|node| node.num_points as usize)
+        .sum();
+    let options = BuildOptions {
+        crs: octree_meta.crs,
+        ..Default::default()
+    };
+    let (checkpoint, checkpoint_state) = match open_build(
+        output_directory,
+        octree_meta.resolution,
+        octree_meta.bounding_box.clone(),
+        attributes,
+        num_input_points,
+        &options,
+        find_previous_build(output_directory, &options)?,
+    )? {
+        Some(build) => build,
+        None => return Ok(()),
+    };
+    let build_directory = staging_directory(output_directory)?;
+
+    eprintln!("Merging {} subtrees.", subtrees.len());
+    let mut finished_nodes = FnvHashMap::default();
+    // The ranges of the subtree roots and all nodes above are replaced while subsampling.
+    let mut attribute_ranges = FnvHashMap::default();
+    for (subtree, directory) in subtrees.iter().zip(subtree_directories) {
+        for (id, node_meta) in &subtree.nodes {
+            for attribute in iter::once(&"position").chain(attributes) {
+                let file_name =
+                    Path::new(&id.to_string()).with_extension(attribute_extension(attribute));
+                let from = directory.as_ref().join(&file_name);
+                if from.exists() {
+                    link_or_copy(&from, &build_directory.join(&file_name))?;
+                }
+            }
+            finished_nodes.insert(*id, node_meta.num_points);
+            attribute_ranges.insert(*id, node_meta.attribute_ranges.clone());
+        }
+    }
+
+    subsample_levels(
+        &build_directory,
+        &octree_meta,
+        attributes,
+        subtree_roots,
+        0,
+        &checkpoint,
+        &checkpoint_state,
+        &mut finished_nodes,
+        &mut attribute_ranges,
+    )?;
+    finish_build(
+        output_directory,
+        &octree_meta,
+        attributes,
+        &finished_nodes,
+        attribute_ranges,
+        checkpoint,
+    )
+}
@@ -1,13 +1,29 @@
+use crate::aggregation::Aggregations;
+use crate::attributes::{AttributeRanges, LabelDictionaries, LabelDictionary};
+use crate::color::Color;
 use crate::data_provider::OnDiskDataProvider;
-use crate::errors::Result;
-use crate::geometry::Aabb;
-use crate::iterator::{ParallelIterator, PointQuery};
-use crate::octree::{build_octree, Octree};
+use crate::errors::{Error, ErrorKind, Result};
+use crate::geometry::{Aabb, Ray};
+use crate::iterator::{
+    OwnedPointQuery, ParallelIterator, PointCloud, PointLocation, PointQuery, PointsIterator,
+    QueryHandle,
+};
+use crate::math::{ClosedInterval, Crs, Relation};
+use crate::octree::checkpoint::{Checkpoint, CRASH_AFTER};
+use crate::octree::{
+    build_octree, build_subtree_from_partition, check_octree, merge_subtrees, partition_octree,
+    repair_octree, BuildOptions, DeduplicationPolicy, NodeId, Octree, Problem,
+};
+use crate::read_write::{staging_directory, start_staged_build};
 use crate::{AttributeData, NumberOfPoints, PointsBatch};
-use nalgebra::{Point3, Vector3};
+use futures::Stream;
+use nalgebra::{Isometry3, Point3, Vector3};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tempdir::TempDir;
 
-const NUM_POINTS: usize = 100_001;
+pub(crate) const NUM_POINTS: usize = 100_001;
 
 impl NumberOfPoints for std::vec::IntoIter<PointsBatch> {
     fn num_points(&self) -> usize {
@@ -15,7 +31,8 @@ impl NumberOfPoints for std::vec::IntoIter<PointsBatch> {
     }
 }
 
-fn build_test_octree() -> Octree {
+/// All but one point are at the origin.
+fn coincident_points() -> (PointsBatch, Aabb<f64>) {
     let mut batch = PointsBatch {
         position: vec![Point3::new(0.0, 0.0, 0.0); NUM_POINTS],
         attributes: vec![(
@@ -29,20 +46,42 @@ fn build_test_octree() -> Octree {
     batch.position[NUM_POINTS - 1] = Point3::new(-200., -40., 30.);
 
     let bounding_box = Aabb::new(batch.position[0], batch.position[NUM_POINTS - 1]);
+    (batch, bounding_box)
+}
 
+/// Builds an octree in a temporary directory, which is removed when the returned 'TempDir' is
+/// dropped.
+pub(crate) fn build_and_open_octree(
+    resolution: f64,
+    bounding_box: Aabb<f64>,
+    batches: Vec<PointsBatch>,
+    attributes: &[&str],
+    options: &BuildOptions,
+) -> (TempDir, Octree) {
     let tmp_dir = TempDir::new("octree").unwrap();
-
+    let output_directory = tmp_dir.path().join("octree");
     build_octree(
-        &tmp_dir,
+        &output_directory,
+        resolution,
+        bounding_box,
+        batches.into_iter(),
+        attributes,
+        options,
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    (tmp_dir, octree)
+}
+
+fn build_test_octree() -> (TempDir, Octree) {
+    let (batch, bounding_box) = coincident_points();
+    build_and_open_octree(
         1.0,
         bounding_box,
-        vec![batch].into_iter(),
+        vec![batch],
         &["color"],
-    );
-    Octree::from_data_provider(Box::new(OnDiskDataProvider {
-        directory: tmp_dir.into_path(),
-    }))
-    .unwrap()
+        &BuildOptions::default(),
+    )
 }
 
 struct Consumer {
@@ -86,7 +125,7 @@ fn test_batch_iterator() {
     let max_num_points = 13_000;
     let mut c = Consumer::new(max_num_points);
     // octree and iterator
-    let octree = build_test_octree();
+    let (_tmp_dir, octree) = build_test_octree();
     let location = PointQuery {
         attributes: vec!["color"],
         ..Default::default()
@@ -120,7 +159,7 @@ fn test_batch_iterator_more_points() {
     let mut c = Consumer::new(max_num_points);
 
     // octree and iterator
-    let octree = build_test_octree();
+    let (_tmp_dir, octree) = build_test_octree();
     let location = PointQuery {
         attributes: vec!["color"],
         ..Default::default()
@@ -134,3 +173,1332 @@ fn test_batch_iterator_more_points() {
         .expect("Iterator errored even though callback should not have errored.");
     assert_eq!(c.num_received_points, NUM_POINTS);
 }
+
+pub(crate) fn spread_out_points() -> (PointsBatch, Aabb<f64>) {
+    let batch = PointsBatch {
+        position: (0..NUM_POINTS)
+            .map(|i| Point3::new(i as f64 * 0.01, (i % 100) as f64, (i % 7) as f64))
+            .collect(),
+        attributes: vec![(
+            "color".to_string(),
+            AttributeData::U8Vec3(vec![Vector3::new(0, 255, 0); NUM_POINTS]),
+        )]
+        .into_iter()
+        .collect(),
+    };
+    let bounding_box = Aabb::new(
+        batch.position[0],
+        Point3::new((NUM_POINTS - 1) as f64 * 0.01, 99., 6.),
+    );
+    (batch, bounding_box)
+}
+
+fn count_points(octree: &Octree) -> usize {
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+    count_points_for_query(octree, &query)
+}
+
+fn count_points_for_query(octree: &Octree, query: &PointQuery) -> usize {
+    let mut num_points = 0;
+    ParallelIterator::new(std::slice::from_ref(octree), query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            num_points += points_batch.position.len();
+            Ok(())
+        })
+        .unwrap();
+    num_points
+}
+
+fn open_octree(directory: PathBuf) -> Octree {
+    Octree::from_data_provider(Box::new(OnDiskDataProvider::new(directory))).unwrap()
+}
+
+/// Adds an intensity that grows with the index of the points.
+fn with_intensity(mut batch: PointsBatch) -> PointsBatch {
+    batch.attributes.insert(
+        "intensity".to_string(),
+        AttributeData::F32((0..batch.position.len()).map(|i| i as f32).collect()),
+    );
+    batch
+}
+
+/// The number of points of every node, sorted by node id, to compare the structure of octrees.
+fn node_sizes(octree: &Octree) -> Vec<(String, i64)> {
+    let mut sizes: Vec<(String, i64)> = octree
+        .nodes
+        .iter()
+        .map(|(id, node_meta)| (id.to_string(), node_meta.num_points))
+        .collect();
+    sizes.sort();
+    sizes
+}
+
+/// Checks that the attribute ranges of every node are the ones of its points.
+fn assert_attribute_ranges_match_points(octree: &Octree, attributes: &[&str]) {
+    for (id, node_meta) in &octree.nodes {
+        let mut attribute_ranges = AttributeRanges::new();
+        if node_meta.num_points > 0 {
+            for batch in octree.points_in_node(attributes, *id, 4096).unwrap() {
+                attribute_ranges.update(&batch);
+            }
+        }
+        assert_eq!(
+            node_meta.attribute_ranges, attribute_ranges,
+            "Wrong attribute ranges for {}.",
+            id
+        );
+    }
+}
+
+#[test]
+fn test_resume_build() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+
+    // Simulate a build that was interrupted right after it started, but left a stale node behind.
+    let build_directory = start_staged_build(&output_directory).unwrap();
+    {
+        let checkpoint = Checkpoint::open(&build_directory, true).unwrap();
+        checkpoint
+            .record_parameters(1.0, &bounding_box, None, None, &["color"], None, NUM_POINTS)
+            .unwrap();
+        std::fs::write(build_directory.join("r0.xyz"), b"garbage").unwrap();
+    }
+    match Octree::from_data_provider(Box::new(OnDiskDataProvider {
+        directory: build_directory.clone(),
+    })) {
+        Err(Error(ErrorKind::BuildInProgress(_), _)) => (),
+        _ => panic!("Incomplete octree was not rejected."),
+    }
+
+    let options = BuildOptions {
+        resume: true,
+        ..Default::default()
+    };
+    build_octree(
+        &output_directory,
+        1.0,
+        bounding_box.clone(),
+        vec![batch.clone()].into_iter(),
+        &["color"],
+        &options,
+    )
+    .unwrap();
+    assert!(!build_directory.exists());
+    assert_eq!(count_points(&open_octree(output_directory)), NUM_POINTS);
+
+    // Simulate crashes while splitting and while subsampling. There are enough points that nodes
+    // below the root are split as well.
+    let batch = with_intensity(batch);
+    let input = || vec![batch.clone(), batch.clone(), batch.clone()].into_iter();
+    let reference_directory = tmp_dir.path().join("reference");
+    build_octree(
+        &reference_directory,
+        1.0,
+        bounding_box.clone(),
+        input(),
+        &["color", "intensity"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let reference = open_octree(reference_directory);
+    for record in &["split", "level 1 staged"] {
+        let output_directory = tmp_dir.path().join(record.replace(' ', "_"));
+        let build_directory = staging_directory(&output_directory).unwrap();
+        CRASH_AFTER
+            .lock()
+            .unwrap()
+            .push((Checkpoint::path(&build_directory), record.to_string()));
+        let crashed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            build_octree(
+                &output_directory,
+                1.0,
+                bounding_box.clone(),
+                input(),
+                &["color", "intensity"],
+                &options,
+            )
+            .unwrap()
+        }));
+        assert!(crashed.is_err());
+        assert!(build_directory.exists());
+
+        build_octree(
+            &output_directory,
+            1.0,
+            bounding_box.clone(),
+            input(),
+            &["color", "intensity"],
+            &options,
+        )
+        .unwrap();
+        assert!(!build_directory.exists());
+        let octree = open_octree(output_directory);
+        assert_eq!(node_sizes(&octree), node_sizes(&reference));
+        // The nodes written before the crash have no ranges in memory, so they are read again.
+        assert_attribute_ranges_match_points(&octree, &["intensity"]);
+    }
+
+    // Files that a crashed build left truncated fail the resumed build instead of panicking it.
+    let output_directory = tmp_dir.path().join("truncated");
+    let build_directory = staging_directory(&output_directory).unwrap();
+    CRASH_AFTER.lock().unwrap().push((
+        Checkpoint::path(&build_directory),
+        "level 1 staged".to_string(),
+    ));
+    let build = || {
+        build_octree(
+            &output_directory,
+            1.0,
+            bounding_box.clone(),
+            input(),
+            &["color", "intensity"],
+            &options,
+        )
+    };
+    assert!(std::panic::catch_unwind(AssertUnwindSafe(|| build().unwrap())).is_err());
+    for entry in std::fs::read_dir(&build_directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some("intensity".as_ref()) {
+            let intensities = std::fs::read(&path).unwrap();
+            std::fs::write(&path, &intensities[..intensities.len() / 2]).unwrap();
+        }
+    }
+    let resumed = std::panic::catch_unwind(AssertUnwindSafe(build));
+    assert!(resumed.expect("The resumed build panicked.").is_err());
+}
+
+#[test]
+fn test_resume_rejects_different_parameters() {
+    let (batch, bounding_box) = spread_out_points();
+    let batch = with_intensity(batch);
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    let build_directory = staging_directory(&output_directory).unwrap();
+    CRASH_AFTER
+        .lock()
+        .unwrap()
+        .push((Checkpoint::path(&build_directory), "split".to_string()));
+    let build = |resolution: f64,
+                 bounding_box: &Aabb<f64>,
+                 batches: Vec<PointsBatch>,
+                 attributes: &[&str],
+                 deduplication: Option<DeduplicationPolicy>| {
+        build_octree(
+            &output_directory,
+            resolution,
+            bounding_box.clone(),
+            batches.into_iter(),
+            attributes,
+            &BuildOptions {
+                resume: true,
+                deduplication,
+                ..Default::default()
+            },
+        )
+    };
+    let crashed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        build(1.0, &bounding_box, vec![batch.clone()], &["color"], None).unwrap()
+    }));
+    assert!(crashed.is_err());
+
+    let mut larger_bounding_box = bounding_box.clone();
+    larger_bounding_box.grow(Point3::new(-1., -1., -1.));
+    for (resolution, bounding_box, batches, attributes, deduplication) in vec![
+        (
+            0.5,
+            &bounding_box,
+            vec![batch.clone()],
+            &["color"][..],
+            None,
+        ),
+        (
+            1.0,
+            &larger_bounding_box,
+            vec![batch.clone()],
+            &["color"][..],
+            None,
+        ),
+        (
+            1.0,
+            &bounding_box,
+            vec![batch.clone(), batch.clone()],
+            &["color"][..],
+            None,
+        ),
+        (
+            1.0,
+            &bounding_box,
+            vec![batch.clone()],
+            &["color", "intensity"][..],
+            None,
+        ),
+        (
+            1.0,
+            &bounding_box,
+            vec![batch.clone()],
+            &["color"][..],
+            Some(DeduplicationPolicy::KeepFirst),
+        ),
+    ] {
+        match build(resolution, bounding_box, batches, attributes, deduplication) {
+            Err(Error(ErrorKind::InvalidInput(_), _)) => (),
+            _ => panic!("Resuming with different parameters was not rejected."),
+        }
+    }
+    build(1.0, &bounding_box, vec![batch], &["color"], None).unwrap();
+    assert!(!build_directory.exists());
+    assert_eq!(count_points(&open_octree(output_directory)), NUM_POINTS);
+}
+
+#[test]
+fn test_build_returns_publication_errors() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    std::fs::create_dir(&output_directory).unwrap();
+    std::fs::write(output_directory.join("notes.txt"), "mine").unwrap();
+    // The build is complete, but the output directory must not be replaced.
+    match build_octree(
+        &output_directory,
+        1.0,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    ) {
+        Err(Error(ErrorKind::InvalidInput(_), _)) => (),
+        _ => panic!("Replacing a directory without a point cloud was not rejected."),
+    }
+    assert!(output_directory.join("notes.txt").exists());
+}
+
+#[test]
+fn test_sharded_build() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let partition_directory = tmp_dir.path().join("partition");
+    // The partition records the frame and label dictionaries, which the subtrees and the merged
+    // octree inherit.
+    let crs = Crs::LocalEnu {
+        latitude: 37.4,
+        longitude: -122.1,
+    };
+    let mut dictionary = LabelDictionary::new();
+    dictionary.insert(1, "one", None);
+    let mut label_dictionaries = LabelDictionaries::new();
+    label_dictionaries.insert("intensity", dictionary.clone());
+    let subtrees = partition_octree(
+        &partition_directory,
+        1.0,
+        bounding_box.clone(),
+        vec![with_intensity(batch)].into_iter(),
+        &["color", "intensity"],
+        1,
+        &BuildOptions {
+            crs: Some(crs),
+            label_dictionaries,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(subtrees.len() > 1);
+
+    let subtree_directories: Vec<PathBuf> = subtrees
+        .iter()
+        .map(|id| {
+            let directory = tmp_dir.path().join(id.to_string());
+            build_subtree_from_partition(
+                &directory,
+                &partition_directory,
+                *id,
+                &["color", "intensity"],
+                &BuildOptions::default(),
+            )
+            .unwrap();
+            assert_attribute_ranges_match_points(&open_octree(directory.clone()), &["intensity"]);
+            directory
+        })
+        .collect();
+    let output_directory = tmp_dir.path().join("octree");
+    merge_subtrees(
+        &output_directory,
+        &subtree_directories,
+        &["color", "intensity"],
+    )
+    .unwrap();
+
+    let merged = open_octree(output_directory);
+    assert_eq!(merged.crs(), Some(crs));
+    assert_eq!(merged.label_dictionary("intensity"), Some(&dictionary));
+    assert_eq!(count_points(&merged), NUM_POINTS);
+    assert_attribute_ranges_match_points(&merged, &["intensity"]);
+    // The merged octree has the same structure as one built in one go.
+    let (batch, _) = spread_out_points();
+    let single_directory = tmp_dir.path().join("single");
+    build_octree(
+        &single_directory,
+        1.0,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let single = open_octree(single_directory);
+    let mut merged_nodes: Vec<_> = merged
+        .nodes
+        .iter()
+        .map(|(id, n)| (id.to_string(), n.num_points))
+        .collect();
+    let mut single_nodes: Vec<_> = single
+        .nodes
+        .iter()
+        .map(|(id, n)| (id.to_string(), n.num_points))
+        .collect();
+    merged_nodes.sort();
+    single_nodes.sort();
+    assert_eq!(merged_nodes, single_nodes);
+}
+
+#[test]
+fn test_deduplicated_build() {
+    let (batch, bounding_box) = coincident_points();
+    let (_tmp_dir, octree) = build_and_open_octree(
+        1.0,
+        bounding_box,
+        vec![batch],
+        &["color"],
+        &BuildOptions {
+            deduplication: Some(DeduplicationPolicy::KeepFirst),
+            ..Default::default()
+        },
+    );
+    assert_eq!(count_points(&octree), 2);
+}
+
+#[test]
+fn test_check_and_repair() {
+    let (tmp_dir, octree) = build_test_octree();
+    let directory = &tmp_dir.path().join("octree");
+    assert!(check_octree(directory).unwrap().is_ok());
+
+    // Truncate the colors of the largest node and add a stray file.
+    let (leaf, num_points) = octree
+        .nodes
+        .iter()
+        .max_by_key(|(_, node_meta)| node_meta.num_points)
+        .map(|(id, node_meta)| (id.to_string(), node_meta.num_points as u64))
+        .unwrap();
+    std::fs::write(directory.join(format!("{}.rgb", leaf)), [0u8; 3]).unwrap();
+    std::fs::write(directory.join("notes.txt"), b"").unwrap();
+
+    let report = repair_octree(directory).unwrap();
+    assert_eq!(
+        report.problems,
+        vec![
+            Problem::FileSizeMismatch {
+                node: leaf.clone(),
+                attribute: "color".to_string(),
+                expected_bytes: 3 * num_points,
+                actual_bytes: 3,
+            },
+            Problem::OrphanFile {
+                file: "notes.txt".to_string()
+            },
+        ]
+    );
+
+    // The broken node is no longer in the meta, so its files are orphans now.
+    let report = check_octree(directory).unwrap();
+    assert_eq!(report.num_nodes, octree.nodes.len() - 1);
+    assert_eq!(
+        report.problems,
+        vec![
+            Problem::OrphanFile {
+                file: "notes.txt".to_string()
+            },
+            Problem::OrphanFile {
+                file: format!("{}.rgb", leaf)
+            },
+            Problem::OrphanFile {
+                file: format!("{}.xyz", leaf)
+            },
+        ]
+    );
+    assert_eq!(
+        count_points(&open_octree(directory.to_path_buf())),
+        NUM_POINTS - num_points as usize
+    );
+}
+
+#[test]
+fn test_repair_prunes_subtrees() {
+    let (batch, bounding_box) = spread_out_points();
+    // Enough points that some nodes below the root are split as well.
+    let (tmp_dir, octree) = build_and_open_octree(
+        1.0,
+        bounding_box,
+        vec![batch.clone(), batch.clone(), batch],
+        &["color"],
+        &BuildOptions::default(),
+    );
+    let directory = &tmp_dir.path().join("octree");
+    let interior = *octree
+        .nodes
+        .keys()
+        .find(|id| {
+            id.level() == 1
+                && octree
+                    .nodes
+                    .keys()
+                    .any(|child| child.parent_id() == Some(**id))
+        })
+        .unwrap();
+    let in_subtree = |id: &NodeId| {
+        let mut ancestor = Some(*id);
+        while let Some(id) = ancestor {
+            if id == interior {
+                return true;
+            }
+            ancestor = id.parent_id();
+        }
+        false
+    };
+    let subtree_size = octree.nodes.keys().filter(|id| in_subtree(id)).count();
+    assert!(subtree_size > 1);
+    std::fs::remove_file(directory.join(format!("{}.xyz", interior))).unwrap();
+
+    repair_octree(directory).unwrap();
+    // Only the files of the pruned subtree are left over.
+    let report = check_octree(directory).unwrap();
+    assert_eq!(report.num_nodes, octree.nodes.len() - subtree_size);
+    assert!(report.problems.iter().all(|problem| match problem {
+        Problem::OrphanFile { .. } => true,
+        _ => false,
+    }));
+    let repaired = open_octree(directory.to_path_buf());
+    assert!(repaired.nodes.keys().all(|id| !in_subtree(id)));
+}
+
+#[test]
+fn test_attribute_ranges_prune_nodes() {
+    let (batch, bounding_box) = spread_out_points();
+    // The intensity grows along x, so most nodes cannot contain the filtered points.
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        1.0,
+        bounding_box,
+        vec![with_intensity(batch)].into_iter(),
+        &["color", "intensity"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    assert_attribute_ranges_match_points(&octree, &["intensity"]);
+
+    let mut query = PointQuery {
+        attributes: vec!["intensity"],
+        ..Default::default()
+    };
+    query
+        .filter_intervals
+        .insert("intensity", ClosedInterval::new(0., 10.));
+    assert!(octree.nodes_for_query(&query).len() < octree.nodes_in_location(&query.location).len());
+
+    let mut intensities: Vec<f32> = Vec::new();
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            match &points_batch.attributes["intensity"] {
+                AttributeData::F32(values) => intensities.extend(values),
+                _ => panic!("Unexpected intensity type."),
+            }
+            Ok(())
+        })
+        .unwrap();
+    intensities.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(intensities, (0..=10).map(|i| i as f32).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_nodes_in_location_relation() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        1.0,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+
+    // The root cube spans [0, 1000] in all dimensions.
+    let half = PointLocation::Aabb(Aabb::new(
+        Point3::new(-1., -1., -1.),
+        Point3::new(501., 1001., 1001.),
+    ));
+    let nodes = octree.nodes_in_location(&half);
+    assert!(nodes.iter().any(|(_, relation)| *relation == Relation::In));
+    assert!(nodes
+        .iter()
+        .any(|(_, relation)| *relation == Relation::Cross));
+
+    let everything = PointLocation::Aabb(Aabb::new(
+        Point3::new(-1., -1., -1.),
+        Point3::new(1001., 1001., 1001.),
+    ));
+    let nodes = octree.nodes_in_location(&everything);
+    assert_eq!(nodes.len(), octree.nodes.len());
+    assert!(nodes.iter().all(|(_, relation)| *relation == Relation::In));
+
+    let query = PointQuery {
+        attributes: vec!["color"],
+        location: everything,
+        ..Default::default()
+    };
+    let mut num_points = 0;
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            num_points += points_batch.position.len();
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(num_points, NUM_POINTS);
+}
+
+#[test]
+fn test_nearest_neighbors() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+
+    // Compare against the distances to all points, as they were decoded from the octree.
+    let query_point = Point3::new(300., 50.5, 3.2);
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+    let mut all_distances = Vec::new();
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            all_distances.extend(
+                points_batch
+                    .position
+                    .iter()
+                    .map(|p| nalgebra::distance(p, &query_point)),
+            );
+            Ok(())
+        })
+        .unwrap();
+    all_distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let neighbors = octree
+        .nearest_neighbors(&query_point, 20, std::f64::INFINITY, &["color"])
+        .unwrap();
+    assert_eq!(neighbors.distances, all_distances[..20].to_vec());
+    assert_eq!(neighbors.points.position.len(), 20);
+    assert_eq!(neighbors.points.attributes["color"].len(), 20);
+
+    let within_radius = octree
+        .nearest_neighbors_batch(&[query_point], std::usize::MAX, 1.5, &["color"])
+        .unwrap();
+    let expected: Vec<f64> = all_distances.into_iter().filter(|d| *d <= 1.5).collect();
+    assert!(!expected.is_empty());
+    assert_eq!(within_radius[0].distances, expected);
+}
+
+#[test]
+fn test_pick_point() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+
+    // Compare against all points, as they were decoded from the octree.
+    let ray = Ray::new(Point3::new(300.2, 50.1, -10.), Vector3::new(0.1, 0., 1.));
+    let radius = 0.3;
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+    let mut closest_along_ray = std::f64::INFINITY;
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            for p in &points_batch.position {
+                let along = ray.distance_along(p);
+                if along >= 0. && ray.distance_to_line(p) <= radius && along < closest_along_ray {
+                    closest_along_ray = along;
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert!(closest_along_ray.is_finite());
+
+    let picked = octree
+        .pick_point(&ray, radius, &["color"])
+        .unwrap()
+        .expect("The ray should hit a point.");
+    assert_eq!(picked.distance_along_ray, closest_along_ray);
+    assert!(picked.distance_to_ray <= radius);
+    assert_eq!(picked.point.position.len(), 1);
+    assert_eq!(picked.point.attributes["color"].len(), 1);
+
+    // Pointing away from all points.
+    let away = Ray::new(Point3::new(300.2, 50.1, -10.), Vector3::new(0., 0., -1.));
+    assert!(octree
+        .pick_point(&away, radius, &["color"])
+        .unwrap()
+        .is_none());
+
+    // Restricted to the root node, the hit can only be farther along the ray.
+    if let Some(picked) = octree
+        .pick_point_up_to_level(&ray, radius, 0, &["color"])
+        .unwrap()
+    {
+        assert!(picked.distance_along_ray >= closest_along_ray);
+    }
+}
+
+#[test]
+fn test_query_limits() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    let all_nodes = octree.nodes_in_location(&PointLocation::AllPoints);
+    assert!(all_nodes.len() > 1);
+
+    let mut query = PointQuery {
+        attributes: vec!["color"],
+        max_level: Some(0),
+        ..Default::default()
+    };
+    let root_nodes = octree.nodes_for_query(&query);
+    assert_eq!(root_nodes.len(), 1);
+    assert_eq!(root_nodes[0].0.level(), 0);
+    let num_root_points = octree.nodes[&root_nodes[0].0].num_points as usize;
+    assert_eq!(count_points_for_query(&octree, &query), num_root_points);
+
+    // The budget allows the root, but not the complete next level.
+    query.max_level = None;
+    query.point_budget = Some(num_root_points);
+    assert_eq!(octree.nodes_for_query(&query).len(), 1);
+    assert_eq!(count_points_for_query(&octree, &query), num_root_points);
+
+    // The root is returned even if it alone exceeds the budget.
+    query.point_budget = Some(num_root_points - 1);
+    assert_eq!(octree.nodes_for_query(&query).len(), 1);
+    query.point_budget = Some(0);
+    assert_eq!(count_points_for_query(&octree, &query), num_root_points);
+
+    query.point_budget = Some(NUM_POINTS);
+    let nodes = octree.nodes_for_query(&query);
+    assert_eq!(nodes.len(), all_nodes.len());
+    assert_eq!(count_points_for_query(&octree, &query), NUM_POINTS);
+}
+
+#[test]
+fn test_failing_nodes() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+
+    let octree = open_octree(output_directory.clone());
+    let (node_id, num_points) = octree
+        .nodes
+        .iter()
+        .max_by_key(|(_, node_meta)| node_meta.num_points)
+        .map(|(id, node_meta)| (*id, node_meta.num_points as usize))
+        .unwrap();
+    let node = node_id.to_string();
+    assert!(num_points > 2 * 4096);
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+
+    // The largest node fails when it is opened if its colors are missing, and halfway through
+    // reading if they are truncated.
+    let colors_path = output_directory.join(format!("{}.rgb", node));
+    let colors = std::fs::read(&colors_path).unwrap();
+    for truncated_length in &[None, Some(colors.len() / 2)] {
+        match truncated_length {
+            None => std::fs::remove_file(&colors_path).unwrap(),
+            Some(length) => std::fs::write(&colors_path, &colors[..*length]).unwrap(),
+        }
+
+        let result = ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+            .try_for_each_batch(|_| Ok(()));
+        assert!(result.is_err());
+        // Threads that claim a node after the failure must not leave the ordered reader waiting,
+        // and the nodes before the failing one are passed on completely.
+        let num_points_before: usize = octree
+            .nodes
+            .iter()
+            .filter(|(id, _)| **id < node_id)
+            .map(|(_, node_meta)| node_meta.num_points as usize)
+            .sum();
+        for num_threads in &[1, 2, 8] {
+            let mut num_points_read = 0;
+            let result =
+                ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, *num_threads, 1)
+                    .ordered(true)
+                    .try_for_each_batch(|points_batch| {
+                        num_points_read += points_batch.position.len();
+                        Ok(())
+                    });
+            assert!(result.is_err());
+            assert!(num_points_read >= num_points_before);
+        }
+
+        // None of the points of the failing node are passed on.
+        for ordered in &[false, true] {
+            let mut parallel_iterator =
+                ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+                    .ordered(*ordered)
+                    .skip_failing_nodes(true);
+            let mut num_points_read = 0;
+            parallel_iterator
+                .try_for_each_batch(|points_batch| {
+                    num_points_read += points_batch.position.len();
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(num_points_read, NUM_POINTS - num_points);
+            let skipped_nodes = parallel_iterator.skipped_nodes();
+            assert_eq!(skipped_nodes.len(), 1);
+            assert_eq!(skipped_nodes[0].node_id, node);
+        }
+
+        // Aggregates fail or skip the node the same way, without counting any of its points.
+        let aggregations = Aggregations {
+            statistics: vec!["color"],
+            ..Default::default()
+        };
+        let result = ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+            .aggregate(&aggregations);
+        assert!(result.is_err());
+        let mut parallel_iterator =
+            ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+                .skip_failing_nodes(true);
+        let aggregate = parallel_iterator.aggregate(&aggregations).unwrap();
+        assert_eq!(aggregate.num_points, (NUM_POINTS - num_points) as u64);
+        assert_eq!(parallel_iterator.skipped_nodes().len(), 1);
+
+        // Picking and nearest neighbor searches return the error of the node instead of
+        // panicking.
+        let center = octree.nodes[&node_id].bounding_cube.to_aabb().center();
+        let ray = Ray::new(center, Vector3::new(0., 0., 1.));
+        assert!(octree.pick_point(&ray, 1., &["color"]).is_err());
+        let result = octree.nearest_neighbors_batch(&[center], 10, 1., &["color"]);
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn test_points_iterator() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octrees: Arc<[Octree]> = vec![open_octree(output_directory)].into();
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+
+    let num_points: usize = PointsIterator::new(
+        Arc::clone(&octrees),
+        &query,
+        4096,
+        2,
+        2,
+        false,
+        Arc::new(QueryHandle::new()),
+    )
+    .unwrap()
+    .map(|points_batch| points_batch.unwrap().position.len())
+    .sum();
+    assert_eq!(num_points, NUM_POINTS);
+
+    // The query can be owned, and a buffer of one batch makes the stream wait for the workers.
+    let owned_query = OwnedPointQuery {
+        attributes: vec!["color".to_string()],
+        ..Default::default()
+    };
+    let num_points: usize = PointsIterator::new(
+        Arc::clone(&octrees),
+        owned_query,
+        4096,
+        2,
+        1,
+        false,
+        Arc::new(QueryHandle::new()),
+    )
+    .unwrap()
+    .into_stream()
+    .wait()
+    .map(|points_batch| points_batch.unwrap().position.len())
+    .sum();
+    assert_eq!(num_points, NUM_POINTS);
+
+    // Cancelling through the handle ends the iteration with an error.
+    let handle = Arc::new(QueryHandle::new());
+    let mut points = PointsIterator::new(
+        Arc::clone(&octrees),
+        &query,
+        4096,
+        1,
+        1,
+        false,
+        Arc::clone(&handle),
+    )
+    .unwrap();
+    let first_batch = points.next().unwrap().unwrap();
+    assert_eq!(handle.num_points(), first_batch.position.len());
+    handle.cancel();
+    match points.find_map(|batch| batch.err()) {
+        Some(Error(ErrorKind::Cancelled, _)) => (),
+        _ => panic!("Cancelled iteration did not fail."),
+    }
+
+    // Dropping the iterator early cancels the remaining jobs instead of blocking.
+    let mut points = PointsIterator::new(
+        octrees,
+        &query,
+        4096,
+        2,
+        2,
+        false,
+        Arc::new(QueryHandle::new()),
+    )
+    .unwrap();
+    assert!(points.next().unwrap().is_ok());
+}
+
+#[test]
+fn test_ordered_iteration() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    let query = PointQuery {
+        attributes: vec!["color"],
+        location: PointLocation::Aabb(Aabb::new(
+            Point3::new(100., 0., 0.),
+            Point3::new(700., 60., 6.),
+        )),
+        ..Default::default()
+    };
+    let positions = |num_threads, buffer_size| {
+        let mut positions = Vec::new();
+        ParallelIterator::new(
+            std::slice::from_ref(&octree),
+            &query,
+            1000,
+            num_threads,
+            buffer_size,
+        )
+        .ordered(true)
+        .try_for_each_batch(|points_batch| {
+            positions.extend(points_batch.position);
+            Ok(())
+        })
+        .unwrap();
+        positions
+    };
+
+    let sequential = positions(1, 1);
+    assert!(!sequential.is_empty());
+    assert_eq!(positions(4, 1), sequential);
+    assert_eq!(positions(3, 8), sequential);
+}
+
+#[test]
+fn test_query_handle() {
+    let (batch, bounding_box) = spread_out_points();
+    let num_points = batch.position.len();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    let query = PointQuery {
+        attributes: vec!["color"],
+        ..Default::default()
+    };
+
+    let handle = Arc::new(QueryHandle::new());
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 1000, 2, 2)
+        .query_handle(Arc::clone(&handle))
+        .try_for_each_batch(|_| Ok(()))
+        .unwrap();
+    assert!(handle.num_nodes() > 1);
+    assert_eq!(handle.num_nodes_completed(), handle.num_nodes());
+    assert_eq!(handle.num_points(), num_points);
+    assert!(handle.num_bytes_read() > 0);
+
+    for ordered in &[false, true] {
+        let handle = Arc::new(QueryHandle::new());
+        let result = ParallelIterator::new(std::slice::from_ref(&octree), &query, 1000, 2, 1)
+            .ordered(*ordered)
+            .query_handle(Arc::clone(&handle))
+            .try_for_each_batch(|_| {
+                handle.cancel();
+                Ok(())
+            });
+        // The workers may have finished all nodes before noticing the cancellation.
+        if handle.num_nodes_completed() < handle.num_nodes() {
+            match result {
+                Err(Error(ErrorKind::Cancelled, _)) => (),
+                _ => panic!("The query should have been cancelled."),
+            }
+            assert!(handle.num_points() < num_points);
+        } else {
+            assert!(result.is_ok());
+        }
+    }
+}
+
+#[test]
+fn test_output_transform() {
+    let (batch, bounding_box) = spread_out_points();
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    let positions = |query: &PointQuery| {
+        let mut positions = Vec::new();
+        ParallelIterator::new(std::slice::from_ref(&octree), query, 1000, 2, 2)
+            .ordered(true)
+            .try_for_each_batch(|points_batch| {
+                positions.extend(points_batch.position);
+                Ok(())
+            })
+            .unwrap();
+        positions
+    };
+
+    let query = PointQuery {
+        location: PointLocation::Aabb(Aabb::new(
+            Point3::new(100., 0., 0.),
+            Point3::new(700., 60., 6.),
+        )),
+        ..Default::default()
+    };
+    let local_from_global = Isometry3::new(Vector3::new(1., 2., 3.), Vector3::z() * 0.5);
+    let transformed_query = PointQuery {
+        output_transform: Some(local_from_global),
+        ..query.clone()
+    };
+    let expected: Vec<Point3<f64>> = positions(&query)
+        .iter()
+        .map(|p| local_from_global.transform_point(p))
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(positions(&transformed_query), expected);
+}
+
+#[test]
+fn test_crs() {
+    let (batch, _) = spread_out_points();
+    let input_crs = Crs::LocalEnu {
+        latitude: 37.4,
+        longitude: -122.1,
+    };
+    // About 100 m further north.
+    let crs = Crs::LocalEnu {
+        latitude: 37.401,
+        longitude: -122.1,
+    };
+    let expected: Vec<Point3<f64>> = batch
+        .position
+        .iter()
+        .map(|p| input_crs.transform_to(&crs, p))
+        .collect();
+    let mut bounding_box = Aabb::new(expected[0], expected[0]);
+    for p in &expected {
+        bounding_box.grow(*p);
+    }
+
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        0.001,
+        bounding_box.clone(),
+        vec![batch].into_iter(),
+        &["color"],
+        &BuildOptions {
+            crs: Some(crs),
+            input_crs: Some(input_crs),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    assert_eq!(octree.crs(), Some(crs));
+
+    let mut positions = Vec::new();
+    ParallelIterator::new(
+        std::slice::from_ref(&octree),
+        &PointQuery::default(),
+        4096,
+        2,
+        2,
+    )
+    .try_for_each_batch(|points_batch| {
+        positions.extend(points_batch.position);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(positions.len(), expected.len());
+    let mean = |points: &[Point3<f64>]| {
+        points
+            .iter()
+            .fold(Vector3::zeros(), |sum, p| sum + p.coords)
+            / points.len() as f64
+    };
+    assert!((mean(&positions) - mean(&expected)).norm() < 1e-3);
+}
+
+#[test]
+fn test_label_dictionaries() {
+    let (mut batch, bounding_box) = spread_out_points();
+    // The intensities are used as categorical values here.
+    batch.attributes.insert(
+        "intensity".to_string(),
+        AttributeData::F32((0..NUM_POINTS).map(|i| (i % 1000) as f32).collect()),
+    );
+    let mut dictionary = LabelDictionary::new();
+    dictionary.insert(3, "three", None);
+    dictionary.insert(
+        7,
+        "seven",
+        Some(Color {
+            red: 1.,
+            green: 0.5,
+            blue: 0.,
+            alpha: 1.,
+        }),
+    );
+    let mut label_dictionaries = LabelDictionaries::new();
+    label_dictionaries.insert("intensity", dictionary.clone());
+    let json_dir = TempDir::new("labels").unwrap();
+    let json_path = json_dir.path().join("labels.json");
+    std::fs::write(
+        &json_path,
+        r#"{"intensity": {
+            "3": {"name": "three"},
+            "7": {"name": "seven", "color": {"red": 1.0, "green": 0.5, "blue": 0.0, "alpha": 1.0}}
+        }}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        LabelDictionaries::from_json_file(&json_path).unwrap(),
+        label_dictionaries
+    );
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        1.0,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color", "intensity"],
+        &BuildOptions {
+            label_dictionaries,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+    assert_eq!(octree.label_dictionary("intensity"), Some(&dictionary));
+    assert_eq!(octree.label_dictionary("color"), None);
+
+    let mut query = PointQuery {
+        attributes: vec!["intensity"],
+        ..Default::default()
+    };
+    query
+        .filter_labels
+        .insert("intensity", vec!["three", "seven"]);
+    let mut intensities: Vec<f32> = Vec::new();
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            match &points_batch.attributes["intensity"] {
+                AttributeData::F32(values) => intensities.extend(values),
+                _ => panic!("Unexpected intensity type."),
+            }
+            Ok(())
+        })
+        .unwrap();
+    let num_expected = (0..NUM_POINTS)
+        .filter(|i| i % 1000 == 3 || i % 1000 == 7)
+        .count();
+    assert_eq!(intensities.len(), num_expected);
+    assert!(intensities.iter().all(|i| *i == 3. || *i == 7.));
+
+    query.filter_labels.insert("intensity", vec!["eight"]);
+    let result = ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|_| Ok(()));
+    match result {
+        Err(Error(ErrorKind::InvalidInput(_), _)) => (),
+        _ => panic!("Expected an error for an unknown label."),
+    }
+}
+
+#[test]
+fn test_filter_expression() {
+    let (mut batch, bounding_box) = spread_out_points();
+    batch.attributes.insert(
+        "intensity".to_string(),
+        AttributeData::F32((0..NUM_POINTS).map(|i| i as f32).collect()),
+    );
+    let tmp_dir = TempDir::new("octree").unwrap();
+    let output_directory = tmp_dir.path().join("octree");
+    build_octree(
+        &output_directory,
+        1.0,
+        bounding_box,
+        vec![batch].into_iter(),
+        &["color", "intensity"],
+        &BuildOptions::default(),
+    )
+    .unwrap();
+    let octree = open_octree(output_directory);
+
+    let mut query = PointQuery {
+        attributes: vec!["color", "intensity"],
+        filter: Some(
+            "(intensity < 5 || intensity in {100, 200}) && color[1] == 255"
+                .parse()
+                .unwrap(),
+        ),
+        ..Default::default()
+    };
+    // The filter intervals and the filter expression need to match both.
+    query
+        .filter_intervals
+        .insert("intensity", ClosedInterval::new(0., 150.));
+    assert!(octree.nodes_for_query(&query).len() < octree.nodes_in_location(&query.location).len());
+
+    let mut intensities: Vec<f32> = Vec::new();
+    ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            match &points_batch.attributes["intensity"] {
+                AttributeData::F32(values) => intensities.extend(values),
+                _ => panic!("Unexpected intensity type."),
+            }
+            Ok(())
+        })
+        .unwrap();
+    intensities.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(intensities, vec![0., 1., 2., 3., 4., 100.]);
+
+    // The filter attributes are read, but only the query attributes are returned.
+    let color_query = PointQuery {
+        attributes: vec!["color"],
+        ..query.clone()
+    };
+    let mut num_points = 0;
+    ParallelIterator::new(std::slice::from_ref(&octree), &color_query, 4096, 2, 2)
+        .try_for_each_batch(|points_batch| {
+            assert_eq!(
+                points_batch.attributes.keys().collect::<Vec<_>>(),
+                vec!["color"]
+            );
+            num_points += points_batch.position.len();
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(num_points, intensities.len());
+
+    query.filter = Some("color > 0".parse().unwrap());
+    let result = ParallelIterator::new(std::slice::from_ref(&octree), &query, 4096, 2, 2)
+        .try_for_each_batch(|_| Ok(()));
+    match result {
+        Err(Error(ErrorKind::InvalidInput(_), _)) => (),
+        _ => panic!("Expected an error for a vector attribute without component."),
+    }
+}
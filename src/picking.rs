@@ -0,0 +1,100 @@
+//! Picking the first point along a ray, e.g. for click-to-measure.
+
+use crate::errors::*;
+use crate::geometry::{Corridor, Ray};
+use crate::iterator::{PointCloud, PointLocation};
+use crate::math::ConvexPolyhedron;
+use crate::PointsBatch;
+use std::cmp::Ordering;
+
+/// A point hit by a ray.
+#[derive(Debug, Clone)]
+pub struct PickedPoint {
+    /// The point with the requested attributes, as a batch of one.
+    pub point: PointsBatch,
+    /// The distance from the ray origin to the projection of the point onto the ray.
+    pub distance_along_ray: f64,
+    /// The distance of the point to the ray.
+    pub distance_to_ray: f64,
+}
+
+/// Returns the point within 'radius' of 'ray' that comes first along the ray, considering only
+/// nodes accepted by 'node_filter'. Nodes are read front to back, until no node can contain a
+/// point before the current one.
+pub fn pick_point<C: PointCloud + ?Sized>(
+    point_cloud: &C,
+    ray: &Ray<f64>,
+    radius: f64,
+    attributes: &[&str],
+    node_filter: impl Fn(C::Id) -> bool,
+) -> Result<Option<PickedPoint>> {
+    if radius.is_nan() || radius < 0. {
+        return Err(ErrorKind::InvalidInput(format!(
+            "The radius must not be negative, got {}.",
+            radius
+        ))
+        .into());
+    }
+    // No point can be hit farther away than the farthest corner of the bounding box.
+    let length = point_cloud
+        .bounding_box()
+        .compute_corners()
+        .iter()
+        .map(|corner| ray.distance_along(corner))
+        .fold(0.0, f64::max)
+        + radius;
+    let cylinder = PointLocation::Corridor(Corridor::new(
+        vec![*ray.origin(), ray.point_at(length)],
+        radius,
+    ));
+    // A hit point at distance 'd' from the origin is at least 'sqrt(d² - radius²)' along the ray.
+    let mut nodes: Vec<(f64, C::Id)> = point_cloud
+        .nodes_in_location(&cylinder)
+        .into_iter()
+        .filter(|(node_id, _)| node_filter(*node_id))
+        .map(|(node_id, _)| {
+            let distance = point_cloud.distance_to_node(node_id, ray.origin());
+            let distance_along = (distance * distance - radius * radius).max(0.0).sqrt();
+            (distance_along, node_id)
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    let mut picked: Option<PickedPoint> = None;
+    for (node_distance_along, node_id) in nodes {
+        if let Some(picked) = &picked {
+            if node_distance_along > picked.distance_along_ray {
+                break;
+            }
+        }
+        let mut node_iterator =
+            point_cloud.points_in_node(attributes, node_id, crate::NUM_POINTS_PER_BATCH)?;
+        while let Some(batch) = node_iterator.try_next()? {
+            let mut closest_in_batch: Option<(usize, f64, f64)> = None;
+            for (i, p) in batch.position.iter().enumerate() {
+                let distance_along = ray.distance_along(p);
+                if distance_along < 0.0 {
+                    continue;
+                }
+                let distance_to_ray = ray.distance_to_line(p);
+                if distance_to_ray > radius {
+                    continue;
+                }
+                let best_so_far = closest_in_batch
+                    .map(|(_, along, _)| along)
+                    .or_else(|| picked.as_ref().map(|picked| picked.distance_along_ray));
+                if best_so_far.map_or(true, |best| distance_along < best) {
+                    closest_in_batch = Some((i, distance_along, distance_to_ray));
+                }
+            }
+            if let Some((i, distance_along_ray, distance_to_ray)) = closest_in_batch {
+                picked = Some(PickedPoint {
+                    point: batch.select(&[i]),
+                    distance_along_ray,
+                    distance_to_ray,
+                });
+            }
+        }
+    }
+    Ok(picked)
+}
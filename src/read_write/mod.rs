@@ -30,9 +30,18 @@ pub use self::ply::{PlyIterator, PlyNodeWriter};
 mod raw;
 pub use self::raw::{RawNodeReader, RawNodeWriter};
 
+mod reproject;
+pub use self::reproject::Reproject;
+
 mod s2;
 pub use self::s2::S2Splitter;
 
+mod staging;
+pub use self::staging::{
+    is_build_in_progress, is_publication_interrupted, publish_staged_build, staging_directory,
+    start_staged_build, write_meta,
+};
+
 use std::io::{BufReader, Read};
 
 pub struct AttributeReader {
@@ -26,6 +26,7 @@ pub struct NodeIterator {
     num_points: usize,
     point_count: usize,
     batch_size: usize,
+    num_bytes: usize,
 }
 
 impl Default for NodeIterator {
@@ -35,6 +36,7 @@ impl Default for NodeIterator {
             num_points: 0,
             point_count: 0,
             batch_size: 0,
+            num_bytes: 0,
         }
     }
 }
@@ -50,9 +52,30 @@ impl NodeIterator {
             num_points,
             point_count: 0,
             batch_size,
+            num_bytes: 0,
         }
     }
 
+    /// The number of bytes read from the data provider when iterating over all points, if known.
+    pub fn num_bytes(&self) -> usize {
+        self.num_bytes
+    }
+
+    /// Like `next`, but returns an error instead of panicking if the node cannot be read, e.g.
+    /// because one of its files is truncated.
+    pub fn try_next(&mut self) -> Result<Option<PointsBatch>> {
+        if let Some(reader) = &mut self.reader {
+            if self.point_count < self.num_points {
+                let num_points_to_read =
+                    std::cmp::min(self.batch_size, self.num_points - self.point_count);
+                let batch = reader.read_batch(num_points_to_read)?;
+                self.point_count += num_points_to_read;
+                return Ok(Some(batch));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn from_data_provider<Id: ToString>(
         data_provider: &dyn DataProvider,
         attribute_data_types: &HashMap<String, AttributeDataType>,
@@ -81,11 +104,24 @@ impl NodeIterator {
             })
             .collect();
 
-        Ok(Self::new(
+        let bytes_per_position = match &encoding {
+            Encoding::Plain => 3 * std::mem::size_of::<f64>(),
+            Encoding::ScaledToCube(_, _, position_encoding) => {
+                3 * position_encoding.bytes_per_coordinate()
+            }
+        };
+        let bytes_per_point = bytes_per_position
+            + attribute_data_types
+                .values()
+                .map(|data_type| data_type.size_of())
+                .sum::<usize>();
+        let mut node_iterator = Self::new(
             RawNodeReader::new(position_reader, attribute_readers, encoding)?,
             num_points,
             batch_size,
-        ))
+        );
+        node_iterator.num_bytes = num_points * bytes_per_point;
+        Ok(node_iterator)
     }
 }
 
@@ -103,17 +139,6 @@ impl Iterator for NodeIterator {
         (num_batches, Some(num_batches))
     }
     fn next(&mut self) -> Option<PointsBatch> {
-        if let Some(reader) = &mut self.reader {
-            if self.point_count < self.num_points {
-                let num_points_to_read =
-                    std::cmp::min(self.batch_size, self.num_points - self.point_count);
-                let res = reader
-                    .read_batch(num_points_to_read)
-                    .expect("Couldn't read from node.");
-                self.point_count += num_points_to_read;
-                return Some(res);
-            }
-        }
-        None
+        self.try_next().expect("Couldn't read from node.")
     }
 }
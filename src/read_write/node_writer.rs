@@ -16,7 +16,7 @@ use crate::color::Color;
 use crate::read_write::{vec3_encode, vec3_fixpoint_encode, Encoding, PositionEncoding};
 use crate::AttributeData;
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Point3, Vector3, Vector4};
 use std::fs::{remove_file, File, OpenOptions};
 use std::io::{BufWriter, Result, Seek, SeekFrom, Write};
 use std::path::PathBuf;
@@ -170,6 +170,14 @@ impl WriteLE for Vector3<u16> {
     }
 }
 
+impl WriteLE for Vector3<i32> {
+    fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
+        let mut bytes = [0; 12];
+        LittleEndian::write_i32_into(self.as_slice(), &mut bytes);
+        writer.write_all(&bytes)
+    }
+}
+
 impl WriteLE for Vector3<f32> {
     fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
         let mut bytes = [0; 12];
@@ -186,6 +194,12 @@ impl WriteLE for Vector3<f64> {
     }
 }
 
+impl WriteLE for Vector4<u8> {
+    fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
+        writer.write_all(self.as_slice())
+    }
+}
+
 impl WriteLE for Color<u8> {
     fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
         writer.write_u8(self.red)?;
@@ -195,23 +209,29 @@ impl WriteLE for Color<u8> {
     }
 }
 
-impl WriteLE for Vec<Vector3<u8>> {
-    fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
-        for elem in self {
-            elem.write_le(writer)?;
-        }
-        Ok(())
-    }
+macro_rules! derive_write_le_vec_elementwise {
+    ($($vector:ty),*) => {
+        $(
+            impl WriteLE for Vec<$vector> {
+                fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
+                    for elem in self {
+                        elem.write_le(writer)?;
+                    }
+                    Ok(())
+                }
+            }
+        )*
+    };
 }
 
-impl WriteLE for Vec<Vector3<f64>> {
-    fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
-        for elem in self {
-            elem.write_le(writer)?;
-        }
-        Ok(())
-    }
-}
+derive_write_le_vec_elementwise!(
+    Vector3<u8>,
+    Vector3<u16>,
+    Vector3<i32>,
+    Vector3<f32>,
+    Vector3<f64>,
+    Vector4<u8>
+);
 
 impl WriteLE for Vec<Point3<f64>> {
     fn write_le(&self, writer: &mut DataWriter) -> Result<()> {
@@ -16,12 +16,12 @@ use crate::errors::*;
 use crate::read_write::{
     DataWriter, Encoding, NodeWriter, OpenMode, PositionEncoding, WriteEncoded, WriteLE, WriteLEPos,
 };
-use crate::{AttributeData, NumberOfPoints, Point, PointsBatch};
+use crate::{AttributeData, AttributeDataType, NumberOfPoints, Point, PointsBatch};
 use byteorder::{ByteOrder, LittleEndian};
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Point3, Vector3, Vector4};
 use num_integer::div_ceil;
 use num_traits::identities::Zero;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
@@ -308,6 +308,42 @@ macro_rules! push_skip_reader {
     }};
 }
 
+// Vector attributes are stored as one property per component, e.g. 'normal0', 'normal1' and
+// 'normal2'. Returns the attribute name and the component index.
+fn split_component_name(name: &str) -> Option<(&str, usize)> {
+    let attribute = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if attribute.is_empty() || attribute.len() == name.len() {
+        return None;
+    }
+    name[attribute.len()..]
+        .parse()
+        .ok()
+        .map(|index| (attribute, index))
+}
+
+// Only names with a trailing index that share their attribute name with two or three other
+// properties are components, so that e.g. a lone 'scalar_field2' stays a scalar attribute.
+fn vector_attribute_names<'a>(names: impl Iterator<Item = &'a str>) -> BTreeSet<String> {
+    let mut num_components: BTreeMap<&str, usize> = BTreeMap::new();
+    for (attribute, _) in names.filter_map(split_component_name) {
+        *num_components.entry(attribute).or_default() += 1;
+    }
+    num_components
+        .into_iter()
+        .filter(|(_, count)| *count == 3 || *count == 4)
+        .map(|(attribute, _)| attribute.to_string())
+        .collect()
+}
+
+// Returns the attribute name and the component index if 'name' is a component of one of
+// 'vector_attributes'.
+fn vector_component<'a>(
+    name: &'a str,
+    vector_attributes: &BTreeSet<String>,
+) -> Option<(&'a str, usize)> {
+    split_component_name(name).filter(|(attribute, _)| vector_attributes.contains(*attribute))
+}
+
 struct PropertyReader {
     prop: ScalarProperty,
     data: AttributeData,
@@ -318,6 +354,7 @@ struct PropertyReader {
 pub struct PlyIterator {
     reader: BufReader<File>,
     readers: Vec<PropertyReader>,
+    vector_attributes: BTreeSet<String>,
     pub num_total_points: i64,
     batch_size: usize,
     offset: Vector3<f64>,
@@ -347,6 +384,9 @@ impl PlyIterator {
 
         let mut readers: Vec<PropertyReader> = Vec::new();
         let mut num_bytes_per_point = 0;
+        let mut vector_components: BTreeMap<&str, Vec<DataType>> = BTreeMap::new();
+        let vector_attributes =
+            vector_attribute_names(vertex.properties.iter().map(|prop| &prop.name as &str));
 
         for prop in &vertex.properties {
             match &prop.name as &str {
@@ -383,10 +423,64 @@ impl PlyIterator {
                 "a" | "alpha" => {
                     readers.push(push_skip_reader!(prop, &mut num_bytes_per_point, 1));
                 }
-                other => {
-                    // TODO(feuerste): We may need to support multidimensional attributes.
-                    assert!(!other.chars().last().unwrap().is_ascii_digit(),
-                    "Multidimensional attributes other than position and color are currently unsupported.");
+                other if vector_component(other, &vector_attributes).is_some() => {
+                    let (attribute, index) = vector_component(other, &vector_attributes).unwrap();
+                    let components = vector_components.entry(attribute).or_default();
+                    if index != components.len() {
+                        return Err(ErrorKind::InvalidInput(format!(
+                            "Property '{}' is out of order.",
+                            other
+                        ))
+                        .into());
+                    }
+                    components.push(prop.data_type);
+                    use self::DataType::*;
+                    match prop.data_type {
+                        Uint8 => push_reader!(
+                            readers,
+                            prop,
+                            AttributeData::U8(Vec::with_capacity(batch_size)),
+                            &mut num_bytes_per_point,
+                            u8
+                        ),
+                        Uint16 => push_reader!(
+                            readers,
+                            prop,
+                            AttributeData::U16(Vec::with_capacity(batch_size)),
+                            &mut num_bytes_per_point,
+                            u16
+                        ),
+                        Int32 => push_reader!(
+                            readers,
+                            prop,
+                            AttributeData::I32(Vec::with_capacity(batch_size)),
+                            &mut num_bytes_per_point,
+                            i32
+                        ),
+                        Float32 => push_reader!(
+                            readers,
+                            prop,
+                            AttributeData::F32(Vec::with_capacity(batch_size)),
+                            &mut num_bytes_per_point,
+                            f32
+                        ),
+                        Float64 => push_reader!(
+                            readers,
+                            prop,
+                            AttributeData::F64(Vec::with_capacity(batch_size)),
+                            &mut num_bytes_per_point,
+                            f64
+                        ),
+                        _ => {
+                            return Err(ErrorKind::InvalidInput(format!(
+                                "Unsupported data type {:?} of property '{}'.",
+                                prop.data_type, other
+                            ))
+                            .into())
+                        }
+                    }
+                }
+                _ => {
                     use self::DataType::*;
                     match prop.data_type {
                         Uint8 => push_reader!(
@@ -441,11 +535,31 @@ impl PlyIterator {
             panic!("PLY must contain properties 'x', 'y', 'z' for 'vertex'.");
         }
 
+        for (attribute, components) in &vector_components {
+            let supported = match (components.len(), components[0]) {
+                (3, DataType::Uint8)
+                | (3, DataType::Uint16)
+                | (3, DataType::Int32)
+                | (3, DataType::Float32)
+                | (3, DataType::Float64)
+                | (4, DataType::Uint8) => components.iter().all(|c| *c == components[0]),
+                _ => false,
+            };
+            if !supported {
+                return Err(ErrorKind::InvalidInput(format!(
+                    "Unsupported vector attribute '{}' with components {:?}.",
+                    attribute, components
+                ))
+                .into());
+            }
+        }
+
         // We align the buffer of this 'BufReader' to points, so that we can index this buffer and know
         // that it will always contain full points to parse.
         Ok(PlyIterator {
             reader: BufReader::with_capacity(num_bytes_per_point * 1024, file),
             readers,
+            vector_attributes,
             num_total_points: header["vertex"].count,
             batch_size,
             offset: header.offset,
@@ -454,10 +568,15 @@ impl PlyIterator {
     }
 }
 
-fn batch_from_readers(readers: &mut [PropertyReader], offset: &Vector3<f64>) -> PointsBatch {
+fn batch_from_readers(
+    readers: &mut [PropertyReader],
+    vector_attributes: &BTreeSet<String>,
+    offset: &Vector3<f64>,
+) -> PointsBatch {
     let (mut x_vec, mut y_vec, mut z_vec) = (Vec::new(), Vec::new(), Vec::new());
     let (mut r_vec, mut g_vec, mut b_vec) = (Vec::new(), Vec::new(), Vec::new());
     let mut attributes = BTreeMap::new();
+    let mut vector_components: BTreeMap<String, Vec<AttributeData>> = BTreeMap::new();
     for reader in readers {
         let data = &mut reader.data;
         match &reader.prop.name as &str {
@@ -468,6 +587,13 @@ fn batch_from_readers(readers: &mut [PropertyReader], offset: &Vector3<f64>) ->
             "g" | "green" => g_vec = <&mut Vec<u8>>::try_from(data).unwrap().split_off(0),
             "b" | "blue" => b_vec = <&mut Vec<u8>>::try_from(data).unwrap().split_off(0),
             "a" | "alpha" => {}
+            other if vector_component(other, vector_attributes).is_some() => {
+                let (attribute, _) = vector_component(other, vector_attributes).unwrap();
+                vector_components
+                    .entry(attribute.to_string())
+                    .or_default()
+                    .push(data.split_off(0));
+            }
             other => {
                 let other_data = match reader.prop.data_type {
                     DataType::Uint8
@@ -485,6 +611,9 @@ fn batch_from_readers(readers: &mut [PropertyReader], offset: &Vector3<f64>) ->
             }
         }
     }
+    for (attribute, components) in vector_components {
+        attributes.insert(attribute, vector_from_components(components));
+    }
     let position: Vec<Point3<f64>> = x_vec
         .into_iter()
         .zip(y_vec.into_iter())
@@ -510,6 +639,56 @@ fn batch_from_readers(readers: &mut [PropertyReader], offset: &Vector3<f64>) ->
     }
 }
 
+// Combines the components of a vector attribute, which have been checked to be supported when
+// parsing the header.
+fn vector_from_components(components: Vec<AttributeData>) -> AttributeData {
+    macro_rules! vector3 {
+        ($dtype:ident, $scalar:ty) => {{
+            let mut components = components
+                .into_iter()
+                .map(|data| Vec::<$scalar>::try_from(data).unwrap());
+            let x = components.next().unwrap();
+            let y = components.next().unwrap();
+            let z = components.next().unwrap();
+            AttributeData::$dtype(
+                x.into_iter()
+                    .zip(y.into_iter())
+                    .zip(z.into_iter())
+                    .map(|((x, y), z)| Vector3::new(x, y, z))
+                    .collect(),
+            )
+        }};
+    }
+    match (components.len(), components[0].data_type()) {
+        (3, AttributeDataType::U8) => vector3!(U8Vec3, u8),
+        (3, AttributeDataType::U16) => vector3!(U16Vec3, u16),
+        (3, AttributeDataType::I32) => vector3!(I32Vec3, i32),
+        (3, AttributeDataType::F32) => vector3!(F32Vec3, f32),
+        (3, AttributeDataType::F64) => vector3!(F64Vec3, f64),
+        (4, AttributeDataType::U8) => {
+            let mut components = components
+                .into_iter()
+                .map(|data| Vec::<u8>::try_from(data).unwrap());
+            let x = components.next().unwrap();
+            let y = components.next().unwrap();
+            let z = components.next().unwrap();
+            let w = components.next().unwrap();
+            AttributeData::U8Vec4(
+                x.into_iter()
+                    .zip(y.into_iter())
+                    .zip(z.into_iter())
+                    .zip(w.into_iter())
+                    .map(|(((x, y), z), w)| Vector4::new(x, y, z, w))
+                    .collect(),
+            )
+        }
+        (len, data_type) => panic!(
+            "Unsupported vector attribute with {} components of type {:?}.",
+            len, data_type
+        ),
+    }
+}
+
 impl NumberOfPoints for PlyIterator {
     fn num_points(&self) -> usize {
         self.num_total_points as usize
@@ -551,7 +730,7 @@ impl Iterator for PlyIterator {
         }
         self.point_count += cur_batch_size;
 
-        let batch = batch_from_readers(&mut self.readers, &self.offset);
+        let batch = batch_from_readers(&mut self.readers, &self.vector_attributes, &self.offset);
         Some(batch)
     }
 }
@@ -590,7 +769,11 @@ impl NodeWriter<PointsBatch> for PlyNodeWriter {
                                 AttributeData::F32(_) => "float",
                                 AttributeData::F64(_) => "double",
                                 AttributeData::U8Vec3(_) => "uchar",
+                                AttributeData::U16Vec3(_) => "ushort",
+                                AttributeData::I32Vec3(_) => "int",
+                                AttributeData::F32Vec3(_) => "float",
                                 AttributeData::F64Vec3(_) => "double",
+                                AttributeData::U8Vec4(_) => "uchar",
                             },
                             data.dim(),
                         )
@@ -834,4 +1017,60 @@ mod tests {
                 assert!(test_intensity.iter().all(|i| i.is_nan()));
             });
     }
+
+    #[test]
+    fn test_ply_read_write_vector_attributes() {
+        let tmp_dir = TempDir::new("test_ply_read_write_vector_attributes").unwrap();
+        let file_path_test = tmp_dir.path().join("out.ply");
+        let mut attributes = BTreeMap::new();
+        attributes.insert(
+            "normal".to_string(),
+            AttributeData::F32Vec3(vec![Vector3::new(0., 0., 1.), Vector3::new(0.5, -0.5, 0.)]),
+        );
+        attributes.insert(
+            "counts".to_string(),
+            AttributeData::U16Vec3(vec![Vector3::new(1, 2, 3), Vector3::new(4, 5, 65535)]),
+        );
+        attributes.insert(
+            "cell".to_string(),
+            AttributeData::I32Vec3(vec![Vector3::new(-1, 0, 1), Vector3::new(7, -8, 9)]),
+        );
+        attributes.insert(
+            "label_color".to_string(),
+            AttributeData::U8Vec4(vec![Vector4::new(1, 2, 3, 4), Vector4::new(5, 6, 7, 8)]),
+        );
+        // Not a component, as there is no 'scalar_field0' or 'scalar_field1'.
+        attributes.insert(
+            "scalar_field2".to_string(),
+            AttributeData::F32(vec![0.25, -1.5]),
+        );
+        let batch = PointsBatch {
+            position: vec![Point3::new(1., 2., 3.), Point3::new(4., 5., 6.)],
+            attributes,
+        };
+        {
+            let mut ply_writer =
+                PlyNodeWriter::new(&file_path_test, Encoding::Plain, OpenMode::Truncate);
+            ply_writer.write(&batch).unwrap();
+        }
+        let batches = batches_from_file(&file_path_test);
+        assert_eq!(1, batches.len());
+        let test = &batches[0];
+        assert_eq!(batch.position, test.position);
+        let gt_normal: &Vec<Vector3<f32>> = batch.get_attribute_vec("normal").unwrap();
+        let test_normal: &Vec<Vector3<f32>> = test.get_attribute_vec("normal").unwrap();
+        assert_eq!(gt_normal, test_normal);
+        let gt_counts: &Vec<Vector3<u16>> = batch.get_attribute_vec("counts").unwrap();
+        let test_counts: &Vec<Vector3<u16>> = test.get_attribute_vec("counts").unwrap();
+        assert_eq!(gt_counts, test_counts);
+        let gt_cell: &Vec<Vector3<i32>> = batch.get_attribute_vec("cell").unwrap();
+        let test_cell: &Vec<Vector3<i32>> = test.get_attribute_vec("cell").unwrap();
+        assert_eq!(gt_cell, test_cell);
+        let gt_label_color: &Vec<Vector4<u8>> = batch.get_attribute_vec("label_color").unwrap();
+        let test_label_color: &Vec<Vector4<u8>> = test.get_attribute_vec("label_color").unwrap();
+        assert_eq!(gt_label_color, test_label_color);
+        let gt_scalar_field: &Vec<f32> = batch.get_attribute_vec("scalar_field2").unwrap();
+        let test_scalar_field: &Vec<f32> = test.get_attribute_vec("scalar_field2").unwrap();
+        assert_eq!(gt_scalar_field, test_scalar_field);
+    }
 }
@@ -20,7 +20,7 @@ use crate::read_write::{
 };
 use crate::{attribute_extension, AttributeData, AttributeDataType, Point, PointsBatch};
 use byteorder::{LittleEndian, ReadBytesExt};
-use nalgebra::{Point3, Vector3};
+use nalgebra::{Point3, Vector3, Vector4};
 use std::collections::{BTreeMap, HashMap};
 use std::io::{self, BufReader, ErrorKind, Read};
 use std::path::PathBuf;
@@ -306,6 +306,51 @@ impl RawNodeReader {
                             .attributes
                             .insert(key.to_owned(), AttributeData::U8Vec3(attr));
                     }
+                    AttributeDataType::U16Vec3 => {
+                        let mut attr = Vec::with_capacity(num_points);
+                        let mut buffer = vec![0; 3 * num_points];
+                        reader.read_u16_into::<LittleEndian>(&mut buffer)?;
+                        for i in 0..num_points {
+                            attr.push(Vector3::new(
+                                buffer[3 * i],
+                                buffer[3 * i + 1],
+                                buffer[3 * i + 2],
+                            ));
+                        }
+                        batch
+                            .attributes
+                            .insert(key.to_owned(), AttributeData::U16Vec3(attr));
+                    }
+                    AttributeDataType::I32Vec3 => {
+                        let mut attr = Vec::with_capacity(num_points);
+                        let mut buffer = vec![0; 3 * num_points];
+                        reader.read_i32_into::<LittleEndian>(&mut buffer)?;
+                        for i in 0..num_points {
+                            attr.push(Vector3::new(
+                                buffer[3 * i],
+                                buffer[3 * i + 1],
+                                buffer[3 * i + 2],
+                            ));
+                        }
+                        batch
+                            .attributes
+                            .insert(key.to_owned(), AttributeData::I32Vec3(attr));
+                    }
+                    AttributeDataType::F32Vec3 => {
+                        let mut attr = Vec::with_capacity(num_points);
+                        let mut buffer = vec![0.0; 3 * num_points];
+                        reader.read_f32_into::<LittleEndian>(&mut buffer)?;
+                        for i in 0..num_points {
+                            attr.push(Vector3::new(
+                                buffer[3 * i],
+                                buffer[3 * i + 1],
+                                buffer[3 * i + 2],
+                            ));
+                        }
+                        batch
+                            .attributes
+                            .insert(key.to_owned(), AttributeData::F32Vec3(attr));
+                    }
                     AttributeDataType::F64Vec3 => {
                         let mut attr = Vec::with_capacity(num_points);
                         let mut buffer = vec![0.0; 3 * num_points];
@@ -321,6 +366,22 @@ impl RawNodeReader {
                             .attributes
                             .insert(key.to_owned(), AttributeData::F64Vec3(attr));
                     }
+                    AttributeDataType::U8Vec4 => {
+                        let mut attr = Vec::with_capacity(num_points);
+                        let mut buffer = vec![0; 4 * num_points];
+                        reader.read_exact(&mut buffer)?;
+                        for i in 0..num_points {
+                            attr.push(Vector4::new(
+                                buffer[4 * i],
+                                buffer[4 * i + 1],
+                                buffer[4 * i + 2],
+                                buffer[4 * i + 3],
+                            ));
+                        }
+                        batch
+                            .attributes
+                            .insert(key.to_owned(), AttributeData::U8Vec4(attr));
+                    }
                 };
                 Ok(())
             },
@@ -448,3 +509,72 @@ impl RawNodeWriter {
         self.xyz_writer.bytes_written() as i64 / bytes_per_coordinate / 3
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_raw_read_write_vector_attributes() {
+        let tmp_dir = TempDir::new("test_raw_read_write_vector_attributes").unwrap();
+        let stem = tmp_dir.path().join("node");
+        let mut attributes = BTreeMap::new();
+        attributes.insert(
+            "normal".to_string(),
+            AttributeData::F32Vec3(vec![Vector3::new(0., 0., 1.), Vector3::new(0.5, -0.5, 0.)]),
+        );
+        attributes.insert(
+            "counts".to_string(),
+            AttributeData::U16Vec3(vec![Vector3::new(1, 2, 3), Vector3::new(4, 5, 65535)]),
+        );
+        attributes.insert(
+            "cell".to_string(),
+            AttributeData::I32Vec3(vec![Vector3::new(-1, 0, 1), Vector3::new(7, -8, 9)]),
+        );
+        attributes.insert(
+            "label_color".to_string(),
+            AttributeData::U8Vec4(vec![Vector4::new(1, 2, 3, 4), Vector4::new(5, 6, 7, 8)]),
+        );
+        let batch = PointsBatch {
+            position: vec![Point3::new(1., 2., 3.), Point3::new(4., 5., 6.)],
+            attributes,
+        };
+        {
+            let mut raw_writer = RawNodeWriter::new(&stem, Encoding::Plain, OpenMode::Truncate);
+            raw_writer.write(&batch).unwrap();
+        }
+
+        let open = |attribute: &str| -> Box<dyn Read + Send> {
+            Box::new(File::open(stem.with_extension(attribute_extension(attribute))).unwrap())
+        };
+        let attribute_readers = batch
+            .attributes
+            .iter()
+            .map(|(attribute, data)| {
+                let reader = AttributeReader {
+                    data_type: data.data_type(),
+                    reader: BufReader::new(open(attribute)),
+                };
+                (attribute.clone(), reader)
+            })
+            .collect();
+        let mut raw_reader =
+            RawNodeReader::new(open("position"), attribute_readers, Encoding::Plain).unwrap();
+        let test = raw_reader.read_batch(batch.position.len()).unwrap();
+        assert_eq!(batch.position, test.position);
+        let gt_normal: &Vec<Vector3<f32>> = batch.get_attribute_vec("normal").unwrap();
+        let test_normal: &Vec<Vector3<f32>> = test.get_attribute_vec("normal").unwrap();
+        assert_eq!(gt_normal, test_normal);
+        let gt_counts: &Vec<Vector3<u16>> = batch.get_attribute_vec("counts").unwrap();
+        let test_counts: &Vec<Vector3<u16>> = test.get_attribute_vec("counts").unwrap();
+        assert_eq!(gt_counts, test_counts);
+        let gt_cell: &Vec<Vector3<i32>> = batch.get_attribute_vec("cell").unwrap();
+        let test_cell: &Vec<Vector3<i32>> = test.get_attribute_vec("cell").unwrap();
+        assert_eq!(gt_cell, test_cell);
+        let gt_label_color: &Vec<Vector4<u8>> = batch.get_attribute_vec("label_color").unwrap();
+        let test_label_color: &Vec<Vector4<u8>> = test.get_attribute_vec("label_color").unwrap();
+        assert_eq!(gt_label_color, test_label_color);
+    }
+}
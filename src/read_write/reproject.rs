@@ -0,0 +1,46 @@
+//! Converting the positions of a stream of points into another coordinate reference system while
+//! reading, e.g. UTM input into an ECEF point cloud.
+
+use crate::math::Crs;
+use crate::{NumberOfPoints, PointsBatch};
+
+/// Converts the positions of each batch of 'input' from 'from' to 'to'. Positions are passed on
+/// unchanged if either frame is unknown or both are the same.
+pub struct Reproject<I> {
+    input: I,
+    conversion: Option<(Crs, Crs)>,
+}
+
+impl<I> Reproject<I> {
+    pub fn new(input: I, from: Option<Crs>, to: Option<Crs>) -> Self {
+        let conversion = match (from, to) {
+            (Some(from), Some(to)) if from != to => Some((from, to)),
+            _ => None,
+        };
+        Reproject { input, conversion }
+    }
+}
+
+impl<I: NumberOfPoints> NumberOfPoints for Reproject<I> {
+    fn num_points(&self) -> usize {
+        self.input.num_points()
+    }
+}
+
+impl<I: Iterator<Item = PointsBatch>> Iterator for Reproject<I> {
+    type Item = PointsBatch;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+
+    fn next(&mut self) -> Option<PointsBatch> {
+        let mut batch = self.input.next()?;
+        if let Some((from, to)) = &self.conversion {
+            for p in &mut batch.position {
+                *p = from.transform_to(to, p);
+            }
+        }
+        Some(batch)
+    }
+}
@@ -1,5 +1,6 @@
+use crate::attributes::LabelDictionaries;
 use crate::geometry::Aabb;
-use crate::math::{FromPoint3, EARTH_RADIUS_MAX_M, EARTH_RADIUS_MIN_M};
+use crate::math::{Crs, FromPoint3, EARTH_RADIUS_MAX_M, EARTH_RADIUS_MIN_M};
 use crate::read_write::{Encoding, NodeWriter, OpenMode};
 use crate::s2_cells::{S2CellMeta, S2Meta};
 use crate::{AttributeData, AttributeDataType, PointsBatch};
@@ -26,6 +27,8 @@ pub struct S2Splitter<W> {
     encoding: Encoding,
     open_mode: OpenMode,
     stem: PathBuf,
+    input_crs: Crs,
+    label_dictionaries: LabelDictionaries,
 }
 
 impl<W> S2Splitter<W> {
@@ -45,8 +48,22 @@ impl<W> S2Splitter<W> {
             encoding,
             open_mode,
             stem: path.into(),
+            input_crs: Crs::Ecef,
+            label_dictionaries: LabelDictionaries::new(),
         }
     }
+
+    /// Sets the frame of the input positions, which are converted to ECEF. Defaults to ECEF.
+    pub fn input_crs(mut self, input_crs: Crs) -> Self {
+        self.input_crs = input_crs;
+        self
+    }
+
+    /// Sets the label dictionaries of the categorical attributes, which are recorded in the meta.
+    pub fn label_dictionaries(mut self, label_dictionaries: LabelDictionaries) -> Self {
+        self.label_dictionaries = label_dictionaries;
+        self
+    }
 }
 
 impl<W> NodeWriter<PointsBatch> for S2Splitter<W>
@@ -61,7 +78,9 @@ where
         self.check_attributes(points_batch)?;
         let mut batches_by_s2_cell = HashMap::new();
         for (i, pos) in points_batch.position.iter().enumerate() {
+            let pos = &self.input_crs.point_to_ecef(pos);
             let radius = pos.coords.norm();
+            // 'S2Cells::distance_to_node' relies on this range.
             if radius > EARTH_RADIUS_MAX_M || radius < EARTH_RADIUS_MIN_M {
                 let msg = format!(
                     "Point ({}, {}, {}) is not a valid ECEF point",
@@ -75,7 +94,7 @@ where
             let s2_cell_id = CellID::from_point(pos).parent(self.split_level);
             self.cell_stats
                 .entry(s2_cell_id)
-                .or_insert(S2CellMeta { num_points: 0 })
+                .or_insert_with(S2CellMeta::default)
                 .num_points += 1;
             let s2_cell_batch = batches_by_s2_cell.entry(s2_cell_id).or_insert(PointsBatch {
                 position: Vec::new(),
@@ -100,7 +119,11 @@ where
                         (F32(in_vec), F32(out_vec)) => out_vec.push(in_vec[i]),
                         (F64(in_vec), F64(out_vec)) => out_vec.push(in_vec[i]),
                         (U8Vec3(in_vec), U8Vec3(out_vec)) => out_vec.push(in_vec[i]),
+                        (U16Vec3(in_vec), U16Vec3(out_vec)) => out_vec.push(in_vec[i]),
+                        (I32Vec3(in_vec), I32Vec3(out_vec)) => out_vec.push(in_vec[i]),
+                        (F32Vec3(in_vec), F32Vec3(out_vec)) => out_vec.push(in_vec[i]),
                         (F64Vec3(in_vec), F64Vec3(out_vec)) => out_vec.push(in_vec[i]),
+                        (U8Vec4(in_vec), U8Vec4(out_vec)) => out_vec.push(in_vec[i]),
                         _ => panic!("Input data type unequal output data type."),
                     })
                     .or_insert_with(|| in_data.get(i));
@@ -108,6 +131,11 @@ where
         }
 
         for (cell_id, batch) in &batches_by_s2_cell {
+            self.cell_stats
+                .get_mut(cell_id)
+                .unwrap()
+                .attribute_ranges
+                .update(batch);
             self.writer(cell_id).write(batch)?;
         }
         Ok(())
@@ -168,7 +196,88 @@ where
             self.cell_stats,
             self.attributes_seen.into_iter().collect(),
             self.bounding_box?,
+            Some(Crs::Ecef),
+            self.label_dictionaries,
         );
         Some(meta)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::LabelDictionary;
+    use crate::read_write::RawNodeWriter;
+    use nalgebra::Point3;
+    use tempdir::TempDir;
+
+    fn local_batch() -> PointsBatch {
+        let mut attributes = BTreeMap::new();
+        attributes.insert(
+            "intensity".to_string(),
+            AttributeData::F32(vec![1.0, 2.0, 3.0]),
+        );
+        PointsBatch {
+            position: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(10.0, 0.0, 0.0),
+                Point3::new(0.0, 10.0, 5.0),
+            ],
+            attributes,
+        }
+    }
+
+    #[test]
+    fn test_label_dictionaries() {
+        let tmp_dir = TempDir::new("s2").unwrap();
+        let mut dictionary = LabelDictionary::new();
+        dictionary.insert(2, "ground", None);
+        let mut label_dictionaries = LabelDictionaries::new();
+        label_dictionaries.insert("intensity", dictionary.clone());
+        let mut splitter: S2Splitter<RawNodeWriter> =
+            S2Splitter::new(tmp_dir.path(), Encoding::Plain, OpenMode::Truncate)
+                .label_dictionaries(label_dictionaries);
+        let mut batch = local_batch();
+        batch.position = vec![Point3::new(EARTH_RADIUS_MIN_M + 10.0, 0.0, 0.0); 3];
+        splitter.write(&batch).unwrap();
+        let meta = S2Meta::from_proto(splitter.get_meta().unwrap().to_proto()).unwrap();
+        assert_eq!(
+            meta.label_dictionaries().get("intensity"),
+            Some(&dictionary)
+        );
+    }
+
+    #[test]
+    fn test_input_crs() {
+        let tmp_dir = TempDir::new("s2").unwrap();
+        let input_crs = Crs::LocalEnu {
+            latitude: 37.4,
+            longitude: -122.1,
+        };
+        let batch = local_batch();
+
+        // Local positions are close to the earth's center when taken as ECEF.
+        let mut ecef_splitter: S2Splitter<RawNodeWriter> =
+            S2Splitter::new(tmp_dir.path(), Encoding::Plain, OpenMode::Truncate);
+        assert!(ecef_splitter.write(&batch).is_err());
+
+        let mut splitter: S2Splitter<RawNodeWriter> =
+            S2Splitter::new(tmp_dir.path(), Encoding::Plain, OpenMode::Truncate)
+                .input_crs(input_crs);
+        splitter.write(&batch).unwrap();
+        let meta = splitter.get_meta().unwrap();
+        assert_eq!(meta.crs(), Some(Crs::Ecef));
+        let num_points: u64 = meta.get_cells().values().map(|cell| cell.num_points).sum();
+        assert_eq!(num_points, 3);
+        let ecef: Vec<Point3<f64>> = batch
+            .position
+            .iter()
+            .map(|p| input_crs.point_to_ecef(p))
+            .collect();
+        let mut expected_bounding_box = Aabb::new(ecef[0], ecef[0]);
+        for p in &ecef {
+            expected_bounding_box.grow(*p);
+        }
+        assert_eq!(meta.bounding_box(), &expected_bounding_box);
+    }
+}
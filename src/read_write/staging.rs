@@ -0,0 +1,350 @@
+//! Building point clouds next to their final location, so that readers never see a partial one.
+//!
+//! A build writes everything into a staging directory that contains a build-in-progress marker.
+//! Once all nodes and the meta are written, the staging directory becomes a new version directory
+//! next to the output directory. The output directory is a symlink to the current version, which
+//! is switched to the new version with a single rename. The previous version is removed
+//! afterwards. If the publication is interrupted, it is finished the next time a build for the
+//! same output directory starts.
+//!
+//! Readers resolve the symlink when they open a point cloud, so they keep reading the version they
+//! opened. Once it is removed by the next publication, their reads fail and they need to open the
+//! point cloud again.
+
+use crate::errors::*;
+use crate::proto;
+use crate::{BUILD_IN_PROGRESS_FILENAME, META_FILENAME};
+use protobuf::Message;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+fn sibling_directory(output_directory: &Path, suffix: &str) -> Result<PathBuf> {
+    let mut name: OsString = output_directory
+        .file_name()
+        .ok_or_else(|| {
+            ErrorKind::InvalidInput(format!(
+                "Output directory {} needs to have a name.",
+                output_directory.display()
+            ))
+        })?
+        .to_os_string();
+    name.push(suffix);
+    Ok(output_directory.with_file_name(name))
+}
+
+/// Returns the directory a point cloud for 'output_directory' is built in.
+pub fn staging_directory(output_directory: &Path) -> Result<PathBuf> {
+    sibling_directory(output_directory, ".staging")
+}
+
+/// Returns the directory version 'version' of the point cloud in 'output_directory' is published
+/// in.
+fn version_directory(output_directory: &Path, version: u64) -> Result<PathBuf> {
+    sibling_directory(output_directory, &format!(".v{}", version))
+}
+
+/// Returns the published versions of the point cloud in 'output_directory' with their
+/// directories, sorted by version. This includes versions whose publication was interrupted.
+fn versions(output_directory: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let prefix = sibling_directory(output_directory, ".v")?;
+    let prefix = prefix.file_name().unwrap().to_string_lossy().into_owned();
+    let parent = match output_directory.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut versions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let version = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|version| version.parse::<u64>().ok());
+        if let Some(version) = version {
+            versions.push((version, version_directory(output_directory, version)?));
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// Returns the version directory 'output_directory' links to, if it is a symlink.
+fn current_version_directory(output_directory: &Path) -> Result<Option<PathBuf>> {
+    match fs::symlink_metadata(output_directory) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            let target = fs::read_link(output_directory)?;
+            Ok(Some(output_directory.with_file_name(target)))
+        }
+        Ok(_) => Ok(None),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Returns true if 'directory' contains a point cloud that is still being built or whose build
+/// was interrupted.
+pub fn is_build_in_progress(directory: &Path) -> bool {
+    directory.join(BUILD_IN_PROGRESS_FILENAME).exists()
+}
+
+/// Returns true if publishing the completed build for 'output_directory' was started, but did not
+/// finish. Call `publish_staged_build` to finish it.
+pub fn is_publication_interrupted(output_directory: &Path) -> Result<bool> {
+    let staging_directory = staging_directory(output_directory)?;
+    if staging_directory.join(META_FILENAME).exists() && !is_build_in_progress(&staging_directory) {
+        return Ok(true);
+    }
+    let current = current_version_directory(output_directory)?;
+    Ok(versions(output_directory)?
+        .iter()
+        .any(|(_, directory)| Some(directory) != current.as_ref()))
+}
+
+/// Creates an empty staging directory for 'output_directory' and marks it as in progress. An
+/// interrupted publication of a previous build is finished first, other leftovers from previous
+/// builds are removed. Returns the staging directory.
+pub fn start_staged_build(output_directory: &Path) -> Result<PathBuf> {
+    if is_publication_interrupted(output_directory)? {
+        publish_staged_build(output_directory)?;
+    }
+    let staging_directory = staging_directory(output_directory)?;
+    if staging_directory.exists() {
+        fs::remove_dir_all(&staging_directory)?;
+    }
+    fs::create_dir_all(&staging_directory)?;
+    File::create(staging_directory.join(BUILD_IN_PROGRESS_FILENAME))?.sync_all()?;
+    Ok(staging_directory)
+}
+
+/// Writes 'meta' into 'directory'. The meta is written to a temporary file first, so that it is
+/// either complete or absent.
+pub fn write_meta(directory: &Path, meta: &proto::Meta) -> Result<()> {
+    let meta_path = directory.join(META_FILENAME);
+    let tmp_meta_path = meta_path.with_extension("pb.tmp");
+    {
+        let mut buf_writer = BufWriter::new(File::create(&tmp_meta_path)?);
+        meta.write_to_writer(&mut buf_writer)
+            .chain_err(|| format!("Could not write {}", META_FILENAME))?;
+        buf_writer.flush()?;
+        buf_writer.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp_meta_path, &meta_path)?;
+    Ok(())
+}
+
+/// Publishes the completed build in the staging directory as a new version and points
+/// 'output_directory' to it with a single rename, then removes the previous version. Readers that
+/// opened the previous version fail to read from it from then on, but never see a mix of both
+/// point clouds. If this is interrupted, it can be called again to finish.
+///
+/// An output directory that was not published this way is moved aside to a sibling with the
+/// suffix '.replaced' if it contains a point cloud, and left alone otherwise. In between, readers
+/// cannot open 'output_directory' at all.
+pub fn publish_staged_build(output_directory: &Path) -> Result<()> {
+    let staging_directory = staging_directory(output_directory)?;
+    let is_staged = staging_directory.join(META_FILENAME).exists();
+    let current = current_version_directory(output_directory)?;
+    let mut versions = versions(output_directory)?;
+    let is_unlinked_version = versions.last().map(|(_, directory)| directory) != current.as_ref();
+    if !is_staged && !is_unlinked_version {
+        return Err(ErrorKind::InvalidInput(format!(
+            "{} does not contain a complete point cloud.",
+            staging_directory.display()
+        ))
+        .into());
+    }
+
+    // A directory that was not published as a version has to be moved out of the way first.
+    let replaced_directory = sibling_directory(output_directory, ".replaced")?;
+    let is_directory = current.is_none() && output_directory.exists();
+    if is_directory {
+        if !output_directory.is_dir() {
+            return Err(ErrorKind::InvalidInput(format!(
+                "{} is not a directory.",
+                output_directory.display()
+            ))
+            .into());
+        }
+        let is_empty = fs::read_dir(output_directory)?.next().is_none();
+        let is_point_cloud =
+            output_directory.join(META_FILENAME).exists() || is_build_in_progress(output_directory);
+        if !is_empty && !is_point_cloud {
+            return Err(ErrorKind::InvalidInput(format!(
+                "Refusing to replace {}, which does not contain a point cloud.",
+                output_directory.display()
+            ))
+            .into());
+        }
+        if !is_empty && replaced_directory.exists() {
+            return Err(ErrorKind::InvalidInput(format!(
+                "Refusing to replace {}, since {} already exists.",
+                output_directory.display(),
+                replaced_directory.display()
+            ))
+            .into());
+        }
+    }
+
+    if is_staged {
+        // From here on, the publication is finished by the next build if it is interrupted.
+        if is_build_in_progress(&staging_directory) {
+            fs::remove_file(staging_directory.join(BUILD_IN_PROGRESS_FILENAME))?;
+        }
+        let version = versions.last().map_or(1, |(version, _)| version + 1);
+        let directory = version_directory(output_directory, version)?;
+        fs::rename(&staging_directory, &directory)?;
+        versions.push((version, directory));
+    }
+    let (_, new_directory) = versions.pop().unwrap();
+
+    if is_directory {
+        if fs::read_dir(output_directory)?.next().is_none() {
+            fs::remove_dir(output_directory)?;
+        } else {
+            fs::rename(output_directory, &replaced_directory)?;
+        }
+    }
+    let link = sibling_directory(output_directory, ".link")?;
+    if fs::symlink_metadata(&link).is_ok() {
+        fs::remove_file(&link)?;
+    }
+    symlink(new_directory.file_name().unwrap(), &link)?;
+    fs::rename(&link, output_directory)?;
+
+    for (_, directory) in versions {
+        fs::remove_dir_all(directory)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_provider::OnDiskDataProvider;
+    use tempdir::TempDir;
+
+    /// Stages a point cloud for 'output_directory' that consists of the meta and 'files'.
+    fn stage(output_directory: &Path, files: &[(&str, &str)]) -> PathBuf {
+        let staging_directory = start_staged_build(output_directory).unwrap();
+        assert!(is_build_in_progress(&staging_directory));
+        for (file, contents) in files {
+            fs::write(staging_directory.join(file), contents).unwrap();
+        }
+        write_meta(&staging_directory, &proto::Meta::new()).unwrap();
+        staging_directory
+    }
+
+    fn read(directory: &Path, file: &str) -> String {
+        fs::read_to_string(directory.join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_publish_replaces_previous_output() {
+        let tmp_dir = TempDir::new("staging").unwrap();
+        let output_directory = tmp_dir.path().join("cloud");
+
+        let staging_directory = start_staged_build(&output_directory).unwrap();
+        fs::write(staging_directory.join("a.xyz"), "first").unwrap();
+        // Publishing without a meta must fail and leave everything in place.
+        assert!(publish_staged_build(&output_directory).is_err());
+        assert!(!output_directory.exists());
+
+        stage(&output_directory, &[("a.xyz", "first"), ("b.xyz", "first")]);
+        publish_staged_build(&output_directory).unwrap();
+        assert!(!staging_directory.exists());
+        assert!(!is_build_in_progress(&output_directory));
+        assert_eq!(read(&output_directory, "a.xyz"), "first");
+        assert!(output_directory.join(META_FILENAME).exists());
+
+        // A reader keeps the version it opened, which is gone once the next one is published.
+        let reader = OnDiskDataProvider::new(output_directory.clone());
+        stage(
+            &output_directory,
+            &[("b.xyz", "second"), ("c.xyz", "second")],
+        );
+        publish_staged_build(&output_directory).unwrap();
+        assert!(!output_directory.join("a.xyz").exists());
+        assert_eq!(read(&output_directory, "b.xyz"), "second");
+        assert_eq!(read(&output_directory, "c.xyz"), "second");
+        assert!(!reader.directory.exists());
+        assert_eq!(
+            fs::read_dir(tmp_dir.path()).unwrap().count(),
+            2,
+            "Only the output directory and its current version are left."
+        );
+    }
+
+    #[test]
+    fn test_publish_replaces_unversioned_output() {
+        let tmp_dir = TempDir::new("staging").unwrap();
+        let output_directory = tmp_dir.path().join("cloud");
+        fs::create_dir(&output_directory).unwrap();
+        fs::write(output_directory.join(META_FILENAME), "").unwrap();
+        fs::write(output_directory.join("input.ply"), "input").unwrap();
+
+        stage(&output_directory, &[("a.xyz", "first")]);
+        publish_staged_build(&output_directory).unwrap();
+        assert_eq!(read(&output_directory, "a.xyz"), "first");
+        // The previous directory is kept, since it might contain other files.
+        let replaced_directory = sibling_directory(&output_directory, ".replaced").unwrap();
+        assert_eq!(read(&replaced_directory, "input.ply"), "input");
+    }
+
+    #[test]
+    fn test_publish_refuses_to_replace_other_directories() {
+        let tmp_dir = TempDir::new("staging").unwrap();
+        let output_directory = tmp_dir.path().join("cloud");
+        fs::create_dir(&output_directory).unwrap();
+        fs::write(output_directory.join("a.xyz"), "mine").unwrap();
+
+        stage(&output_directory, &[("a.xyz", "first")]);
+        assert!(publish_staged_build(&output_directory).is_err());
+        assert_eq!(read(&output_directory, "a.xyz"), "mine");
+        assert!(!is_publication_interrupted(&output_directory).unwrap());
+    }
+
+    #[test]
+    fn test_interrupted_publication_is_finished() {
+        let tmp_dir = TempDir::new("staging").unwrap();
+        let output_directory = tmp_dir.path().join("cloud");
+        stage(&output_directory, &[("a.xyz", "first"), ("b.xyz", "first")]);
+        publish_staged_build(&output_directory).unwrap();
+
+        // Simulate a publication that was interrupted after the new version was moved into place,
+        // but before the output directory was switched to it.
+        let staging_directory = stage(
+            &output_directory,
+            &[("a.xyz", "second"), ("c.xyz", "second")],
+        );
+        fs::remove_file(staging_directory.join(BUILD_IN_PROGRESS_FILENAME)).unwrap();
+        fs::rename(
+            &staging_directory,
+            version_directory(&output_directory, 2).unwrap(),
+        )
+        .unwrap();
+        assert!(is_publication_interrupted(&output_directory).unwrap());
+        assert_eq!(read(&output_directory, "a.xyz"), "first");
+
+        // The next build finishes the publication before it starts.
+        start_staged_build(&output_directory).unwrap();
+        assert!(!is_publication_interrupted(&output_directory).unwrap());
+        assert!(!output_directory.join("b.xyz").exists());
+        assert_eq!(read(&output_directory, "a.xyz"), "second");
+        assert_eq!(read(&output_directory, "c.xyz"), "second");
+        assert!(!version_directory(&output_directory, 1).unwrap().exists());
+    }
+
+    #[test]
+    fn test_output_directory_needs_a_name() {
+        assert!(staging_directory(Path::new("/")).is_err());
+        assert!(start_staged_build(Path::new("/")).is_err());
+    }
+}
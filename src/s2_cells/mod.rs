@@ -1,15 +1,24 @@
+use crate::attributes::{AttributeRanges, LabelDictionaries, LabelDictionary};
 use crate::data_provider::DataProvider;
 use crate::errors::*;
-use crate::geometry::Aabb;
+use crate::geometry::{
+    difference_relation, intersection_relation, union_relation, Aabb, Difference, Intersection,
+    Union,
+};
 use crate::iterator::{PointCloud, PointLocation};
-use crate::math::{ConvexPolyhedron, FromPoint3};
+use crate::math::{
+    ConvexPolyhedron, Crs, FromPoint3, Relation, EARTH_RADIUS_MAX_M, EARTH_RADIUS_MIN_M,
+};
 use crate::proto;
 use crate::read_write::{Encoding, NodeIterator};
 use crate::{AttributeDataType, PointCloudMeta, CURRENT_VERSION};
 use fnv::FnvHashMap;
+use nalgebra::{Point3, Vector3};
+use num::clamp;
 use s2::cell::Cell;
 use s2::cellid::CellID;
 use s2::cellunion::CellUnion;
+use s2::point::Point;
 use s2::region::Region;
 use std::collections::HashMap;
 use std::iter;
@@ -20,16 +29,20 @@ pub struct S2Cells {
     meta: S2Meta,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone, Default)]
 pub struct S2CellMeta {
     pub num_points: u64,
+    pub attribute_ranges: AttributeRanges,
 }
 
 impl S2CellMeta {
-    pub fn to_proto(self, cell_id: u64) -> proto::S2Cell {
+    pub fn to_proto(&self, cell_id: u64) -> proto::S2Cell {
         let mut meta = proto::S2Cell::new();
         meta.set_id(cell_id);
         meta.set_num_points(self.num_points);
+        meta.set_attribute_ranges(::protobuf::RepeatedField::from_vec(
+            self.attribute_ranges.to_proto(),
+        ));
         meta
     }
 }
@@ -38,6 +51,8 @@ pub struct S2Meta {
     cells: FnvHashMap<CellID, S2CellMeta>,
     attribute_data_types: HashMap<String, AttributeDataType>,
     bounding_box: Aabb<f64>,
+    crs: Option<Crs>,
+    label_dictionaries: LabelDictionaries,
 }
 
 impl PointCloudMeta for S2Meta {
@@ -51,11 +66,15 @@ impl S2Meta {
         cells: FnvHashMap<CellID, S2CellMeta>,
         attribute_data_types: HashMap<String, AttributeDataType>,
         bounding_box: Aabb<f64>,
+        crs: Option<Crs>,
+        label_dictionaries: LabelDictionaries,
     ) -> Self {
         S2Meta {
             cells,
             attribute_data_types,
             bounding_box,
+            crs,
+            label_dictionaries,
         }
     }
 
@@ -74,6 +93,14 @@ impl S2Meta {
         &self.bounding_box
     }
 
+    pub fn crs(&self) -> Option<Crs> {
+        self.crs
+    }
+
+    pub fn label_dictionaries(&self) -> &LabelDictionaries {
+        &self.label_dictionaries
+    }
+
     pub fn to_proto(&self) -> proto::Meta {
         let cell_protos = self
             .cells
@@ -101,6 +128,12 @@ impl S2Meta {
             attributes_meta,
         ));
         meta.set_s2(s2_meta);
+        if let Some(crs) = self.crs {
+            meta.set_crs(crs.to_proto());
+        }
+        meta.set_label_dictionaries(::protobuf::RepeatedField::from_vec(
+            self.label_dictionaries.to_proto(),
+        ));
         meta
     }
 
@@ -131,6 +164,7 @@ impl S2Meta {
                 cell_id,
                 S2CellMeta {
                     num_points: cell.num_points,
+                    attribute_ranges: AttributeRanges::from_proto(cell.get_attribute_ranges()),
                 },
             );
         });
@@ -145,10 +179,13 @@ impl S2Meta {
             cells,
             attribute_data_types,
             bounding_box,
+            crs: Crs::from_proto(meta_proto.get_crs())?,
+            label_dictionaries: LabelDictionaries::from_proto(meta_proto.get_label_dictionaries()),
         })
     }
 
     pub fn from_data_provider(data_provider: &dyn DataProvider) -> Result<Self> {
+        data_provider.check_complete()?;
         let meta = data_provider.meta_proto()?;
         S2Meta::from_proto(meta)
     }
@@ -157,14 +194,37 @@ impl S2Meta {
 impl PointCloud for S2Cells {
     type Id = CellID;
 
-    fn nodes_in_location(&self, location: &PointLocation) -> Vec<Self::Id> {
+    fn nodes_in_location(&self, location: &PointLocation) -> Vec<(Self::Id, Relation)> {
         match location {
-            PointLocation::AllPoints => self.cells.keys().cloned().collect(),
+            PointLocation::AllPoints => self
+                .cells
+                .keys()
+                .map(|cell_id| (*cell_id, Relation::In))
+                .collect(),
             PointLocation::Aabb(aabb) => self.cells_in_convex_polyhedron(aabb),
             PointLocation::Obb(obb) => self.cells_in_convex_polyhedron(obb),
             PointLocation::Frustum(frustum) => self.cells_in_convex_polyhedron(frustum),
-            PointLocation::S2Cells(cell_union) => self.cells_intersecting_region(cell_union),
+            PointLocation::S2Cells(cell_union) => self.cells_in_cell_union(cell_union),
             PointLocation::WebMercatorRect(wmr) => self.cells_in_convex_polyhedron(wmr),
+            // The covering of the bounding box also covers the sphere.
+            PointLocation::Sphere(sphere) => {
+                self.cells_in_convex_polyhedron(&sphere.bounding_box())
+            }
+            PointLocation::Prism(prism) => self.cells_in_convex_polyhedron(&prism.bounding_obb()),
+            PointLocation::Corridor(corridor) => {
+                let mut cells = corridor
+                    .bounding_obbs()
+                    .iter()
+                    .flat_map(|obb| self.cells_in_convex_polyhedron(obb))
+                    .collect::<Vec<_>>();
+                // Cells covered by several segments must only be returned once.
+                cells.sort_unstable();
+                cells.dedup();
+                cells
+            }
+            PointLocation::Union(union) => self.cells_in_union(union),
+            PointLocation::Intersection(intersection) => self.cells_in_intersection(intersection),
+            PointLocation::Difference(difference) => self.cells_in_difference(difference),
         }
     }
 
@@ -193,10 +253,62 @@ impl PointCloud for S2Cells {
     fn bounding_box(&self) -> &Aabb<f64> {
         &self.meta.bounding_box
     }
+
+    fn crs(&self) -> Option<Crs> {
+        self.meta.crs
+    }
+
+    fn attribute_data_types(&self) -> &HashMap<String, AttributeDataType> {
+        self.meta.attribute_data_types()
+    }
+
+    fn label_dictionary(&self, attribute: &str) -> Option<&LabelDictionary> {
+        self.meta.label_dictionaries.get(attribute)
+    }
+
+    fn attribute_ranges(&self, node_id: Self::Id) -> Option<&AttributeRanges> {
+        self.meta
+            .cells
+            .get(&node_id)
+            .map(|cell_meta| &cell_meta.attribute_ranges)
+    }
+
+    fn num_points_in_node(&self, node_id: Self::Id) -> usize {
+        self.meta.cells[&node_id].num_points as usize
+    }
+
+    fn distance_to_node(&self, node_id: Self::Id, point: &Point3<f64>) -> f64 {
+        // Points in a cell have directions within the cell and a valid ECEF radius. The cell is
+        // bounded by the cap around its center which contains its vertices.
+        let cell = &self.cells[&node_id];
+        let direction = |p: Point| Vector3::new(p.0.x, p.0.y, p.0.z);
+        let center = direction(cell.center());
+        let cap_angle = (0..4)
+            .map(|k| center.angle(&direction(cell.vertex(k))))
+            .fold(0.0, f64::max);
+        let point_radius = point.coords.norm();
+        let angle = if point_radius > 0.0 {
+            (center.angle(&point.coords) - cap_angle).max(0.0)
+        } else {
+            0.0
+        };
+        // The closest radius along a direction at 'angle' to the point. The S2 writer rejects
+        // points outside of [EARTH_RADIUS_MIN_M, EARTH_RADIUS_MAX_M], so the clamp keeps this a
+        // lower bound only as long as both use the same constants.
+        let radius = clamp(
+            point_radius * angle.cos(),
+            EARTH_RADIUS_MIN_M,
+            EARTH_RADIUS_MAX_M,
+        );
+        (point_radius * point_radius + radius * radius - 2.0 * point_radius * radius * angle.cos())
+            .max(0.0)
+            .sqrt()
+    }
 }
 
 impl S2Cells {
     pub fn from_data_provider(data_provider: Box<dyn DataProvider>) -> Result<Self> {
+        data_provider.check_complete()?;
         let meta_proto = data_provider.meta_proto()?;
         let meta = S2Meta::from_proto(meta_proto)?;
         let cells: FnvHashMap<_, _> = meta
@@ -215,8 +327,9 @@ impl S2Cells {
         self.meta.to_proto()
     }
 
-    /// Returns all cells that intersect this convex polyhedron
-    fn cells_in_convex_polyhedron<T>(&self, poly: &T) -> Vec<CellID>
+    /// Returns all cells that intersect this convex polyhedron. The covering of the polyhedron
+    /// is only approximate, so no cell is known to be completely inside.
+    fn cells_in_convex_polyhedron<T>(&self, poly: &T) -> Vec<(CellID, Relation)>
     where
         T: ConvexPolyhedron<f64>,
     {
@@ -229,14 +342,88 @@ impl S2Cells {
         let mut cell_union = CellUnion(point_cells);
         cell_union.normalize();
         let rect = cell_union.rect_bound();
-        self.cells_intersecting_region(&rect)
+        self.cells
+            .values()
+            .filter(|cell| rect.intersects_cell(cell))
+            .map(|cell| (cell.id, Relation::Cross))
+            .collect()
     }
 
-    fn cells_intersecting_region(&self, region: &impl Region) -> Vec<CellID> {
+    fn cells_in_union(&self, union: &Union<PointLocation>) -> Vec<(CellID, Relation)> {
+        let mut cells: FnvHashMap<CellID, Relation> = FnvHashMap::default();
+        for part in union.parts() {
+            for (cell_id, relation) in self.nodes_in_location(part) {
+                let union_relation_so_far = cells.entry(cell_id).or_insert(Relation::Out);
+                *union_relation_so_far =
+                    union_relation([*union_relation_so_far, relation].iter().copied());
+            }
+        }
+        cells.into_iter().collect()
+    }
+
+    fn cells_in_intersection(
+        &self,
+        intersection: &Intersection<PointLocation>,
+    ) -> Vec<(CellID, Relation)> {
+        let mut parts = intersection.parts().iter();
+        let mut cells: FnvHashMap<CellID, Relation> = match parts.next() {
+            Some(part) => self.nodes_in_location(part).into_iter().collect(),
+            None => return self.nodes_in_location(&PointLocation::AllPoints),
+        };
+        for part in parts {
+            let part_cells: FnvHashMap<CellID, Relation> =
+                self.nodes_in_location(part).into_iter().collect();
+            // Cells that are not returned for a part are outside of it.
+            cells = cells
+                .into_iter()
+                .filter_map(|(cell_id, relation)| {
+                    part_cells.get(&cell_id).map(|part_relation| {
+                        (
+                            cell_id,
+                            intersection_relation([relation, *part_relation].iter().copied()),
+                        )
+                    })
+                })
+                .collect();
+        }
+        cells.into_iter().collect()
+    }
+
+    fn cells_in_difference(
+        &self,
+        difference: &Difference<PointLocation>,
+    ) -> Vec<(CellID, Relation)> {
+        let subtrahend_cells: FnvHashMap<CellID, Relation> = self
+            .nodes_in_location(difference.subtrahend())
+            .into_iter()
+            .collect();
+        self.nodes_in_location(difference.minuend())
+            .into_iter()
+            .filter_map(|(cell_id, relation)| {
+                let subtrahend_relation = subtrahend_cells
+                    .get(&cell_id)
+                    .copied()
+                    .unwrap_or(Relation::Out);
+                match difference_relation(relation, subtrahend_relation) {
+                    Relation::Out => None,
+                    relation => Some((cell_id, relation)),
+                }
+            })
+            .collect()
+    }
+
+    fn cells_in_cell_union(&self, cell_union: &CellUnion) -> Vec<(CellID, Relation)> {
         self.cells
             .values()
-            .filter(|cell| region.intersects_cell(cell))
-            .map(|cell| cell.id)
+            .filter_map(|cell| {
+                if cell_union.contains_cellid(&cell.id) {
+                    Some((cell.id, Relation::In))
+                } else if cell_union.intersects_cell(cell) {
+                    Some((cell.id, Relation::Cross))
+                } else {
+                    None
+                }
+            })
             .collect()
     }
 }
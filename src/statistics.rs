@@ -0,0 +1,445 @@
+//! Summaries of point clouds, both from their meta and from their points.
+
+use crate::attributes::Components;
+use crate::errors::*;
+use crate::geometry::Aabb;
+use crate::iterator::{ParallelIterator, PointCloud, PointQuery};
+use crate::math::ClosedInterval;
+use crate::octree::{NodeId, OctreeMeta};
+use crate::proto;
+use crate::read_write::PositionEncoding;
+use crate::{AttributeData, AttributeDataType, PointCloudMeta, PointsBatch};
+use s2::cellid::CellID;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Everything that can be learned about a point cloud from its meta alone.
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaSummary {
+    /// Either "octree" or "s2".
+    pub format: String,
+    pub version: i32,
+    pub bounding_box: Aabb<f64>,
+    /// Only octrees have a resolution.
+    pub resolution: Option<f64>,
+    pub attributes: BTreeMap<String, String>,
+    /// The number of octree nodes or S2 cells.
+    pub num_nodes: usize,
+    pub num_points: u64,
+    pub num_points_per_level: BTreeMap<u64, u64>,
+    /// The number of nodes using each position encoding.
+    pub num_nodes_per_encoding: BTreeMap<String, usize>,
+}
+
+fn data_type_name(data_type: AttributeDataType) -> String {
+    format!("{:?}", data_type)
+}
+
+fn summarize_octree(meta: &proto::Meta) -> Result<MetaSummary> {
+    let (bounding_box, resolution, nodes) = match meta.version {
+        9 | 10 | 11 => (
+            Aabb::from(meta.get_bounding_box()),
+            meta.deprecated_resolution,
+            meta.get_deprecated_nodes(),
+        ),
+        12 => (
+            Aabb::from(meta.get_octree().get_deprecated_bounding_box()),
+            meta.get_octree().resolution,
+            meta.get_octree().get_nodes(),
+        ),
+        _ => (
+            Aabb::from(meta.get_bounding_box()),
+            meta.get_octree().resolution,
+            meta.get_octree().get_nodes(),
+        ),
+    };
+    let attributes = OctreeMeta::new_with_standard_attributes(resolution, bounding_box.clone())
+        .attribute_data_types()
+        .iter()
+        .map(|(name, data_type)| (name.clone(), data_type_name(*data_type)))
+        .collect();
+    let mut num_points_per_level = BTreeMap::new();
+    let mut num_nodes_per_encoding = BTreeMap::new();
+    for node in nodes {
+        let level = u64::from(NodeId::from_proto(node.get_id()).level());
+        *num_points_per_level.entry(level).or_insert(0) += node.num_points as u64;
+        let encoding = PositionEncoding::from_proto(node.position_encoding)?;
+        *num_nodes_per_encoding
+            .entry(format!("{:?}", encoding))
+            .or_insert(0) += 1;
+    }
+    Ok(MetaSummary {
+        format: "octree".to_string(),
+        version: meta.version,
+        bounding_box,
+        resolution: Some(resolution),
+        attributes,
+        num_nodes: nodes.len(),
+        num_points: num_points_per_level.values().sum(),
+        num_points_per_level,
+        num_nodes_per_encoding,
+    })
+}
+
+fn summarize_s2(meta: &proto::Meta) -> Result<MetaSummary> {
+    let s2_meta = meta.get_s2();
+    let attributes = s2_meta
+        .get_attributes()
+        .iter()
+        .map(|attribute| {
+            AttributeDataType::from_proto(attribute.get_data_type())
+                .map(|data_type| (attribute.name.clone(), data_type_name(data_type)))
+        })
+        .collect::<Result<_>>()?;
+    let mut num_points_per_level = BTreeMap::new();
+    for cell in s2_meta.get_cells() {
+        *num_points_per_level
+            .entry(CellID(cell.id).level())
+            .or_insert(0) += cell.num_points;
+    }
+    // Points in S2 cells are always stored unscaled.
+    let mut num_nodes_per_encoding = BTreeMap::new();
+    if !s2_meta.get_cells().is_empty() {
+        num_nodes_per_encoding.insert(
+            format!("{:?}", PositionEncoding::Float64),
+            s2_meta.get_cells().len(),
+        );
+    }
+    Ok(MetaSummary {
+        format: "s2".to_string(),
+        version: meta.version,
+        bounding_box: Aabb::from(meta.get_bounding_box()),
+        resolution: None,
+        attributes,
+        num_nodes: s2_meta.get_cells().len(),
+        num_points: num_points_per_level.values().sum(),
+        num_points_per_level,
+        num_nodes_per_encoding,
+    })
+}
+
+/// Summarizes the meta of an octree or S2 point cloud of any supported version.
+pub fn summarize_meta(meta: &proto::Meta) -> Result<MetaSummary> {
+    match meta.version {
+        9 | 10 | 11 => summarize_octree(meta),
+        12 | crate::CURRENT_VERSION if meta.has_octree() => summarize_octree(meta),
+        12 | crate::CURRENT_VERSION if meta.has_s2() => summarize_s2(meta),
+        12 | crate::CURRENT_VERSION => Err(ErrorKind::InvalidInput(
+            "The meta describes neither an octree nor S2 cells.".to_string(),
+        )
+        .into()),
+        version => Err(ErrorKind::InvalidVersion(version).into()),
+    }
+}
+
+/// Statistics of one component of an attribute, e.g. of the red channel of a color.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ComponentStatistics {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Number of values in equally wide bins between 'min' and 'max'.
+    pub histogram: Vec<u64>,
+}
+
+/// Equally wide bins over a fixed range, so that the histograms of different nodes can be merged.
+/// Values outside of the range are counted in the first or last bin.
+#[derive(Clone, Copy, Debug)]
+pub struct HistogramBins {
+    pub range: ClosedInterval<f64>,
+    pub num_bins: usize,
+}
+
+impl HistogramBins {
+    fn bin(&self, value: f64) -> usize {
+        let (min, max) = (self.range.lower_bound(), self.range.upper_bound());
+        if value.is_nan() || value <= min || max <= min {
+            return 0;
+        }
+        let bin = ((value - min) / (max - min) * self.num_bins as f64) as usize;
+        bin.min(self.num_bins - 1)
+    }
+}
+
+/// Min, max and mean of the values of one component, as accumulated so far.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ComponentAccumulator {
+    pub min: f64,
+    pub max: f64,
+    sum: f64,
+    count: u64,
+}
+
+impl ComponentAccumulator {
+    fn new() -> Self {
+        Self {
+            min: std::f64::INFINITY,
+            max: std::f64::NEG_INFINITY,
+            sum: 0.,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    /// Bins between min and max, or a single range for components without any number.
+    fn histogram_bins(&self, num_bins: usize) -> HistogramBins {
+        let range = if self.min <= self.max {
+            ClosedInterval::new(self.min, self.max)
+        } else {
+            ClosedInterval::new(0., 0.)
+        };
+        HistogramBins { range, num_bins }
+    }
+}
+
+/// Accumulates statistics and histograms of each component of attributes from batches of points.
+/// Accumulators of parts of the points can be merged. The attribute "position" refers to the
+/// coordinates of the points.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AttributeAccumulator {
+    pub statistics: BTreeMap<String, Vec<ComponentAccumulator>>,
+    pub histograms: BTreeMap<String, Vec<Vec<u64>>>,
+}
+
+impl AttributeAccumulator {
+    pub fn add_statistics(&mut self, batch: &PointsBatch, name: &str) {
+        let components = self.statistics.entry(name.to_string()).or_default();
+        for_each_component(batch, name, |component, value| {
+            if components.len() <= component {
+                components.resize(component + 1, ComponentAccumulator::new());
+            }
+            components[component].add(value);
+        });
+    }
+
+    /// Counts the values of 'name' into the histograms of their components, using the bins
+    /// returned by 'bins' for each component.
+    pub fn add_histograms(
+        &mut self,
+        batch: &PointsBatch,
+        name: &str,
+        bins: impl Fn(usize) -> HistogramBins,
+    ) {
+        let histograms = self.histograms.entry(name.to_string()).or_default();
+        for_each_component(batch, name, |component, value| {
+            let bins = bins(component);
+            if bins.num_bins == 0 {
+                return;
+            }
+            if histograms.len() <= component {
+                histograms.resize(component + 1, Vec::new());
+            }
+            let histogram = &mut histograms[component];
+            if histogram.is_empty() {
+                histogram.resize(bins.num_bins, 0);
+            }
+            histogram[bins.bin(value)] += 1;
+        });
+    }
+
+    pub fn merge(&mut self, other: AttributeAccumulator) {
+        for (name, components) in other.statistics {
+            let own = self.statistics.entry(name).or_default();
+            if own.len() < components.len() {
+                own.resize(components.len(), ComponentAccumulator::new());
+            }
+            for (own, other) in own.iter_mut().zip(&components) {
+                own.merge(other);
+            }
+        }
+        for (name, histograms) in other.histograms {
+            let own = self.histograms.entry(name).or_default();
+            for (component, histogram) in histograms.into_iter().enumerate() {
+                if own.len() <= component {
+                    own.push(histogram);
+                } else if own[component].is_empty() {
+                    own[component] = histogram;
+                } else {
+                    for (own, other) in own[component].iter_mut().zip(histogram) {
+                        *own += other;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn for_each_value(data: &AttributeData, mut f: impl FnMut(usize, f64)) {
+    macro_rules! rhs {
+        ($dtype:ident, $data:ident, $f:ident) => {
+            $data
+                .iter()
+                .for_each(|value| value.for_each_component(&mut $f))
+        };
+    }
+    match_attr_data!(data, rhs, f)
+}
+
+fn for_each_component(batch: &PointsBatch, name: &str, mut f: impl FnMut(usize, f64)) {
+    if name == "position" {
+        for p in &batch.position {
+            p.coords.for_each_component(&mut f);
+        }
+    } else if let Some(data) = batch.attributes.get(name) {
+        for_each_value(data, f);
+    }
+}
+
+/// Computes the statistics of the positions and 'attributes' of all points in 'point_clouds'.
+/// With a non-zero number of histogram bins, all points are read a second time to fill the
+/// histograms.
+pub fn compute_attribute_statistics<C: PointCloud>(
+    point_clouds: &[C],
+    attributes: &[&str],
+    num_histogram_bins: usize,
+    num_threads: usize,
+) -> Result<BTreeMap<String, Vec<ComponentStatistics>>> {
+    let query = PointQuery {
+        attributes: attributes.to_vec(),
+        ..Default::default()
+    };
+    let batch_size = crate::NUM_POINTS_PER_BATCH;
+    let mut accumulator = AttributeAccumulator::default();
+    ParallelIterator::new(point_clouds, &query, batch_size, num_threads, 4).try_for_each_batch(
+        |batch| {
+            accumulator.add_statistics(&batch, "position");
+            for name in batch.attributes.keys() {
+                accumulator.add_statistics(&batch, name);
+            }
+            Ok(())
+        },
+    )?;
+
+    if num_histogram_bins > 0 {
+        let bins: BTreeMap<String, Vec<HistogramBins>> = accumulator
+            .statistics
+            .iter()
+            .map(|(name, components)| {
+                let bins = components
+                    .iter()
+                    .map(|stats| stats.histogram_bins(num_histogram_bins))
+                    .collect();
+                (name.clone(), bins)
+            })
+            .collect();
+        ParallelIterator::new(point_clouds, &query, batch_size, num_threads, 4)
+            .try_for_each_batch(|batch| {
+                for (name, bins) in &bins {
+                    accumulator.add_histograms(&batch, name, |component| bins[component]);
+                }
+                Ok(())
+            })?;
+    }
+
+    let mut histograms = accumulator.histograms;
+    Ok(accumulator
+        .statistics
+        .into_iter()
+        .map(|(name, components)| {
+            let mut component_histograms = histograms.remove(&name).unwrap_or_default();
+            component_histograms.resize(components.len(), Vec::new());
+            let statistics = components
+                .iter()
+                .zip(component_histograms)
+                .map(|(stats, histogram)| ComponentStatistics {
+                    min: stats.min,
+                    max: stats.max,
+                    mean: stats.mean(),
+                    histogram: if histogram.is_empty() {
+                        vec![0; num_histogram_bins]
+                    } else {
+                        histogram
+                    },
+                })
+                .collect();
+            (name, statistics)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_provider::OnDiskDataProvider;
+    use crate::iterator::PointLocation;
+    use crate::octree::{build_octree, BuildOptions, Octree};
+    use nalgebra::{Point3, Vector3};
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_statistics_of_octree() {
+        let batch = PointsBatch {
+            position: (0..4).map(|i| Point3::new(f64::from(i), 0., 0.)).collect(),
+            attributes: vec![
+                (
+                    "color".to_string(),
+                    AttributeData::U8Vec3((0..4).map(|i| Vector3::new(i * 10, 0, 255)).collect()),
+                ),
+                (
+                    "intensity".to_string(),
+                    AttributeData::F32(vec![1., 2., 3., 6.]),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let bounding_box = Aabb::new(Point3::new(0., 0., 0.), Point3::new(3., 0., 0.));
+        let tmp_dir = TempDir::new("statistics").unwrap();
+        let output_directory = tmp_dir.path().join("octree");
+        build_octree(
+            &output_directory,
+            0.01,
+            bounding_box,
+            vec![batch].into_iter(),
+            &["color", "intensity"],
+            &BuildOptions::default(),
+        )
+        .unwrap();
+        let octree =
+            Octree::from_data_provider(Box::new(OnDiskDataProvider::new(output_directory)))
+                .unwrap();
+
+        let meta = summarize_meta(&octree.to_meta_proto()).unwrap();
+        assert_eq!(meta.format, "octree");
+        assert_eq!(
+            meta.num_nodes,
+            octree.nodes_in_location(&PointLocation::AllPoints).len()
+        );
+        assert_eq!(meta.num_points, 4);
+        assert_eq!(meta.num_points_per_level.values().sum::<u64>(), 4);
+
+        let statistics =
+            compute_attribute_statistics(&[octree], &["color", "intensity"], 2, 2).unwrap();
+        assert_eq!(
+            statistics["intensity"],
+            vec![ComponentStatistics {
+                min: 1.,
+                max: 6.,
+                mean: 3.,
+                histogram: vec![3, 1],
+            }]
+        );
+        let red = &statistics["color"][0];
+        assert_eq!((red.min, red.max, red.mean), (0., 30., 15.));
+        assert_eq!(statistics["color"][2].histogram, vec![4, 0]);
+        assert_eq!(statistics["position"].len(), 3);
+        assert_eq!(statistics["position"][0].histogram, vec![2, 2]);
+    }
+}
@@ -6,6 +6,7 @@ use clap::value_t;
 use nalgebra::Isometry3;
 use point_cloud_client::PointCloudClientBuilder;
 use point_viewer::data_provider::DataProviderFactory;
+use point_viewer::filter::FilterExpression;
 use point_viewer::math::ClosedInterval;
 use point_viewer::read_write::attempt_increasing_rlimit_to_max;
 use point_viewer::utils::parse_key_val;
@@ -96,6 +97,10 @@ fn parse_arguments<T: Extension>() -> clap::ArgMatches<'static> {
                 .long("filter-interval")
                 .takes_value(true)
                 .multiple(true),
+            clap::Arg::with_name("filter")
+                .help("Filter expression for attributes, e.g. --filter 'classification in {2, 6}'")
+                .long("filter")
+                .takes_value(true),
             clap::Arg::with_name("binning")
                 .help(
                     "Binning size for one attribute, e.g. --binning timestamp=30000000000, \
@@ -187,6 +192,10 @@ pub fn run<T: Extension>(data_provider_factory: DataProviderFactory) {
         .unwrap_or_default()
         .map(|f| parse_key_val(f).unwrap())
         .collect::<HashMap<String, ClosedInterval<f64>>>();
+    let filter = args.value_of("filter").map(|f| {
+        f.parse::<FilterExpression>()
+            .expect("filter could not be parsed.")
+    });
     let root_node_id = args
         .value_of("root_node_id")
         .unwrap()
@@ -197,6 +206,7 @@ pub fn run<T: Extension>(data_provider_factory: DataProviderFactory) {
         point_cloud_client,
         query_from_global: T::query_from_global(&args),
         filter_intervals,
+        filter,
         tile_background_color,
         tile_size_px,
         pixel_size_m,
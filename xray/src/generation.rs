@@ -12,6 +12,7 @@ use num::clamp;
 use point_cloud_client::PointCloudClient;
 use point_viewer::attributes::AttributeData;
 use point_viewer::color::{Color, TRANSPARENT, WHITE};
+use point_viewer::filter::FilterExpression;
 use point_viewer::geometry::{Aabb, Obb};
 use point_viewer::iterator::{PointLocation, PointQuery};
 use point_viewer::math::ClosedInterval;
@@ -155,7 +156,9 @@ trait BinnedColoringStrategy {
                         $data.iter().map(|e| (*e as f64 / *$size) as i64).collect()
                     };
                 }
-                match_1d_attr_data!(attr_data, rhs, size)
+                match_1d_attr_data!(attr_data, rhs, size).unwrap_or_else(|message| {
+                    panic!("Cannot bin by attribute '{}': {}", attrib_name, message)
+                })
             }
             None => vec![0; points_batch.position.len()],
         }
@@ -461,6 +464,7 @@ pub struct XrayParameters {
     pub point_cloud_client: PointCloudClient,
     pub query_from_global: Option<Isometry3<f64>>,
     pub filter_intervals: HashMap<String, ClosedInterval<f64>>,
+    pub filter: Option<FilterExpression>,
     pub tile_background_color: Color<u8>,
     pub tile_size_px: u32,
     pub pixel_size_m: f64,
@@ -491,16 +495,14 @@ pub fn xray_from_points(
             .iter()
             .map(|(k, v)| (&k[..], *v))
             .collect(),
+        filter: parameters.filter.clone(),
+        output_transform: parameters.query_from_global,
+        ..Default::default()
     };
     let _ = parameters
         .point_cloud_client
-        .for_each_point_data(&point_query, |mut points_batch| {
+        .for_each_point_data(&point_query, |points_batch| {
             seen_any_points = true;
-            if let Some(query_from_global) = &parameters.query_from_global {
-                for p in &mut points_batch.position {
-                    *p = query_from_global.transform_point(p);
-                }
-            }
             coloring_strategy.process_point_data(&points_batch, bbox, image_size);
             Ok(())
         });